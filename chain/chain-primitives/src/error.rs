@@ -450,6 +450,10 @@ impl From<ShardLayoutError> for Error {
             ShardLayoutError::InvalidShardIndexError { shard_index } => {
                 Error::InvalidShardIndex(shard_index)
             }
+            err @ (ShardLayoutError::InvalidShardUIdError { .. }
+            | ShardLayoutError::InvalidBoundaryAccountError { .. }) => {
+                Error::Other(err.to_string())
+            }
         }
     }
 }
@@ -96,7 +96,7 @@ use near_store::config::StateSnapshotType;
 use near_store::flat::{FlatStorageReadyStatus, FlatStorageStatus};
 use near_store::get_genesis_state_roots;
 use near_store::DBCol;
-use node_runtime::bootstrap_congestion_info;
+use node_runtime::{bootstrap_congestion_info, check_outgoing_metadatas};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
@@ -540,6 +540,15 @@ impl Chain {
             &shard_uids_pending_resharding,
             true,
         )?;
+        if let Err(err) = check_outgoing_metadatas_on_startup(
+            &chain_store,
+            runtime_adapter.as_ref(),
+            head_protocol_version,
+            &tip,
+            &tracked_shards,
+        ) {
+            warn!(target: "chain", ?err, "Failed to check the outgoing buffer metadata on startup");
+        }
 
         info!(target: "chain", "Init: header head @ #{} {}; block head @ #{} {}",
               header_head.height, header_head.last_block_hash,
@@ -4060,6 +4069,40 @@ fn get_genesis_congestion_info(
     Ok(congestion_info)
 }
 
+/// Checks the outgoing metadata of the tracked shards at the head against their
+/// outgoing buffers, see [`check_outgoing_metadatas`]. The recomputed metadata
+/// can't be written to the state outside of applying a chunk, so inconsistencies
+/// are only reported.
+fn check_outgoing_metadatas_on_startup(
+    chain_store: &ChainStore,
+    runtime: &dyn RuntimeAdapter,
+    protocol_version: ProtocolVersion,
+    head: &Tip,
+    tracked_shards: &[ShardUId],
+) -> Result<(), Error> {
+    let runtime_config = runtime.get_runtime_config(protocol_version)?;
+    let tries = runtime.get_tries();
+    for &shard_uid in tracked_shards {
+        // The state of the shard may not be available yet, e.g. during state sync.
+        let Ok(chunk_extra) = chain_store.get_chunk_extra(&head.last_block_hash, &shard_uid) else {
+            continue;
+        };
+        let state_root = *chunk_extra.state_root();
+        let trie = tries.get_view_trie_for_shard(shard_uid, state_root);
+        let mut scratch_update = tries.new_trie_update_view(shard_uid, state_root);
+        let mismatches = check_outgoing_metadatas(
+            &trie,
+            &mut scratch_update,
+            &runtime_config,
+            protocol_version,
+        )?;
+        for mismatch in mismatches {
+            error!(target: "chain", ?shard_uid, ?mismatch, "Outgoing buffer metadata is inconsistent with the outgoing buffer");
+        }
+    }
+    Ok(())
+}
+
 fn shard_id_out_of_bounds(shard_id: ShardId) -> Error {
     Error::InvalidStateRequest(format!("shard_id {shard_id:?} out of bounds").into())
 }
@@ -50,12 +50,12 @@ use near_vm_runner::{precompile_contract, ContractRuntimeCache, FilesystemContra
 use node_runtime::adapter::ViewRuntimeAdapter;
 use node_runtime::state_viewer::{TrieViewer, ViewApplyState};
 use node_runtime::{
-    validate_transaction, verify_and_charge_transaction, ApplyState, Runtime,
+    validate_transaction, verify_and_charge_transaction, ApplyResult, ApplyState, Runtime,
     ValidatorAccountsUpdate,
 };
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tracing::{debug, error, info, instrument};
 
 pub mod errors;
@@ -64,6 +64,10 @@ pub mod migrations;
 #[cfg(test)]
 mod tests;
 
+/// Hook called with the result of every chunk application, used by tests to
+/// observe the state transitions of the shards.
+pub type ApplyResultHook = Box<dyn FnMut(ShardId, BlockHeight, &ApplyResult) + Send>;
+
 /// Defines Nightshade state transition and validator rotation.
 /// TODO: this possibly should be merged with the runtime cargo or at least reconciled on the interfaces.
 pub struct NightshadeRuntime {
@@ -78,6 +82,7 @@ pub struct NightshadeRuntime {
     epoch_manager: Arc<EpochManagerHandle>,
     migration_data: Arc<MigrationData>,
     gc_num_epochs_to_keep: u64,
+    apply_result_hook: Option<Arc<Mutex<ApplyResultHook>>>,
 }
 
 impl NightshadeRuntime {
@@ -131,6 +136,7 @@ impl NightshadeRuntime {
             epoch_manager,
             migration_data,
             gc_num_epochs_to_keep: gc_num_epochs_to_keep.max(MIN_GC_NUM_EPOCHS_TO_KEEP),
+            apply_result_hook: None,
         })
     }
 
@@ -171,8 +177,9 @@ impl NightshadeRuntime {
         runtime_config_store: Option<RuntimeConfigStore>,
         trie_config: TrieConfig,
         state_snapshot_type: StateSnapshotType,
+        apply_result_hook: Option<Arc<Mutex<ApplyResultHook>>>,
     ) -> Arc<Self> {
-        Self::new(
+        let mut runtime = Self::new(
             store,
             compiled_contract_cache,
             genesis_config,
@@ -188,7 +195,10 @@ impl NightshadeRuntime {
                 hot_store_path: PathBuf::from("data"),
                 state_snapshot_subdir: PathBuf::from("state_snapshot"),
             },
-        )
+        );
+        Arc::get_mut(&mut runtime).expect("runtime was just created").apply_result_hook =
+            apply_result_hook;
+        runtime
     }
 
     pub fn test(
@@ -417,6 +427,10 @@ impl NightshadeRuntime {
                 RuntimeError::ValidatorError(e) => e.into(),
             })?;
         let elapsed = instant.elapsed();
+        if let Some(hook) = &self.apply_result_hook {
+            let mut hook = hook.lock().unwrap();
+            hook(shard_id, apply_state.block_height, &apply_result);
+        }
 
         let total_gas_burnt =
             apply_result.outcomes.iter().map(|tx_result| tx_result.outcome.gas_burnt).sum();
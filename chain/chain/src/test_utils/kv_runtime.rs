@@ -485,6 +485,14 @@ impl EpochManagerAdapter for MockEpochManager {
         Ok(ShardUId::new(0, shard_id))
     }
 
+    fn account_id_to_shard_uid(
+        &self,
+        account_id: &AccountId,
+        _epoch_id: &EpochId,
+    ) -> Result<ShardUId, EpochError> {
+        Ok(ShardUId::new(0, account_id_to_shard_id(account_id, self.num_shards)))
+    }
+
     fn shard_id_to_index(
         &self,
         shard_id: ShardId,
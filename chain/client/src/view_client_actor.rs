@@ -370,14 +370,11 @@ impl ViewClientActorInner {
             QueryRequest::CallFunction { account_id, .. } => account_id,
             QueryRequest::ViewCode { account_id, .. } => account_id,
         };
-        let shard_id = self
-            .epoch_manager
-            .account_id_to_shard_id(account_id, header.epoch_id())
-            .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
-        let shard_uid = self
-            .epoch_manager
-            .shard_id_to_uid(shard_id, header.epoch_id())
-            .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
+        let shard_uid =
+            self.epoch_manager
+                .account_id_to_shard_uid(account_id, header.epoch_id())
+                .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
+        let shard_id = shard_uid.shard_id();
 
         let tip = self.chain.head();
         let chunk_extra =
@@ -8,7 +8,7 @@ use near_primitives::epoch_info::EpochInfo;
 use near_primitives::epoch_manager::{EpochConfig, ShardConfig};
 use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use near_primitives::shard_layout::{account_id_to_shard_id, account_id_to_shard_uid, ShardLayout};
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
 use near_primitives::stateless_validation::chunk_endorsement::ChunkEndorsement;
 use near_primitives::stateless_validation::contract_distribution::{
@@ -87,6 +87,19 @@ pub trait EpochManagerAdapter: Send + Sync {
         epoch_id: &EpochId,
     ) -> Result<ShardUId, EpochError>;
 
+    /// Which shard the account belongs to in the given epoch, as a `ShardUId`.
+    /// The shard layout of each epoch is cached, so this is cheap to call
+    /// repeatedly.
+    ///
+    /// This takes the epoch id rather than a block height because a height
+    /// can only be mapped to a block on the canonical chain. Callers with a
+    /// block header should pass its epoch id, which is also right for forks.
+    fn account_id_to_shard_uid(
+        &self,
+        account_id: &AccountId,
+        epoch_id: &EpochId,
+    ) -> Result<ShardUId, EpochError>;
+
     fn shard_id_to_index(
         &self,
         shard_id: ShardId,
@@ -571,6 +584,16 @@ impl EpochManagerAdapter for EpochManagerHandle {
         Ok(ShardUId::from_shard_id_and_layout(shard_id, &shard_layout))
     }
 
+    fn account_id_to_shard_uid(
+        &self,
+        account_id: &AccountId,
+        epoch_id: &EpochId,
+    ) -> Result<ShardUId, EpochError> {
+        let epoch_manager = self.read();
+        let shard_layout = epoch_manager.get_shard_layout(epoch_id)?;
+        Ok(account_id_to_shard_uid(account_id, &shard_layout))
+    }
+
     fn shard_id_to_index(
         &self,
         shard_id: ShardId,
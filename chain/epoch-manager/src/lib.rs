@@ -30,12 +30,11 @@ use near_primitives::views::{
 use near_store::{DBCol, Store, StoreUpdate, HEADER_HEAD_KEY};
 use num_rational::BigRational;
 use primitive_types::U256;
-use reward_calculator::ValidatorOnlineThresholds;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 use validator_stats::{
     get_sortable_validator_online_ratio, get_sortable_validator_online_ratio_without_endorsements,
 };
@@ -44,6 +43,7 @@ pub use crate::adapter::EpochManagerAdapter;
 pub use crate::adapter::ShardUIdAndIndex;
 pub use crate::proposals::proposals_to_epoch_info;
 pub use crate::reward_calculator::RewardCalculator;
+pub use crate::reward_calculator::ValidatorOnlineThresholds;
 pub use crate::reward_calculator::NUM_SECONDS_IN_A_YEAR;
 pub use crate::types::{EpochInfoAggregator, RngSeed};
 
@@ -64,6 +64,10 @@ const EPOCH_CACHE_SIZE: usize = 50;
 const BLOCK_CACHE_SIZE: usize = 1000;
 const AGGREGATOR_SAVE_PERIOD: u64 = 1000;
 
+/// Name of the file in the node home directory with the epoch config overrides
+/// to apply on startup, see [`EpochConfigStore::apply_override`].
+pub const EPOCH_CONFIG_OVERRIDE_FILENAME: &str = "epoch_config_override.json";
+
 /// In the current architecture, various components have access to the same
 /// shared mutable instance of [`EpochManager`]. This handle manages locking
 /// required for such access.
@@ -152,6 +156,8 @@ pub struct EpochManager {
     blocks_info: SyncLruCache<CryptoHash, Arc<BlockInfo>>,
     /// Cache of epoch id to epoch start height
     epoch_id_to_start: SyncLruCache<EpochId, BlockHeight>,
+    /// Cache of epoch id to shard layout
+    epoch_id_to_shard_layout: SyncLruCache<EpochId, ShardLayout>,
     /// Epoch validators ordered by `block_producer_settlement`.
     epoch_validators_ordered: SyncLruCache<EpochId, Arc<[(ValidatorStake, bool)]>>,
     /// Unique validators ordered by `block_producer_settlement`.
@@ -196,6 +202,8 @@ impl EpochManager {
     /// Creates a new instance of `EpochManager` from the given `store`, `genesis_config`, and `home_dir`.
     /// For production environments such as mainnet ant testnet, the epoch config files will be ignored.
     /// In the test environment, the epoch config files will be loaded from the `home_dir` if it is not `None`.
+    /// The overrides in [`EPOCH_CONFIG_OVERRIDE_FILENAME`] in the `home_dir`, if present, are then applied on
+    /// top of them, see [`EpochConfigStore::apply_override`].
     pub fn new_arc_handle(
         store: Store,
         genesis_config: &GenesisConfig,
@@ -216,7 +224,23 @@ impl EpochManager {
 
         let config_dir = home_dir.map(|home_dir| home_dir.join("epoch_configs"));
         let epoch_config_store = if config_dir.as_ref().map_or(false, |dir| dir.exists()) {
-            EpochConfigStore::for_chain_id(chain_id, config_dir).unwrap()
+            let epoch_config_store = EpochConfigStore::for_chain_id(chain_id, config_dir).unwrap();
+            // The configs from the files should only make the network grow
+            // compared to the one described by the genesis config.
+            let genesis_epoch_config_store = EpochConfigStore::test(BTreeMap::from_iter(vec![(
+                genesis_config.protocol_version,
+                Arc::new(EpochConfig::from(genesis_config)),
+            )]));
+            if let Err(breaking_changes) = EpochConfigStore::verify_no_breaking_changes(
+                &genesis_epoch_config_store,
+                &epoch_config_store,
+                genesis_config.protocol_version,
+            ) {
+                for breaking_change in breaking_changes {
+                    warn!(target: "epoch_manager", %breaking_change, "Breaking change in epoch configs");
+                }
+            }
+            epoch_config_store
         } else if chain_id.starts_with("test-chain-") {
             let epoch_config = EpochConfig::from(genesis_config);
             EpochConfigStore::test(BTreeMap::from_iter(vec![(
@@ -234,6 +258,16 @@ impl EpochManager {
                 Arc::new(epoch_config),
             )]))
         };
+        let override_path = home_dir.map(|home_dir| home_dir.join(EPOCH_CONFIG_OVERRIDE_FILENAME));
+        let epoch_config_store = match override_path {
+            Some(override_path) if override_path.exists() => {
+                info!(target: "epoch_manager", ?override_path, "Applying epoch config overrides");
+                epoch_config_store
+                    .apply_override(&override_path)
+                    .unwrap_or_else(|err| panic!("{err}"))
+            }
+            _ => epoch_config_store,
+        };
         Self::new_arc_handle_from_epoch_config_store(store, genesis_config, epoch_config_store)
     }
 
@@ -320,6 +354,7 @@ impl EpochManager {
             epochs_info: SyncLruCache::new(EPOCH_CACHE_SIZE),
             blocks_info: SyncLruCache::new(BLOCK_CACHE_SIZE),
             epoch_id_to_start: SyncLruCache::new(EPOCH_CACHE_SIZE),
+            epoch_id_to_shard_layout: SyncLruCache::new(EPOCH_CACHE_SIZE),
             epoch_validators_ordered: SyncLruCache::new(EPOCH_CACHE_SIZE),
             epoch_validators_ordered_unique: SyncLruCache::new(EPOCH_CACHE_SIZE),
             epoch_chunk_producers_unique: SyncLruCache::new(EPOCH_CACHE_SIZE),
@@ -1844,9 +1879,11 @@ impl EpochManager {
     }
 
     pub fn get_shard_layout(&self, epoch_id: &EpochId) -> Result<ShardLayout, EpochError> {
-        let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
-        let shard_layout = self.config.for_protocol_version(protocol_version).shard_layout;
-        Ok(shard_layout)
+        self.epoch_id_to_shard_layout.get_or_try_put(*epoch_id, |epoch_id| {
+            let protocol_version = self.get_epoch_info(epoch_id)?.protocol_version();
+            let shard_layout = self.config.for_protocol_version(protocol_version).shard_layout;
+            Ok(shard_layout)
+        })
     }
 
     pub fn will_shard_layout_change(&self, parent_hash: &CryptoHash) -> Result<bool, EpochError> {
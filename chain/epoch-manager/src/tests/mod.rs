@@ -2317,6 +2317,70 @@ fn test_protocol_version_switch_with_shard_layout_change() {
     assert_eq!(epoch_manager.will_shard_layout_change(&h[6]).unwrap(), false);
 }
 
+/// Test that the shard layout cached per epoch is never returned for another
+/// epoch, and that a failed lookup of an unknown epoch isn't cached.
+#[test]
+fn test_shard_layout_cache_per_epoch() {
+    let store = create_test_store();
+    let config = epoch_config_with_production_config(2, 1, 2, 100, 90, 60, 0, true);
+    let amount_staked = 1_000_000;
+    let validators = vec![
+        stake("test1".parse().unwrap(), amount_staked),
+        stake("test2".parse().unwrap(), amount_staked),
+    ];
+    let new_protocol_version = SimpleNightshade.protocol_version();
+    let mut epoch_manager = EpochManager::new(
+        store,
+        config,
+        new_protocol_version - 1,
+        default_reward_calculator(),
+        validators,
+    )
+    .unwrap();
+    let h = hash_range(8);
+    let epochs = [EpochId::default(), EpochId(h[2]), EpochId(h[4])];
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    assert!(epoch_manager.get_shard_layout(&epochs[2]).is_err());
+
+    for i in 1..8 {
+        let mut block_info = block_info(
+            h[i],
+            i as u64,
+            i as u64 - 1,
+            h[i - 1],
+            h[i - 1],
+            h[0],
+            vec![],
+            DEFAULT_TOTAL_SUPPLY,
+        );
+        if i == 1 {
+            set_block_info_protocol_version(&mut block_info, new_protocol_version - 1);
+        } else {
+            set_block_info_protocol_version(&mut block_info, new_protocol_version);
+        }
+        epoch_manager.record_block_info(block_info, [0; 32]).unwrap();
+    }
+
+    let old_layout = ShardLayout::single_shard();
+    let new_layout = ShardLayout::get_simple_nightshade_layout();
+    // Query each epoch repeatedly and in both orders, so that every lookup
+    // after the first one is served from the cache.
+    for _ in 0..2 {
+        assert_eq!(epoch_manager.get_shard_layout(&epochs[1]).unwrap(), old_layout);
+        assert_eq!(epoch_manager.get_shard_layout(&epochs[2]).unwrap(), new_layout);
+        assert_eq!(epoch_manager.get_shard_layout(&epochs[1]).unwrap(), old_layout);
+    }
+
+    let account_id: AccountId = "test1".parse().unwrap();
+    let epoch_manager = epoch_manager.into_handle();
+    for (epoch_id, shard_layout) in [(&epochs[1], &old_layout), (&epochs[2], &new_layout)] {
+        assert_eq!(
+            epoch_manager.account_id_to_shard_uid(&account_id, epoch_id).unwrap(),
+            account_id_to_shard_uid(&account_id, shard_layout)
+        );
+    }
+}
+
 #[test]
 fn test_protocol_version_switch_with_many_seats() {
     let store = create_test_store();
@@ -3853,3 +3917,32 @@ fn test_get_shard_uids_pending_resharding_double_same() {
     ]);
     assert_eq!(shard_uids, vec![s1].into_iter().collect::<HashSet<_>>());
 }
+
+/// Test that the epoch config overrides in the node home directory are
+/// applied to the epoch configs of a new epoch manager.
+#[test]
+fn test_epoch_config_override_from_home_dir() {
+    let mut genesis_config = GenesisConfig::default();
+    genesis_config.chain_id = "test-chain-override".to_string();
+    genesis_config.protocol_version = PROTOCOL_VERSION;
+    genesis_config.num_block_producer_seats = 1;
+
+    let home_dir = tempfile::tempdir().unwrap();
+    let epoch_manager =
+        EpochManager::new_arc_handle(create_test_store(), &genesis_config, Some(home_dir.path()));
+    let epoch_config = epoch_manager.read().get_epoch_config(PROTOCOL_VERSION);
+    assert_ne!(epoch_config.validator_selection_config.num_chunk_producer_seats, 7);
+
+    std::fs::write(
+        home_dir.path().join(EPOCH_CONFIG_OVERRIDE_FILENAME),
+        format!(
+            r#"{{ "{PROTOCOL_VERSION}": {{ "validator_selection_config": {{ "num_chunk_producer_seats": 7 }} }} }}"#
+        ),
+    )
+    .unwrap();
+    let epoch_manager =
+        EpochManager::new_arc_handle(create_test_store(), &genesis_config, Some(home_dir.path()));
+    let overridden_config = epoch_manager.read().get_epoch_config(PROTOCOL_VERSION);
+    assert_eq!(overridden_config.validator_selection_config.num_chunk_producer_seats, 7);
+    assert_eq!(overridden_config.shard_layout, epoch_config.shard_layout);
+}
@@ -38,7 +38,7 @@ use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 use tracing::warn;
 
-const MAX_GAS_PRICE: Balance = 10_000_000_000_000_000_000_000;
+pub(crate) const MAX_GAS_PRICE: Balance = 10_000_000_000_000_000_000_000;
 
 fn default_online_min_threshold() -> Rational32 {
     Rational32::new(90, 100)
@@ -295,6 +295,8 @@ impl From<&GenesisConfig> for EpochConfig {
     derive_more::From,
     serde::Serialize,
     serde::Deserialize,
+    borsh::BorshSerialize,
+    borsh::BorshDeserialize,
 )]
 pub struct GenesisRecords(pub Vec<StateRecord>);
 
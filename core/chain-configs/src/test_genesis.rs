@@ -1,11 +1,15 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 
+use borsh::BorshDeserialize;
 use near_crypto::PublicKey;
-use near_primitives::account::{AccessKey, Account};
+use near_parameters::config::CongestionControlConfig;
+use near_parameters::{RuntimeConfig, RuntimeConfigStore};
+use near_primitives::account::{AccessKey, AccessKeyPermission, Account, FunctionCallPermission};
 use near_primitives::epoch_manager::{EpochConfig, EpochConfigStore};
 use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::ShardLayout;
+use near_primitives::shard_layout::{ShardLayout, ShardVersion};
 use near_primitives::state_record::StateRecord;
 use near_primitives::test_utils::{create_test_signer, create_user_test_signer};
 use near_primitives::types::{
@@ -17,7 +21,11 @@ use near_primitives::version::PROTOCOL_VERSION;
 use near_time::Clock;
 use num_rational::Rational32;
 
-use crate::{Genesis, GenesisConfig, GenesisContents, GenesisRecords};
+use crate::genesis_config::MAX_GAS_PRICE;
+use crate::{
+    get_initial_supply, Genesis, GenesisConfig, GenesisContents, GenesisRecords,
+    GAS_PRICE_ADJUSTMENT_RATE, MIN_GAS_PRICE,
+};
 
 /// A builder for constructing a valid genesis for testing.
 ///
@@ -38,13 +46,43 @@ pub struct TestGenesisBuilder {
     genesis_height: Option<BlockHeight>,
     epoch_length: Option<BlockHeightDelta>,
     min_max_gas_price: Option<(Balance, Balance)>,
+    gas_price_adjustment_rate: Option<Rational32>,
     gas_limit: Option<Gas>,
     transaction_validity_period: Option<NumBlocks>,
     validators: Option<ValidatorsSpec>,
+    num_chunk_producer_seats_override: Option<NumSeats>,
     protocol_treasury_account: Option<String>,
     max_inflation_rate: Option<Rational32>,
+    num_blocks_per_year: Option<NumBlocks>,
     user_accounts: Vec<UserAccount>,
     epoch_config: Option<EpochConfig>,
+    /// Epoch configs registered for specific protocol versions. If none of
+    /// them applies to the genesis protocol version, `epoch_config` is used
+    /// for it.
+    epoch_configs_for_versions: BTreeMap<ProtocolVersion, EpochConfig>,
+    runtime_config: Option<RuntimeConfig>,
+    /// Genesis config to take the fields not set by the builder from, instead
+    /// of the hardcoded test defaults.
+    base_genesis_config: Option<GenesisConfig>,
+    /// Records of the genesis the builder was created from, which come before
+    /// the records generated for the accounts added to the builder.
+    base_records: Option<Vec<StateRecord>>,
+    /// Records appended as is after the records generated by the builder.
+    extra_records: Vec<StateRecord>,
+    /// Transformations applied to all genesis records before the genesis is
+    /// built, in the order they were added.
+    records_modifiers: Vec<RecordsModifier>,
+}
+
+/// Transformation of the genesis records, see
+/// [`TestGenesisBuilder::with_modified_accounts`].
+#[derive(Clone)]
+struct RecordsModifier(Arc<dyn Fn(&mut Vec<StateRecord>)>);
+
+impl std::fmt::Debug for RecordsModifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RecordsModifier")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +91,9 @@ enum ValidatorsSpec {
         block_and_chunk_producers: Vec<String>,
         chunk_validators_only: Vec<String>,
     },
+    DesiredRolesWithStakes {
+        validators: Vec<(String, Balance, ValidatorRole)>,
+    },
     Raw {
         validators: Vec<AccountInfo>,
         num_block_producer_seats: NumSeats,
@@ -61,11 +102,21 @@ enum ValidatorsSpec {
     },
 }
 
+/// The role a validator should end up with, see
+/// [`TestGenesisBuilder::validators_desired_roles_with_stakes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidatorRole {
+    BlockAndChunkProducer,
+    ChunkValidatorOnly,
+}
+
 #[derive(Debug, Clone)]
 struct UserAccount {
     account_id: AccountId,
     balance: Balance,
-    access_keys: Vec<PublicKey>,
+    access_keys: Vec<(PublicKey, AccessKeyPermission)>,
+    /// Code of the contract deployed to the account at genesis, if any.
+    contract_code: Option<Vec<u8>>,
 }
 
 impl TestGenesisBuilder {
@@ -73,6 +124,49 @@ impl TestGenesisBuilder {
         Default::default()
     }
 
+    /// Creates a builder initialized from the given genesis config, typically
+    /// the mainnet one, for tests that need protocol-accurate parameters.
+    /// All fields can still be overridden with the builder methods.
+    /// Accounts are not taken from the config; the total supply is computed
+    /// from the accounts added to the builder, as usual.
+    pub fn from_mainnet_genesis_config(genesis_config: GenesisConfig) -> Self {
+        Self {
+            chain_id: Some(genesis_config.chain_id.clone()),
+            genesis_time: Some(genesis_config.genesis_time),
+            protocol_version: Some(genesis_config.protocol_version),
+            genesis_height: Some(genesis_config.genesis_height),
+            epoch_length: Some(genesis_config.epoch_length),
+            min_max_gas_price: Some((genesis_config.min_gas_price, genesis_config.max_gas_price)),
+            gas_price_adjustment_rate: Some(genesis_config.gas_price_adjustment_rate),
+            gas_limit: Some(genesis_config.gas_limit),
+            transaction_validity_period: Some(genesis_config.transaction_validity_period),
+            validators: Some(ValidatorsSpec::Raw {
+                validators: genesis_config.validators.clone(),
+                num_block_producer_seats: genesis_config.num_block_producer_seats,
+                num_chunk_producer_seats: genesis_config.num_chunk_producer_seats,
+                num_chunk_validator_seats: genesis_config.num_chunk_validator_seats,
+            }),
+            protocol_treasury_account: Some(
+                genesis_config.protocol_treasury_account.as_str().to_string(),
+            ),
+            max_inflation_rate: Some(genesis_config.max_inflation_rate),
+            num_blocks_per_year: Some(genesis_config.num_blocks_per_year),
+            epoch_config: Some(EpochConfig::from(&genesis_config)),
+            base_genesis_config: Some(genesis_config),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a builder initialized from an existing genesis, for tests that
+    /// run a chain differing from it in only a few parameters. The config is
+    /// taken as in [`Self::from_mainnet_genesis_config`] and all records of the
+    /// genesis are kept. Accounts added to the builder must not already exist
+    /// in the genesis. The total supply is recomputed from the final records.
+    pub fn from_existing_genesis(mut genesis: Genesis) -> Self {
+        let records = std::mem::take(&mut genesis.force_read_records().0);
+        Self { base_records: Some(records), ..Self::from_mainnet_genesis_config(genesis.config) }
+    }
+
     pub fn epoch_config_mut(&mut self) -> &mut EpochConfig {
         if self.epoch_config.is_none() {
             let mut epoch_config = Genesis::test_epoch_config(1, ShardLayout::single_shard(), 100);
@@ -84,6 +178,40 @@ impl TestGenesisBuilder {
         self.epoch_config.as_mut().unwrap()
     }
 
+    /// Registers the epoch config to use starting from the given protocol
+    /// version, for tests that upgrade through several protocol versions.
+    /// Registered configs are used as is; the validator seats derived by the
+    /// builder are only applied to the default epoch config.
+    pub fn epoch_config_for_version(
+        &mut self,
+        version: ProtocolVersion,
+        config: EpochConfig,
+    ) -> &mut Self {
+        self.epoch_configs_for_versions.insert(version, config);
+        self
+    }
+
+    /// Registers the epoch configs of a resharding: `before` is used for the
+    /// protocol version right before `upgrade_at`, which is expected to be the
+    /// genesis protocol version, and `after` from `upgrade_at` onwards. Both end
+    /// up in the `EpochConfigStore` returned by [`Self::build`].
+    pub fn resharding_config(
+        &mut self,
+        before: Arc<EpochConfig>,
+        after: Arc<EpochConfig>,
+        upgrade_at: ProtocolVersion,
+    ) -> &mut Self {
+        let before_version = upgrade_at
+            .checked_sub(1)
+            .expect("resharding upgrade can't happen at protocol version 0");
+        self.epoch_config_for_version(before_version, before.as_ref().clone())
+            .epoch_config_for_version(upgrade_at, after.as_ref().clone())
+    }
+
+    pub fn runtime_config_mut(&mut self) -> &mut RuntimeConfig {
+        self.runtime_config.get_or_insert_with(RuntimeConfig::test)
+    }
+
     pub fn chain_id(&mut self, chain_id: String) -> &mut Self {
         self.chain_id = Some(chain_id);
         self
@@ -131,6 +259,19 @@ impl TestGenesisBuilder {
         self
     }
 
+    /// Sets a V2 shard layout with the given boundary accounts and version,
+    /// as used by resharding tests.
+    pub fn shard_layout_v2(
+        &mut self,
+        boundary_accounts: &[&str],
+        version: ShardVersion,
+    ) -> &mut Self {
+        let boundary_accounts = boundary_accounts.iter().map(|a| a.parse().unwrap()).collect();
+        self.epoch_config_mut().shard_layout =
+            ShardLayout::multi_shard_custom(boundary_accounts, version);
+        self
+    }
+
     // TODO(#11265): move this and relevant methods to epoch config builder.
     // In dynamic resharding world, shard layout will not be static.
     pub fn shard_layout(&mut self, shard_layout: ShardLayout) -> &mut Self {
@@ -148,6 +289,63 @@ impl TestGenesisBuilder {
         self
     }
 
+    /// Fixes the gas price to `price`, whatever the load of the chain.
+    pub fn gas_price_static(&mut self, price: Balance) -> &mut Self {
+        self.min_max_gas_price = Some((price, price));
+        self
+    }
+
+    /// Sets how fast the gas price follows the load of the chain. Gas prices
+    /// are static by default.
+    pub fn gas_price_adjustment_rate(&mut self, rate: Rational32) -> &mut Self {
+        self.gas_price_adjustment_rate = Some(rate);
+        self
+    }
+
+    /// Uses the mainnet gas price bounds and adjustment rate, so that gas
+    /// prices rise under load and fall back when the chain is idle.
+    pub fn gas_price_market_simulation(&mut self) -> &mut Self {
+        self.gas_prices(MIN_GAS_PRICE, MAX_GAS_PRICE)
+            .gas_price_adjustment_rate(GAS_PRICE_ADJUSTMENT_RATE)
+    }
+
+    pub fn storage_amount_per_byte(&mut self, amount: Balance) -> &mut Self {
+        let fees = Arc::make_mut(&mut self.runtime_config_mut().fees);
+        fees.storage_usage_config.storage_amount_per_byte = amount;
+        self
+    }
+
+    /// Disables storage staking, i.e. accounts don't need to hold any balance
+    /// to cover the storage they use.
+    pub fn storage_amount_free(&mut self) -> &mut Self {
+        self.storage_amount_per_byte(0)
+    }
+
+    /// Overrides the congestion control config of the runtime config.
+    pub fn congestion_control_config(&mut self, config: CongestionControlConfig) -> &mut Self {
+        self.runtime_config_mut().congestion_control_config = config;
+        self
+    }
+
+    /// Limits the gas of the receipts that can be forwarded to another shard
+    /// in a single chunk, regardless of the congestion of that shard.
+    pub fn congestion_control_max_outgoing_gas(&mut self, gas: Gas) -> &mut Self {
+        let config = &mut self.runtime_config_mut().congestion_control_config;
+        config.max_outgoing_gas = gas;
+        config.min_outgoing_gas = config.min_outgoing_gas.min(gas);
+        self
+    }
+
+    /// Limits the total size of the receipts that can be forwarded to another
+    /// shard in a single chunk.
+    pub fn congestion_control_max_outgoing_size(&mut self, bytes: u64) -> &mut Self {
+        let config = &mut self.runtime_config_mut().congestion_control_config;
+        config.outgoing_receipts_big_size_limit = bytes;
+        config.outgoing_receipts_usual_size_limit =
+            config.outgoing_receipts_usual_size_limit.min(bytes);
+        self
+    }
+
     pub fn gas_limit(&mut self, gas_limit: Gas) -> &mut Self {
         self.gas_limit = Some(gas_limit);
         self
@@ -184,6 +382,52 @@ impl TestGenesisBuilder {
         self
     }
 
+    /// Same as `validators_desired_roles`, but with an explicit stake for each
+    /// validator, e.g. to have a single validator hold most of the stake.
+    ///
+    /// Seats are assigned by stake, so every block and chunk producer must
+    /// have more stake than any of the chunk validators only.
+    pub fn validators_desired_roles_with_stakes(
+        &mut self,
+        specs: Vec<(&str, Balance, ValidatorRole)>,
+    ) -> &mut Self {
+        self.validators = Some(ValidatorsSpec::DesiredRolesWithStakes {
+            validators: specs
+                .into_iter()
+                .map(|(account, amount, role)| (account.to_string(), amount, role))
+                .collect(),
+        });
+        self
+    }
+
+    /// Specifies that the given accounts are all block and chunk producers,
+    /// each with the same stake. Unlike `validators_desired_roles`, the stakes
+    /// don't impose an order on the validators.
+    pub fn validators_equal_stake(&mut self, accounts: &[&str]) -> &mut Self {
+        let stakes = accounts.iter().map(|account| (*account, ONE_NEAR * 10000)).collect();
+        self.validators_with_stakes(stakes)
+    }
+
+    /// Specifies that the given accounts are all block and chunk producers,
+    /// with the given stakes. Unlike `validators_desired_roles_with_stakes`,
+    /// there are no chunk validators only and the stakes don't need to be
+    /// ordered, as there is a seat for every validator.
+    pub fn validators_with_stakes(&mut self, stakes: Vec<(&str, Balance)>) -> &mut Self {
+        let num_validators = stakes.len() as NumSeats;
+        let validators = stakes
+            .into_iter()
+            .map(|(account, amount)| {
+                let account_id: AccountId = account.parse().unwrap();
+                AccountInfo {
+                    public_key: create_test_signer(account_id.as_str()).public_key(),
+                    account_id,
+                    amount,
+                }
+            })
+            .collect();
+        self.validators_raw(validators, num_validators, num_validators, 0)
+    }
+
     /// Specifies the validator fields directly, relying on the validator selection
     /// algorithm to determine which validators are selected as block or chunk
     /// producers.
@@ -206,6 +450,14 @@ impl TestGenesisBuilder {
         self
     }
 
+    /// Overrides the number of chunk producer seats derived from the
+    /// validators spec. Useful to have more seats than validators, so that
+    /// seats are uncontested.
+    pub fn num_chunk_producer_seats_override(&mut self, seats: NumSeats) -> &mut Self {
+        self.num_chunk_producer_seats_override = Some(seats);
+        self
+    }
+
     pub fn minimum_stake_ratio(&mut self, minimum_stake_ratio: Rational32) -> &mut Self {
         self.epoch_config_mut().validator_selection_config.minimum_stake_ratio =
             minimum_stake_ratio;
@@ -217,6 +469,13 @@ impl TestGenesisBuilder {
         self
     }
 
+    /// Number of blocks per year used to compute the epoch rewards, only
+    /// before `RectifyInflation`, which computes them from the epoch duration.
+    pub fn num_blocks_per_year(&mut self, num_blocks_per_year: NumBlocks) -> &mut Self {
+        self.num_blocks_per_year = Some(num_blocks_per_year);
+        self
+    }
+
     pub fn minimum_validators_per_shard(
         &mut self,
         minimum_validators_per_shard: NumSeats,
@@ -284,18 +543,110 @@ impl TestGenesisBuilder {
     ) -> &mut Self {
         self.user_accounts.push(UserAccount {
             balance,
-            access_keys: vec![create_user_test_signer(&account_id).public_key()],
+            access_keys: vec![(
+                create_user_test_signer(&account_id).public_key(),
+                AccessKeyPermission::FullAccess,
+            )],
+            account_id,
+            contract_code: None,
+        });
+        self
+    }
+
+    /// Same as [`Self::add_user_account_simple`], with `code` deployed to the
+    /// account at genesis. Saves deploying the contract with a transaction
+    /// once the chain is running. The account must not be added otherwise.
+    pub fn add_user_account_with_contract(
+        &mut self,
+        account_id: AccountId,
+        balance: Balance,
+        code: Vec<u8>,
+    ) -> &mut Self {
+        self.user_accounts.push(UserAccount {
+            balance,
+            access_keys: vec![(
+                create_user_test_signer(&account_id).public_key(),
+                AccessKeyPermission::FullAccess,
+            )],
             account_id,
+            contract_code: Some(code),
         });
         self
     }
 
-    pub fn build(mut self) -> (Genesis, EpochConfigStore) {
+    /// Adds an account whose only access key is a function call key of the
+    /// test signer for the account. The key can only call `method_names` (any
+    /// method if empty) on `receiver_id` and spend up to `allowance` on
+    /// transaction fees (no limit if `None`).
+    pub fn add_user_account_with_function_call_key(
+        &mut self,
+        account_id: AccountId,
+        balance: Balance,
+        receiver_id: AccountId,
+        method_names: Vec<String>,
+        allowance: Option<Balance>,
+    ) -> &mut Self {
+        let permission = AccessKeyPermission::FunctionCall(FunctionCallPermission {
+            allowance,
+            receiver_id: receiver_id.to_string(),
+            method_names,
+        });
+        self.user_accounts.push(UserAccount {
+            balance,
+            access_keys: vec![(create_user_test_signer(&account_id).public_key(), permission)],
+            account_id,
+            contract_code: None,
+        });
+        self
+    }
+
+    /// Registers a transformation of the genesis records, applied once all
+    /// records are generated and before the total supply is computed, e.g. to
+    /// change the balance of an account of an existing genesis.
+    pub fn with_modified_accounts(
+        &mut self,
+        modifier: Box<dyn Fn(&mut Vec<StateRecord>)>,
+    ) -> &mut Self {
+        self.records_modifiers.push(RecordsModifier(Arc::from(modifier)));
+        self
+    }
+
+    /// Appends arbitrary records to the genesis, after the records generated
+    /// for the accounts added to the builder. The records are included in the
+    /// total supply and go through the same consistency checks, so e.g. an
+    /// extra account must not be added to the builder as well.
+    pub fn with_extra_state_records(
+        &mut self,
+        records: impl IntoIterator<Item = StateRecord>,
+    ) -> &mut Self {
+        self.extra_records.extend(records);
+        self
+    }
+
+    /// Same as [`Self::with_extra_state_records`] with the records read from a
+    /// borsh-encoded [`GenesisRecords`] file.
+    pub fn with_state_records_from_file(&mut self, path: &Path) -> &mut Self {
+        let bytes = std::fs::read(path)
+            .unwrap_or_else(|err| panic!("failed to read genesis records from {path:?}: {err}"));
+        let records = GenesisRecords::try_from_slice(&bytes)
+            .unwrap_or_else(|err| panic!("failed to decode genesis records from {path:?}: {err}"));
+        self.with_extra_state_records(records.0)
+    }
+
+    /// Builds the genesis along with the epoch configs and runtime configs
+    /// to run it with. The runtime configs are not part of the genesis, so
+    /// they must be passed to the runtime separately; without any runtime
+    /// config overrides they are the defaults for the chain id.
+    pub fn build(mut self) -> (Genesis, EpochConfigStore, RuntimeConfigStore) {
         let chain_id = self.chain_id.clone().unwrap_or_else(|| {
             let default = "test".to_string();
             tracing::warn!("Genesis chain_id not explicitly set, defaulting to {:?}.", default);
             default
         });
+        let runtime_config_store = match self.runtime_config.take() {
+            Some(runtime_config) => RuntimeConfigStore::with_one_config(runtime_config),
+            None => RuntimeConfigStore::for_chain_id(&chain_id),
+        };
         let protocol_version = self.protocol_version.unwrap_or_else(|| {
             let default = PROTOCOL_VERSION;
             tracing::warn!("Genesis protocol_version not explicitly set, defaulting to latest protocol version {:?}.", default);
@@ -318,18 +669,34 @@ impl TestGenesisBuilder {
             default
         });
 
-        let derived_validator_setup = derive_validator_setup(validator_specs);
+        let mut derived_validator_setup = derive_validator_setup(validator_specs);
+        if let Some(num_chunk_producer_seats) = self.num_chunk_producer_seats_override {
+            if num_chunk_producer_seats < derived_validator_setup.validators.len() as NumSeats {
+                tracing::warn!(
+                    "num_chunk_producer_seats override {:?} is less than the number of validators {:?}.",
+                    num_chunk_producer_seats,
+                    derived_validator_setup.validators.len()
+                );
+            }
+            derived_validator_setup.num_chunk_producer_seats = num_chunk_producer_seats;
+        }
 
-        let mut epoch_config = self.epoch_config_mut().clone();
-        epoch_config.num_block_producer_seats = derived_validator_setup.num_block_producer_seats;
-        epoch_config.validator_selection_config.num_chunk_producer_seats =
-            derived_validator_setup.num_chunk_producer_seats;
-        epoch_config.validator_selection_config.num_chunk_validator_seats =
-            derived_validator_setup.num_chunk_validator_seats;
-        let epoch_config_store = EpochConfigStore::test(BTreeMap::from_iter(vec![(
-            protocol_version,
-            Arc::new(epoch_config),
-        )]));
+        let mut epoch_configs: BTreeMap<ProtocolVersion, Arc<EpochConfig>> = self
+            .epoch_configs_for_versions
+            .iter()
+            .map(|(version, config)| (*version, Arc::new(config.clone())))
+            .collect();
+        if epoch_configs.range(..=protocol_version).next_back().is_none() {
+            let mut epoch_config = self.epoch_config_mut().clone();
+            epoch_config.num_block_producer_seats =
+                derived_validator_setup.num_block_producer_seats;
+            epoch_config.validator_selection_config.num_chunk_producer_seats =
+                derived_validator_setup.num_chunk_producer_seats;
+            epoch_config.validator_selection_config.num_chunk_validator_seats =
+                derived_validator_setup.num_chunk_validator_seats;
+            epoch_configs.insert(protocol_version, Arc::new(epoch_config));
+        }
+        let epoch_config_store = EpochConfigStore::test(epoch_configs);
         let shard_layout =
             epoch_config_store.get_config(protocol_version).as_ref().shard_layout.clone();
 
@@ -391,13 +758,24 @@ impl TestGenesisBuilder {
             default
         });
 
+        let base_records = self.base_records.take().unwrap_or_default();
+        // Accounts whose records are given as is rather than generated.
+        let given_account_ids: HashSet<AccountId> = base_records
+            .iter()
+            .chain(&self.extra_records)
+            .filter_map(|record| match record {
+                StateRecord::Account { account_id, .. } => Some(account_id.clone()),
+                _ => None,
+            })
+            .collect();
         if self
             .user_accounts
             .iter()
             .map(|account| &account.account_id)
+            .chain(given_account_ids.iter())
             .collect::<HashSet<_>>()
             .len()
-            != self.user_accounts.len()
+            != self.user_accounts.len() + given_account_ids.len()
         {
             panic!("Duplicate user accounts specified.");
         }
@@ -408,7 +786,9 @@ impl TestGenesisBuilder {
         // of this to generate the genesis records and also calculate the
         // total supply.
         let mut user_accounts = self.user_accounts.clone();
-        if user_accounts.iter().all(|account| &account.account_id != &protocol_treasury_account) {
+        if user_accounts.iter().all(|account| &account.account_id != &protocol_treasury_account)
+            && !given_account_ids.contains(&protocol_treasury_account)
+        {
             tracing::warn!(
                 "Protocol treasury account {:?} not found in user accounts;
                 to keep genesis valid, adding it as a user account with zero balance.",
@@ -418,37 +798,43 @@ impl TestGenesisBuilder {
                 account_id: protocol_treasury_account.clone(),
                 balance: 0,
                 access_keys: vec![],
+                contract_code: None,
             });
         }
 
-        let mut total_supply = 0;
         let mut validator_stake: HashMap<AccountId, Balance> = HashMap::new();
         for validator in &derived_validator_setup.validators {
-            total_supply += validator.amount;
-            validator_stake.insert(validator.account_id.clone(), validator.amount);
+            // The stake of validators of an existing genesis or with extra
+            // records is already locked in their account records.
+            if !given_account_ids.contains(&validator.account_id) {
+                validator_stake.insert(validator.account_id.clone(), validator.amount);
+            }
         }
-        let mut records = Vec::new();
+        let mut records = base_records;
         for user_account in &user_accounts {
-            total_supply += user_account.balance;
+            let code_hash = user_account.contract_code.as_deref().map(hash).unwrap_or_default();
             records.push(StateRecord::Account {
                 account_id: user_account.account_id.clone(),
                 account: Account::new(
                     user_account.balance,
                     validator_stake.remove(&user_account.account_id).unwrap_or(0),
                     0,
-                    CryptoHash::default(),
+                    code_hash,
                     0,
                     protocol_version,
                 ),
             });
-            for access_key in &user_account.access_keys {
+            if let Some(code) = &user_account.contract_code {
+                records.push(StateRecord::Contract {
+                    account_id: user_account.account_id.clone(),
+                    code: code.clone(),
+                });
+            }
+            for (public_key, permission) in &user_account.access_keys {
                 records.push(StateRecord::AccessKey {
                     account_id: user_account.account_id.clone(),
-                    public_key: access_key.clone(),
-                    access_key: AccessKey {
-                        nonce: 0,
-                        permission: near_primitives::account::AccessKeyPermission::FullAccess,
-                    },
+                    public_key: public_key.clone(),
+                    access_key: AccessKey { nonce: 0, permission: permission.clone() },
                 });
             }
         }
@@ -458,11 +844,19 @@ impl TestGenesisBuilder {
                 account: Account::new(0, balance, 0, CryptoHash::default(), 0, protocol_version),
             });
         }
+        records.extend(std::mem::take(&mut self.extra_records));
+        for modifier in &self.records_modifiers {
+            (modifier.0)(&mut records);
+        }
+        let total_supply = get_initial_supply(&records);
+        validate_genesis_records_consistency(&records, &shard_layout);
 
-        // NOTE: If you want to override any of the hardcoded defaults below,
-        // follow the same pattern and add a corresponding `Option` field to the builder,
-        // and add the corresponding functions to set the field. DO NOT just modify
-        // the defaults.
+        let base_genesis_config =
+            self.base_genesis_config.take().unwrap_or_else(default_base_genesis_config);
+        let gas_price_adjustment_rate =
+            self.gas_price_adjustment_rate.unwrap_or(base_genesis_config.gas_price_adjustment_rate);
+        let num_blocks_per_year =
+            self.num_blocks_per_year.unwrap_or(base_genesis_config.num_blocks_per_year);
         let genesis_config = GenesisConfig {
             chain_id,
             genesis_time,
@@ -470,19 +864,12 @@ impl TestGenesisBuilder {
             epoch_length,
             min_gas_price,
             max_gas_price,
+            gas_price_adjustment_rate,
             gas_limit,
-            dynamic_resharding: false,
-            fishermen_threshold: 0,
             transaction_validity_period,
             protocol_version,
             protocol_treasury_account,
-            online_min_threshold: Rational32::new(90, 100),
-            online_max_threshold: Rational32::new(99, 100),
-            gas_price_adjustment_rate: Rational32::new(0, 1),
-            num_blocks_per_year: 86400,
-            protocol_reward_rate: Rational32::new(0, 1),
             total_supply,
-            max_kickout_stake_perc: 100,
             validators: derived_validator_setup.validators,
             shard_layout: shard_layout.clone(),
             num_block_producer_seats: derived_validator_setup.num_block_producer_seats,
@@ -490,14 +877,11 @@ impl TestGenesisBuilder {
                 .shard_ids()
                 .map(|_| derived_validator_setup.num_block_producer_seats)
                 .collect(),
-            num_chunk_only_producer_seats: 0,
-            minimum_stake_divisor: 10,
             max_inflation_rate,
-            protocol_upgrade_stake_threshold: Rational32::new(8, 10),
+            num_blocks_per_year,
             num_chunk_producer_seats: derived_validator_setup.num_chunk_producer_seats,
             num_chunk_validator_seats: derived_validator_setup.num_chunk_validator_seats,
-            chunk_producer_assignment_changes_limit: 5,
-            ..Default::default()
+            ..base_genesis_config
         };
 
         (
@@ -506,10 +890,69 @@ impl TestGenesisBuilder {
                 contents: GenesisContents::Records { records: GenesisRecords(records) },
             },
             epoch_config_store,
+            runtime_config_store,
         )
     }
 }
 
+/// Checks that every account has a single account record, that every contract
+/// is deployed to an account with a record and at most once, and that every
+/// record maps to a shard id of the shard layout.
+fn validate_genesis_records_consistency(records: &[StateRecord], shard_layout: &ShardLayout) {
+    let mut account_ids = HashSet::new();
+    let mut contract_account_ids = HashSet::new();
+    for record in records {
+        if let StateRecord::Account { account_id, .. } = record {
+            if !account_ids.insert(account_id) {
+                panic!("Genesis contains more than one account record for {}.", account_id);
+            }
+        }
+        if let StateRecord::Contract { account_id, .. } = record {
+            if !contract_account_ids.insert(account_id) {
+                panic!("Genesis deploys more than one contract to {}.", account_id);
+            }
+        }
+        let Some(account_id) = record.primary_account_id() else {
+            continue;
+        };
+        if !shard_layout.contains_account(account_id) {
+            panic!(
+                "Genesis {} record of account {} doesn't map to a shard of the shard layout.",
+                record.get_type_string(),
+                account_id
+            );
+        }
+    }
+    for account_id in contract_account_ids {
+        if !account_ids.contains(account_id) {
+            panic!("Genesis deploys a contract to {} without an account record.", account_id);
+        }
+    }
+}
+
+/// The genesis config fields which are not set by the builder.
+// NOTE: If you want to override any of the hardcoded defaults below,
+// follow the same pattern and add a corresponding `Option` field to the builder,
+// and add the corresponding functions to set the field. DO NOT just modify
+// the defaults.
+fn default_base_genesis_config() -> GenesisConfig {
+    GenesisConfig {
+        dynamic_resharding: false,
+        fishermen_threshold: 0,
+        online_min_threshold: Rational32::new(90, 100),
+        online_max_threshold: Rational32::new(99, 100),
+        gas_price_adjustment_rate: Rational32::new(0, 1),
+        num_blocks_per_year: 86400,
+        protocol_reward_rate: Rational32::new(0, 1),
+        max_kickout_stake_perc: 100,
+        num_chunk_only_producer_seats: 0,
+        minimum_stake_divisor: 10,
+        protocol_upgrade_stake_threshold: Rational32::new(8, 10),
+        chunk_producer_assignment_changes_limit: 5,
+        ..Default::default()
+    }
+}
+
 struct DerivedValidatorSetup {
     validators: Vec<AccountInfo>,
     num_block_producer_seats: NumSeats,
@@ -552,6 +995,50 @@ fn derive_validator_setup(specs: ValidatorsSpec) -> DerivedValidatorSetup {
                     + num_chunk_validator_only_seats,
             }
         }
+        ValidatorsSpec::DesiredRolesWithStakes { validators: specs } => {
+            let min_producer_stake = specs
+                .iter()
+                .filter(|(_, _, role)| *role == ValidatorRole::BlockAndChunkProducer)
+                .map(|(_, amount, _)| *amount)
+                .min();
+            let max_chunk_validator_only_stake = specs
+                .iter()
+                .filter(|(_, _, role)| *role == ValidatorRole::ChunkValidatorOnly)
+                .map(|(_, amount, _)| *amount)
+                .max();
+            if let (Some(min_producer_stake), Some(max_chunk_validator_only_stake)) =
+                (min_producer_stake, max_chunk_validator_only_stake)
+            {
+                assert!(
+                    min_producer_stake > max_chunk_validator_only_stake,
+                    "Chunk validator only stake {} is not below block and chunk producer stake {}, \
+                     the validator selection would not respect the desired roles",
+                    max_chunk_validator_only_stake,
+                    min_producer_stake
+                );
+            }
+            let num_block_and_chunk_producer_seats = specs
+                .iter()
+                .filter(|(_, _, role)| *role == ValidatorRole::BlockAndChunkProducer)
+                .count() as NumSeats;
+            let validators = specs
+                .into_iter()
+                .map(|(account, amount, _)| {
+                    let account_id: AccountId = account.parse().unwrap();
+                    AccountInfo {
+                        public_key: create_test_signer(account_id.as_str()).public_key(),
+                        account_id,
+                        amount,
+                    }
+                })
+                .collect::<Vec<_>>();
+            DerivedValidatorSetup {
+                num_block_producer_seats: num_block_and_chunk_producer_seats,
+                num_chunk_producer_seats: num_block_and_chunk_producer_seats,
+                num_chunk_validator_seats: validators.len() as NumSeats,
+                validators,
+            }
+        }
         ValidatorsSpec::Raw {
             validators,
             num_block_producer_seats,
@@ -565,3 +1052,39 @@ fn derive_validator_setup(specs: ValidatorsSpec) -> DerivedValidatorSetup {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_records_from_file() {
+        let account_id: AccountId = "extra".parse().unwrap();
+        let records = vec![
+            StateRecord::Account {
+                account_id: account_id.clone(),
+                account: Account::new(1_000, 0, 0, CryptoHash::default(), 0, PROTOCOL_VERSION),
+            },
+            StateRecord::AccessKey {
+                account_id: account_id.clone(),
+                public_key: create_user_test_signer(&account_id).public_key(),
+                access_key: AccessKey::full_access(),
+            },
+        ];
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), borsh::to_vec(&GenesisRecords(records.clone())).unwrap())
+            .unwrap();
+
+        let mut builder = TestGenesisBuilder::new();
+        builder.with_state_records_from_file(file.path());
+        let (genesis, _, _) = builder.build();
+
+        let GenesisContents::Records { records: GenesisRecords(genesis_records) } =
+            &genesis.contents
+        else {
+            panic!("the builder should produce a genesis with records");
+        };
+        assert!(genesis_records.ends_with(&records));
+        assert_eq!(genesis.config.total_supply, get_initial_supply(genesis_records));
+    }
+}
@@ -206,6 +206,11 @@ pub struct CongestionControlConfig {
     /// to send a lot of receipts without making the state witness too large.
     /// It limits the total sum of outgoing receipts, not individual receipts.
     pub outgoing_receipts_big_size_limit: u64,
+
+    /// Maximum number of receipts a shard can forward to another shard in a
+    /// single chunk. Many tiny receipts can overwhelm the receiver even when
+    /// they stay within the gas and size limits.
+    pub max_outgoing_receipts: u64,
 }
 
 // The Eq cannot be automatically derived for this class because it contains a
@@ -233,6 +238,7 @@ impl CongestionControlConfig {
             reject_tx_congestion_threshold: 2.0,
             outgoing_receipts_usual_size_limit: max_value,
             outgoing_receipts_big_size_limit: max_value,
+            max_outgoing_receipts: max_value,
         }
     }
 }
@@ -220,6 +220,9 @@ pub enum Parameter {
     MaxTxGas,
     MinTxGas,
     RejectTxCongestionThreshold,
+    /// Maximum number of receipts a shard can forward to another shard in a
+    /// single chunk.
+    MaxOutgoingReceipts,
 
     // Use the StateStoredReceipt structure when storing receipts in State.
     UseStateStoredReceipt,
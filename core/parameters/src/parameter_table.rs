@@ -369,6 +369,7 @@ fn get_congestion_control_config(
         outgoing_receipts_usual_size_limit: params
             .get(Parameter::OutgoingReceiptsUsualSizeLimit)?,
         outgoing_receipts_big_size_limit: params.get(Parameter::OutgoingReceiptsBigSizeLimit)?,
+        max_outgoing_receipts: params.get(Parameter::MaxOutgoingReceipts)?,
     };
     Ok(congestion_control_config)
 }
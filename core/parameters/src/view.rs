@@ -775,6 +775,11 @@ pub struct CongestionControlConfigView {
     /// to send a lot of receipts without making the state witness too large.
     /// It limits the total sum of outgoing receipts, not individual receipts.
     pub outgoing_receipts_big_size_limit: u64,
+
+    /// Maximum number of receipts a shard can forward to another shard in a
+    /// single chunk. Many tiny receipts can overwhelm the receiver even when
+    /// they stay within the gas and size limits.
+    pub max_outgoing_receipts: u64,
 }
 
 impl From<CongestionControlConfig> for CongestionControlConfigView {
@@ -792,6 +797,7 @@ impl From<CongestionControlConfig> for CongestionControlConfigView {
             reject_tx_congestion_threshold: other.reject_tx_congestion_threshold,
             outgoing_receipts_usual_size_limit: other.outgoing_receipts_usual_size_limit,
             outgoing_receipts_big_size_limit: other.outgoing_receipts_big_size_limit,
+            max_outgoing_receipts: other.max_outgoing_receipts,
         }
     }
 }
@@ -811,6 +817,7 @@ impl From<CongestionControlConfigView> for CongestionControlConfig {
             reject_tx_congestion_threshold: other.reject_tx_congestion_threshold,
             outgoing_receipts_usual_size_limit: other.outgoing_receipts_usual_size_limit,
             outgoing_receipts_big_size_limit: other.outgoing_receipts_big_size_limit,
+            max_outgoing_receipts: other.max_outgoing_receipts,
         }
     }
 }
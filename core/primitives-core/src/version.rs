@@ -192,6 +192,9 @@ pub enum ProtocolFeature {
     /// Exclude existing contract code in deploy-contract and delete-account actions from the chunk state witness.
     /// Instead of sending code in the witness, the code checks the code-size using the internal trie nodes.
     ExcludeExistingCodeFromWitnessForCodeLen,
+    /// Forward more receipts from the outgoing buffers at the end of the
+    /// chunk, using the outgoing limits left after processing receipts.
+    ForwardRemainingBufferedReceipts,
 }
 
 impl ProtocolFeature {
@@ -276,6 +279,7 @@ impl ProtocolFeature {
             ProtocolFeature::ExcludeExistingCodeFromWitnessForCodeLen => 147,
             ProtocolFeature::BandwidthScheduler => 148,
             // Place features that are not yet in Nightly below this line.
+            ProtocolFeature::ForwardRemainingBufferedReceipts => 149,
         }
     }
 
@@ -152,6 +152,34 @@ impl BandwidthRequest {
 
         BandwidthRequest { to_shard: to_shard.into(), requested_values_bitmap: bitmap }
     }
+
+    /// Smallest value of bandwidth requested in this request, i.e. the smallest grant that
+    /// allows the shard to send out more than base bandwidth. None when nothing is requested.
+    pub fn minimum_covered_bytes(&self, params: &BandwidthSchedulerParams) -> Option<Bandwidth> {
+        let values = BandwidthRequestValues::new(params).values;
+        (0..self.requested_values_bitmap.len())
+            .find(|&idx| self.requested_values_bitmap.get_bit(idx))
+            .map(|idx| values[idx])
+    }
+
+    /// Largest value of bandwidth requested in this request, i.e. the grant that allows the
+    /// shard to send out everything it asked for. None when nothing is requested.
+    pub fn maximum_covered_bytes(&self, params: &BandwidthSchedulerParams) -> Option<Bandwidth> {
+        let values = BandwidthRequestValues::new(params).values;
+        (0..self.requested_values_bitmap.len())
+            .rev()
+            .find(|&idx| self.requested_values_bitmap.get_bit(idx))
+            .map(|idx| values[idx])
+    }
+
+    /// Checks whether `bytes` is within the range of bandwidth values covered by this request,
+    /// from the smallest to the largest requested value, both inclusive.
+    pub fn covers_size(&self, bytes: u64, params: &BandwidthSchedulerParams) -> bool {
+        match (self.minimum_covered_bytes(params), self.maximum_covered_bytes(params)) {
+            (Some(min), Some(max)) => (min..=max).contains(&bytes),
+            _ => false,
+        }
+    }
 }
 
 /// There are this many predefined values of bandwidth that can be requested in a BandwidthRequest.
@@ -598,6 +626,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bandwidth_request_covered_bytes() {
+        let params = BandwidthSchedulerParams::new(
+            NonZeroU64::new(6).unwrap(),
+            &make_runtime_config(4 * 1024 * 1024),
+        );
+        let values = BandwidthRequestValues::new(&params).values;
+
+        // An empty request doesn't cover anything.
+        let empty_request = make_request_with_ones(&[]);
+        assert_eq!(empty_request.minimum_covered_bytes(&params), None);
+        assert_eq!(empty_request.maximum_covered_bytes(&params), None);
+        assert!(!empty_request.covers_size(values[0], &params));
+
+        let request = make_request_with_ones(&[3, 10, 20]);
+        assert_eq!(request.minimum_covered_bytes(&params), Some(values[3]));
+        assert_eq!(request.maximum_covered_bytes(&params), Some(values[20]));
+        assert!(!request.covers_size(values[3] - 1, &params));
+        assert!(request.covers_size(values[3], &params));
+        assert!(request.covers_size(values[15], &params));
+        assert!(request.covers_size(values[20], &params));
+        assert!(!request.covers_size(values[20] + 1, &params));
+
+        // A request built from receipt sizes covers their total size.
+        let receipt_sizes = [params.base_bandwidth, values[5] - params.base_bandwidth];
+        let request = BandwidthRequest::make_from_receipt_sizes(
+            ShardUId::single_shard().shard_id(),
+            make_sizes_iter(&receipt_sizes),
+            &params,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(request.covers_size(receipt_sizes.iter().sum(), &params));
+    }
+
     /// Generate random receipt sizes and create a bandwidth request from them.
     /// Compare the created bandwidth request with a request created using simpler logic.
     #[test]
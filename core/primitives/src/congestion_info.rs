@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use crate::errors::RuntimeError;
+use crate::errors::{IntegerOverflowError, RuntimeError};
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_parameters::config::CongestionControlConfig;
 use near_primitives_core::types::{Gas, ShardId};
@@ -106,6 +106,11 @@ impl CongestionControl {
         }
     }
 
+    /// How many receipts another shard can send to us in the next block.
+    pub fn outgoing_receipts_limit(&self) -> u64 {
+        self.config.max_outgoing_receipts
+    }
+
     /// How much gas we accept for executing new transactions going to any
     /// uncongested shards.
     pub fn process_tx_limit(&self) -> Gas {
@@ -309,6 +314,55 @@ impl CongestionInfo {
         Ok(())
     }
 
+    /// Removes the bytes and gas of receipts forwarded from the outgoing
+    /// buffers. Either both values are updated or, if either of them would
+    /// underflow, none is.
+    pub fn subtract_forwarded(&mut self, bytes: u64, gas: Gas) -> Result<(), IntegerOverflowError> {
+        match self {
+            CongestionInfo::V1(inner) => {
+                let receipt_bytes =
+                    inner.receipt_bytes.checked_sub(bytes).ok_or(IntegerOverflowError)?;
+                let buffered_receipts_gas = inner
+                    .buffered_receipts_gas
+                    .checked_sub(gas as u128)
+                    .ok_or(IntegerOverflowError)?;
+                inner.receipt_bytes = receipt_bytes;
+                inner.buffered_receipts_gas = buffered_receipts_gas;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the congestion info is internally consistent. See
+    /// [`CongestionInfoV1::sanity_check`].
+    pub fn sanity_check(&self) -> Result<(), CongestionSanityError> {
+        match self {
+            CongestionInfo::V1(inner) => inner.sanity_check(),
+        }
+    }
+
+    /// Checks that `allowed_shard` is one of `all_shards`, or `own_shard` if
+    /// there are no other shards. Only meaningful after
+    /// [`Self::finalize_allowed_shard`] was called with the same arguments.
+    pub fn check_allowed_shard(
+        &self,
+        own_shard: ShardId,
+        all_shards: &[ShardId],
+    ) -> Result<(), CongestionSanityError> {
+        let allowed_shard = ShardId::from(self.allowed_shard());
+        let is_known = if all_shards.is_empty() {
+            allowed_shard == own_shard
+        } else {
+            all_shards.contains(&allowed_shard)
+        };
+        if !is_known {
+            return Err(CongestionSanityError::UnknownAllowedShard {
+                allowed_shard: self.allowed_shard(),
+            });
+        }
+        Ok(())
+    }
+
     /// Congestion level ignoring the chain context (missed chunks count).
     pub fn localized_congestion_level(&self, config: &CongestionControlConfig) -> f64 {
         let incoming_congestion = self.incoming_congestion(config);
@@ -451,6 +505,57 @@ pub struct CongestionInfoV1 {
     pub allowed_shard: u16,
 }
 
+impl CongestionInfoV1 {
+    /// Checks that the gas and bytes accounted for in the congestion info are
+    /// consistent with each other. An error indicates an accounting bug.
+    ///
+    /// Every receipt stored in state has a non-zero size and at most
+    /// `Gas::MAX` gas attached. Hence there can be no receipt gas without
+    /// receipt bytes, and the total gas is bounded by `receipt_bytes *
+    /// Gas::MAX`.
+    ///
+    /// `allowed_shard` is not checked here because it may legitimately point
+    /// to a shard of the previous layout until it is finalized for the next
+    /// chunk. See [`CongestionInfo::check_allowed_shard`] for that.
+    pub fn sanity_check(&self) -> Result<(), CongestionSanityError> {
+        let total_gas = self.delayed_receipts_gas.checked_add(self.buffered_receipts_gas);
+        if total_gas != Some(0) && self.receipt_bytes == 0 {
+            return Err(CongestionSanityError::GasWithoutReceiptBytes {
+                delayed_receipts_gas: self.delayed_receipts_gas,
+                buffered_receipts_gas: self.buffered_receipts_gas,
+            });
+        }
+        let max_gas = self.receipt_bytes as u128 * Gas::MAX as u128;
+        if total_gas.map_or(true, |total_gas| total_gas > max_gas) {
+            return Err(CongestionSanityError::GasExceedsMaximum {
+                delayed_receipts_gas: self.delayed_receipts_gas,
+                buffered_receipts_gas: self.buffered_receipts_gas,
+                receipt_bytes: self.receipt_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Inconsistency found by [`CongestionInfoV1::sanity_check`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CongestionSanityError {
+    #[error(
+        "receipt gas without receipt bytes: delayed gas {delayed_receipts_gas}, buffered gas {buffered_receipts_gas}"
+    )]
+    GasWithoutReceiptBytes { delayed_receipts_gas: u128, buffered_receipts_gas: u128 },
+    #[error(
+        "receipt gas exceeds the maximum for {receipt_bytes} receipt bytes: delayed gas {delayed_receipts_gas}, buffered gas {buffered_receipts_gas}"
+    )]
+    GasExceedsMaximum {
+        delayed_receipts_gas: u128,
+        buffered_receipts_gas: u128,
+        receipt_bytes: u64,
+    },
+    #[error("allowed shard {allowed_shard} is not one of the given shards")]
+    UnknownAllowedShard { allowed_shard: u16 },
+}
+
 /// Returns `value / max` clamped to te range [0,1].
 #[inline]
 fn clamped_f64_fraction(value: u128, max: u64) -> f64 {
@@ -748,6 +853,94 @@ mod tests {
         assert!(control.shard_accepts_transactions().is_yes());
     }
 
+    #[test]
+    fn test_subtract_forwarded() {
+        let mut info = CongestionInfo::default();
+        info.add_receipt_bytes(1000).unwrap();
+        info.add_buffered_receipt_gas(500).unwrap();
+
+        info.subtract_forwarded(400, 200).unwrap();
+        assert_eq!(600, info.receipt_bytes());
+        assert_eq!(300, info.buffered_receipts_gas());
+
+        // Gas underflow must leave the receipt bytes untouched.
+        assert_eq!(Err(IntegerOverflowError), info.subtract_forwarded(100, 301));
+        assert_eq!(600, info.receipt_bytes());
+        assert_eq!(300, info.buffered_receipts_gas());
+
+        // Bytes underflow must leave the buffered gas untouched.
+        assert_eq!(Err(IntegerOverflowError), info.subtract_forwarded(601, 100));
+        assert_eq!(600, info.receipt_bytes());
+        assert_eq!(300, info.buffered_receipts_gas());
+
+        info.subtract_forwarded(600, 300).unwrap();
+        assert_eq!(0, info.receipt_bytes());
+        assert_eq!(0, info.buffered_receipts_gas());
+    }
+
+    #[test]
+    fn test_sanity_check() {
+        let mut info = CongestionInfo::default();
+        assert_eq!(Ok(()), info.sanity_check());
+
+        // Bytes without gas are fine, e.g. for receipts without attached gas.
+        info.add_receipt_bytes(10).unwrap();
+        assert_eq!(Ok(()), info.sanity_check());
+
+        info.add_delayed_receipt_gas(100).unwrap();
+        info.add_buffered_receipt_gas(200).unwrap();
+        assert_eq!(Ok(()), info.sanity_check());
+
+        info.remove_receipt_bytes(10).unwrap();
+        assert_eq!(
+            Err(CongestionSanityError::GasWithoutReceiptBytes {
+                delayed_receipts_gas: 100,
+                buffered_receipts_gas: 200,
+            }),
+            info.sanity_check()
+        );
+
+        let info = CongestionInfo::V1(CongestionInfoV1 {
+            delayed_receipts_gas: Gas::MAX as u128,
+            buffered_receipts_gas: 1,
+            receipt_bytes: 1,
+            allowed_shard: 0,
+        });
+        assert_eq!(
+            Err(CongestionSanityError::GasExceedsMaximum {
+                delayed_receipts_gas: Gas::MAX as u128,
+                buffered_receipts_gas: 1,
+                receipt_bytes: 1,
+            }),
+            info.sanity_check()
+        );
+    }
+
+    #[test]
+    fn test_check_allowed_shard() {
+        let own_shard = ShardId::new(1);
+        let all_shards = [ShardId::new(0), ShardId::new(1), ShardId::new(2)];
+
+        let mut info = CongestionInfo::default();
+        for seed in 0..all_shards.len() as u64 {
+            info.finalize_allowed_shard(own_shard, &all_shards, seed);
+            assert_eq!(Ok(()), info.check_allowed_shard(own_shard, &all_shards));
+        }
+
+        info.finalize_allowed_shard(own_shard, &[], 0);
+        assert_eq!(Ok(()), info.check_allowed_shard(own_shard, &[]));
+
+        info.set_allowed_shard(3);
+        assert_eq!(
+            Err(CongestionSanityError::UnknownAllowedShard { allowed_shard: 3 }),
+            info.check_allowed_shard(own_shard, &all_shards)
+        );
+        assert_eq!(
+            Err(CongestionSanityError::UnknownAllowedShard { allowed_shard: 3 }),
+            info.check_allowed_shard(own_shard, &[])
+        );
+    }
+
     #[test]
     fn test_missed_chunks_congestion() {
         if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
@@ -2,8 +2,8 @@ use crate::num_rational::Rational32;
 use crate::shard_layout::ShardLayout;
 use crate::types::validator_stake::ValidatorStake;
 use crate::types::{
-    AccountId, Balance, BlockChunkValidatorStats, BlockHeightDelta, NumSeats, ProtocolVersion,
-    ValidatorKickoutReason,
+    AccountId, Balance, BlockChunkValidatorStats, BlockHeightDelta, NumSeats, NumShards,
+    ProtocolVersion, ValidatorKickoutReason,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_primitives_core::checked_feature;
@@ -12,10 +12,10 @@ use near_primitives_core::serialize::dec_format;
 use near_primitives_core::version::{ProtocolFeature, PROTOCOL_VERSION};
 use near_schema_checker_lib::ProtocolSchema;
 use smart_default::SmartDefault;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::ops::Bound;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub const AGGREGATOR_KEY: &[u8] = b"AGGREGATOR";
@@ -466,6 +466,80 @@ pub struct EpochConfigStore {
     store: BTreeMap<ProtocolVersion, Arc<EpochConfig>>,
 }
 
+/// Difference between the epoch configs in effect for a protocol version in two
+/// [`EpochConfigStore`]s, see [`EpochConfigStore::diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochConfigDiff {
+    pub protocol_version: ProtocolVersion,
+    pub changed_fields: Vec<EpochConfigFieldDiff>,
+}
+
+/// A field with different values in the compared epoch configs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochConfigFieldDiff {
+    /// Path to the field, e.g. `validator_selection_config.num_chunk_producer_seats`.
+    /// Empty when one of the stores has no config for the protocol version at all.
+    pub field: String,
+    pub left: serde_json::Value,
+    pub right: serde_json::Value,
+}
+
+impl std::fmt::Display for EpochConfigDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "protocol version {}:", self.protocol_version)?;
+        for field_diff in &self.changed_fields {
+            write!(f, " {}: {} -> {};", field_diff.field, field_diff.left, field_diff.right)?;
+        }
+        Ok(())
+    }
+}
+
+/// Change between the epoch configs in effect for a protocol version in two
+/// [`EpochConfigStore`]s which can break the network when upgrading from one
+/// to the other, see [`EpochConfigStore::verify_no_breaking_changes`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BreakingChange {
+    #[error(
+        "protocol version {protocol_version}: number of shards decreased from {before} to {after}"
+    )]
+    ShardCountDecreased { protocol_version: ProtocolVersion, before: NumShards, after: NumShards },
+    #[error("protocol version {protocol_version}: block producer seats decreased from {before} to {after}")]
+    BlockProducerSeatsDecreased {
+        protocol_version: ProtocolVersion,
+        before: NumSeats,
+        after: NumSeats,
+    },
+    #[error("protocol version {protocol_version}: chunk validator seats decreased from {before} to {after}")]
+    ChunkValidatorSeatsDecreased {
+        protocol_version: ProtocolVersion,
+        before: NumSeats,
+        after: NumSeats,
+    },
+    #[error("protocol version {protocol_version}: {threshold} increased from {before} to {after}")]
+    KickoutThresholdIncreased {
+        protocol_version: ProtocolVersion,
+        /// Name of the field of the threshold, e.g. `block_producer_kickout_threshold`.
+        threshold: &'static str,
+        before: u8,
+        after: u8,
+    },
+}
+
+/// Error returned when loading an epoch config override file fails.
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigLoadError {
+    #[error("Failed to read epoch config override file {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Failed to parse epoch config override file {0}: {1}")]
+    InvalidFormat(PathBuf, serde_json::Error),
+    #[error("Invalid protocol version {0} in epoch config override file")]
+    InvalidProtocolVersion(String),
+    #[error("No epoch config to override for protocol version {0}")]
+    MissingBaseConfig(ProtocolVersion),
+    #[error("Invalid epoch config override for protocol version {0}: {1}")]
+    InvalidOverride(ProtocolVersion, serde_json::Error),
+}
+
 impl EpochConfigStore {
     /// Creates a config store to contain the EpochConfigs for the given chain parsed from the JSON files.
     /// If no configs are found for the given chain, try to load the configs from the file system.
@@ -540,6 +614,39 @@ impl EpochConfigStore {
         store
     }
 
+    /// Applies the epoch config overrides from the JSON file at `path` on top of the
+    /// configs of the store.
+    ///
+    /// The file maps protocol versions, as strings, to partial epoch configs, see
+    /// `res/epoch_configs/override_schema.json`. The fields of each override replace
+    /// the ones of the config in effect for that protocol version, nested objects
+    /// are merged recursively. The resulting config is stored for that exact version.
+    pub fn apply_override(mut self, path: &Path) -> Result<Self, ConfigLoadError> {
+        let content =
+            fs::read_to_string(path).map_err(|e| ConfigLoadError::Io(path.to_path_buf(), e))?;
+        let overrides: BTreeMap<String, serde_json::Value> = serde_json::from_str(&content)
+            .map_err(|e| ConfigLoadError::InvalidFormat(path.to_path_buf(), e))?;
+
+        for (version, config_override) in overrides {
+            let version: ProtocolVersion = version
+                .parse()
+                .map_err(|_| ConfigLoadError::InvalidProtocolVersion(version.clone()))?;
+            let base_config = self
+                .store
+                .range((Bound::Unbounded, Bound::Included(version)))
+                .next_back()
+                .map(|(_, config)| config.as_ref())
+                .ok_or(ConfigLoadError::MissingBaseConfig(version))?;
+            let mut config = serde_json::to_value(base_config)
+                .map_err(|e| ConfigLoadError::InvalidOverride(version, e))?;
+            merge_json(&mut config, config_override);
+            let config: EpochConfig = serde_json::from_value(config)
+                .map_err(|e| ConfigLoadError::InvalidOverride(version, e))?;
+            self.store.insert(version, Arc::new(config));
+        }
+        Ok(self)
+    }
+
     pub fn test(store: BTreeMap<ProtocolVersion, Arc<EpochConfig>>) -> Self {
         Self { store }
     }
@@ -548,13 +655,130 @@ impl EpochConfigStore {
     /// This panics if no config is found for the given version, thus the initialization via `for_chain_id` should
     /// only be performed for chains with some configs stored in files.
     pub fn get_config(&self, protocol_version: ProtocolVersion) -> &Arc<EpochConfig> {
+        self.find_config(protocol_version).unwrap_or_else(|| {
+            panic!("Failed to find EpochConfig for protocol version {}", protocol_version)
+        })
+    }
+
+    fn find_config(&self, protocol_version: ProtocolVersion) -> Option<&Arc<EpochConfig>> {
         self.store
             .range((Bound::Unbounded, Bound::Included(protocol_version)))
             .next_back()
-            .unwrap_or_else(|| {
-                panic!("Failed to find EpochConfig for protocol version {}", protocol_version)
-            })
-            .1
+            .map(|(_, config)| config)
+    }
+
+    /// Compares the configs in effect in `a` and `b` for every protocol version
+    /// at which either store changes its config. Returns the versions for which
+    /// the configs differ, with the fields that differ, in increasing order of
+    /// protocol versions. An empty result means that both stores behave the same.
+    pub fn diff(a: &EpochConfigStore, b: &EpochConfigStore) -> Vec<EpochConfigDiff> {
+        let versions: BTreeSet<ProtocolVersion> =
+            a.store.keys().chain(b.store.keys()).copied().collect();
+        let mut diffs = vec![];
+        for protocol_version in versions {
+            let left = a.find_config(protocol_version);
+            let right = b.find_config(protocol_version);
+            if left == right {
+                continue;
+            }
+            let to_json = |config: Option<&Arc<EpochConfig>>| {
+                config.map_or(serde_json::Value::Null, |config| {
+                    serde_json::to_value(config.as_ref())
+                        .expect("Failed to serialize the epoch config")
+                })
+            };
+            let mut changed_fields = vec![];
+            diff_json(String::new(), &to_json(left), &to_json(right), &mut changed_fields);
+            diffs.push(EpochConfigDiff { protocol_version, changed_fields });
+        }
+        diffs
+    }
+
+    /// Checks that the configs in effect in `after` don't have fewer shards or
+    /// seats, or higher kickout thresholds, than the ones in `before`, for
+    /// `from_version` and every later protocol version at which either store
+    /// changes its config. Versions without config in one of the stores are
+    /// skipped.
+    pub fn verify_no_breaking_changes(
+        before: &EpochConfigStore,
+        after: &EpochConfigStore,
+        from_version: ProtocolVersion,
+    ) -> Result<(), Vec<BreakingChange>> {
+        let versions: BTreeSet<ProtocolVersion> = before
+            .store
+            .keys()
+            .chain(after.store.keys())
+            .copied()
+            .filter(|version| *version > from_version)
+            .chain(std::iter::once(from_version))
+            .collect();
+        let mut breaking_changes = vec![];
+        for protocol_version in versions {
+            let (Some(before), Some(after)) =
+                (before.find_config(protocol_version), after.find_config(protocol_version))
+            else {
+                continue;
+            };
+            let (before_shards, after_shards) =
+                (before.shard_layout.num_shards(), after.shard_layout.num_shards());
+            if after_shards < before_shards {
+                breaking_changes.push(BreakingChange::ShardCountDecreased {
+                    protocol_version,
+                    before: before_shards,
+                    after: after_shards,
+                });
+            }
+            if after.num_block_producer_seats < before.num_block_producer_seats {
+                breaking_changes.push(BreakingChange::BlockProducerSeatsDecreased {
+                    protocol_version,
+                    before: before.num_block_producer_seats,
+                    after: after.num_block_producer_seats,
+                });
+            }
+            let (before_seats, after_seats) = (
+                before.validator_selection_config.num_chunk_validator_seats,
+                after.validator_selection_config.num_chunk_validator_seats,
+            );
+            if after_seats < before_seats {
+                breaking_changes.push(BreakingChange::ChunkValidatorSeatsDecreased {
+                    protocol_version,
+                    before: before_seats,
+                    after: after_seats,
+                });
+            }
+            let thresholds = [
+                (
+                    "block_producer_kickout_threshold",
+                    before.block_producer_kickout_threshold,
+                    after.block_producer_kickout_threshold,
+                ),
+                (
+                    "chunk_producer_kickout_threshold",
+                    before.chunk_producer_kickout_threshold,
+                    after.chunk_producer_kickout_threshold,
+                ),
+                (
+                    "chunk_validator_only_kickout_threshold",
+                    before.chunk_validator_only_kickout_threshold,
+                    after.chunk_validator_only_kickout_threshold,
+                ),
+            ];
+            for (threshold, before, after) in thresholds {
+                if after > before {
+                    breaking_changes.push(BreakingChange::KickoutThresholdIncreased {
+                        protocol_version,
+                        threshold,
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        if breaking_changes.is_empty() {
+            Ok(())
+        } else {
+            Err(breaking_changes)
+        }
     }
 
     fn dump_epoch_config(directory: &str, version: &ProtocolVersion, config: &Arc<EpochConfig>) {
@@ -586,15 +810,65 @@ impl EpochConfigStore {
     }
 }
 
+/// Collects the paths of the values that differ between `left` and `right`.
+/// Objects are compared key by key, any other values are compared as a whole.
+fn diff_json(
+    path: String,
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    diffs: &mut Vec<EpochConfigFieldDiff>,
+) {
+    match (left, right) {
+        (serde_json::Value::Object(left), serde_json::Value::Object(right)) => {
+            let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+            for key in keys {
+                let field = if path.is_empty() { key.clone() } else { format!("{path}.{key}") };
+                let null = serde_json::Value::Null;
+                diff_json(
+                    field,
+                    left.get(key).unwrap_or(&null),
+                    right.get(key).unwrap_or(&null),
+                    diffs,
+                );
+            }
+        }
+        (left, right) if left != right => diffs.push(EpochConfigFieldDiff {
+            field: path,
+            left: left.clone(),
+            right: right.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Recursively merges `patch` into `base`. Objects are merged key by key, any
+/// other value replaces the one in `base`.
+fn merge_json(base: &mut serde_json::Value, patch: serde_json::Value) {
+    match (base, patch) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge_json(base.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
+    use std::collections::BTreeMap;
     use std::fs;
     use std::path::Path;
+    use std::sync::Arc;
 
     use near_primitives_core::types::ProtocolVersion;
     use near_primitives_core::version::PROTOCOL_VERSION;
 
-    use crate::epoch_manager::{AllEpochConfig, EpochConfig};
+    use crate::epoch_manager::{
+        AllEpochConfig, BreakingChange, ConfigLoadError, EpochConfig, EpochConfigDiff,
+        EpochConfigFieldDiff,
+    };
 
     use super::EpochConfigStore;
 
@@ -681,6 +955,145 @@ mod tests {
         assert_eq!(epoch_config_55, epoch_config_48);
     }
 
+    #[test]
+    fn test_apply_override() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("override.json");
+        fs::write(
+            &path,
+            r#"{
+                "100": { "epoch_length": 100 },
+                "200": { "validator_selection_config": { "num_chunk_producer_seats": 7 } }
+            }"#,
+        )
+        .unwrap();
+
+        let store = EpochConfigStore::for_chain_id("mainnet", None).unwrap();
+        let config_100 = store.get_config(100).as_ref().clone();
+        let config_200 = store.get_config(200).as_ref().clone();
+        let store = store.apply_override(&path).unwrap();
+
+        let overridden_100 = store.get_config(100);
+        assert_eq!(overridden_100.epoch_length, 100);
+        assert_eq!(overridden_100.shard_layout, config_100.shard_layout);
+        // Versions between the overrides keep using the previous config.
+        assert_eq!(store.get_config(150).epoch_length, 100);
+
+        let overridden_200 = store.get_config(200);
+        assert_eq!(overridden_200.validator_selection_config.num_chunk_producer_seats, 7);
+        assert_eq!(
+            overridden_200.validator_selection_config.num_chunk_validator_seats,
+            config_200.validator_selection_config.num_chunk_validator_seats
+        );
+        assert_eq!(overridden_200.epoch_length, config_200.epoch_length);
+
+        fs::write(&path, r#"{ "latest": { "epoch_length": 100 } }"#).unwrap();
+        let store = EpochConfigStore::for_chain_id("mainnet", None).unwrap();
+        assert_matches!(
+            store.apply_override(&path),
+            Err(ConfigLoadError::InvalidProtocolVersion(_))
+        );
+    }
+
+    #[test]
+    fn test_epoch_config_store_diff() {
+        let mainnet_store = EpochConfigStore::for_chain_id("mainnet", None).unwrap();
+        assert_eq!(EpochConfigStore::diff(&mainnet_store, &mainnet_store), vec![]);
+
+        let config = mainnet_store.get_config(100).as_ref().clone();
+        let mut changed_config = config.clone();
+        changed_config.epoch_length += 1;
+        changed_config.validator_selection_config.num_chunk_producer_seats += 1;
+        let store = EpochConfigStore::test(BTreeMap::from([(100, Arc::new(config.clone()))]));
+        let changed_store = EpochConfigStore::test(BTreeMap::from([
+            (100, Arc::new(config.clone())),
+            (200, Arc::new(changed_config.clone())),
+        ]));
+
+        // Only the versions from which the configs differ are reported.
+        let diffs = EpochConfigStore::diff(&store, &changed_store);
+        assert_eq!(
+            diffs,
+            vec![EpochConfigDiff {
+                protocol_version: 200,
+                changed_fields: vec![
+                    EpochConfigFieldDiff {
+                        field: "epoch_length".to_string(),
+                        left: config.epoch_length.into(),
+                        right: changed_config.epoch_length.into(),
+                    },
+                    EpochConfigFieldDiff {
+                        field: "validator_selection_config.num_chunk_producer_seats".to_string(),
+                        left: config.validator_selection_config.num_chunk_producer_seats.into(),
+                        right: changed_config
+                            .validator_selection_config
+                            .num_chunk_producer_seats
+                            .into(),
+                    },
+                ],
+            }]
+        );
+
+        // A version without config in one of the stores is reported as a whole.
+        let later_store = EpochConfigStore::test(BTreeMap::from([(150, Arc::new(config))]));
+        let diffs = EpochConfigStore::diff(&store, &later_store);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].protocol_version, 100);
+        assert_eq!(diffs[0].changed_fields.len(), 1);
+        assert_eq!(diffs[0].changed_fields[0].field, "");
+        assert!(diffs[0].changed_fields[0].right.is_null());
+    }
+
+    #[test]
+    fn test_verify_no_breaking_changes() {
+        let mainnet_store = EpochConfigStore::for_chain_id("mainnet", None).unwrap();
+        assert_eq!(
+            EpochConfigStore::verify_no_breaking_changes(&mainnet_store, &mainnet_store, 29),
+            Ok(())
+        );
+
+        let config = mainnet_store.get_config(100).as_ref().clone();
+        let mut changed_config = config.clone();
+        changed_config.num_block_producer_seats -= 1;
+        changed_config.validator_selection_config.num_chunk_validator_seats += 1;
+        changed_config.chunk_producer_kickout_threshold += 1;
+        let store = EpochConfigStore::test(BTreeMap::from([(100, Arc::new(config.clone()))]));
+        let changed_store = EpochConfigStore::test(BTreeMap::from([
+            (100, Arc::new(config.clone())),
+            (200, Arc::new(changed_config.clone())),
+        ]));
+
+        // Only the decreased seats and the increased threshold are breaking.
+        assert_eq!(
+            EpochConfigStore::verify_no_breaking_changes(&store, &changed_store, 100),
+            Err(vec![
+                BreakingChange::BlockProducerSeatsDecreased {
+                    protocol_version: 200,
+                    before: config.num_block_producer_seats,
+                    after: changed_config.num_block_producer_seats,
+                },
+                BreakingChange::KickoutThresholdIncreased {
+                    protocol_version: 200,
+                    threshold: "chunk_producer_kickout_threshold",
+                    before: config.chunk_producer_kickout_threshold,
+                    after: changed_config.chunk_producer_kickout_threshold,
+                },
+            ])
+        );
+        // Versions before `from_version` are not checked, the changed config
+        // stays in effect after it.
+        assert!(EpochConfigStore::verify_no_breaking_changes(&store, &changed_store, 300).is_err());
+        // The other way around, only the chunk validator seats decreased.
+        assert_eq!(
+            EpochConfigStore::verify_no_breaking_changes(&changed_store, &store, 100),
+            Err(vec![BreakingChange::ChunkValidatorSeatsDecreased {
+                protocol_version: 200,
+                before: changed_config.validator_selection_config.num_chunk_validator_seats,
+                after: config.validator_selection_config.num_chunk_validator_seats,
+            }])
+        );
+    }
+
     #[test]
     #[ignore]
     fn generate_epoch_configs_mainnet() {
@@ -8,6 +8,7 @@ use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use rand::SeedableRng;
 use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
 use std::{fmt, str};
 
 /// This file implements two data structure `ShardLayout` and `ShardUId`
@@ -327,6 +328,8 @@ impl ShardLayoutV2 {
 pub enum ShardLayoutError {
     InvalidShardIdError { shard_id: ShardId },
     InvalidShardIndexError { shard_index: ShardIndex },
+    InvalidShardUIdError { shard_uid: ShardUId },
+    InvalidBoundaryAccountError { account_id: AccountId, shard_id: ShardId },
 }
 
 impl fmt::Display for ShardLayoutError {
@@ -668,6 +671,32 @@ impl ShardLayout {
         ShardLayout::v2(boundary_accounts, shard_ids, Some(shards_split_map))
     }
 
+    /// Derive new shard layout from an existing one by splitting the `parent`
+    /// shard at `new_boundary_account`. Unlike `derive_shard_layout`, checks
+    /// that `parent` is a shard of `base_layout` and that the new boundary
+    /// account splits it into two non-empty shards.
+    pub fn split_shard_uid(
+        parent: ShardUId,
+        new_boundary_account: AccountId,
+        base_layout: &ShardLayout,
+    ) -> Result<ShardLayout, ShardLayoutError> {
+        if !base_layout.shard_uids().any(|shard_uid| shard_uid == parent) {
+            return Err(ShardLayoutError::InvalidShardUIdError { shard_uid: parent });
+        }
+        let shard_id = parent.shard_id();
+        // The boundary account of the parent belongs to it, but splitting the
+        // parent there would leave its left child empty.
+        if account_id_to_shard_id(&new_boundary_account, base_layout) != shard_id
+            || base_layout.boundary_accounts().contains(&new_boundary_account)
+        {
+            return Err(ShardLayoutError::InvalidBoundaryAccountError {
+                account_id: new_boundary_account,
+                shard_id,
+            });
+        }
+        Ok(Self::derive_shard_layout(base_layout, new_boundary_account))
+    }
+
     #[inline]
     pub fn version(&self) -> ShardVersion {
         match self {
@@ -737,6 +766,117 @@ impl ShardLayout {
         }
     }
 
+    /// Returns the shard uid of the shard that all accounts starting with
+    /// `prefix` are assigned to, or None if such accounts are split between
+    /// multiple shards. That happens when a boundary account starts with the
+    /// prefix, without being equal to it.
+    /// Always returns None for V0, where accounts are assigned to shards by hash.
+    pub fn shard_uid_for_prefix(&self, prefix: &str) -> Option<ShardUId> {
+        let shard_id = self.shard_id_for_prefix(prefix)?;
+        Some(ShardUId::from_shard_id_and_layout(shard_id, self))
+    }
+
+    /// Same as [`Self::shard_uid_for_prefix`], but returns the shard id.
+    pub fn shard_id_for_prefix(&self, prefix: &str) -> Option<ShardId> {
+        let range = self.prefix_shard_range(prefix)?;
+        (range.start() == range.end()).then(|| *range.start())
+    }
+
+    /// Returns the shard ids of the first and the last shard, in the order of
+    /// the layout, containing accounts starting with `prefix`. All the shards
+    /// between them in the layout contain such accounts as well. The range is
+    /// made of a single shard id if the prefix doesn't span a boundary.
+    /// Always returns None for V0, where accounts are assigned to shards by hash.
+    pub fn prefix_shard_range(&self, prefix: &str) -> Option<RangeInclusive<ShardId>> {
+        if let Self::V0(_) = self {
+            return None;
+        }
+        let boundary_accounts = self.boundary_accounts();
+        // Same as in `account_id_to_shard_id`, the prefix belongs to the shard
+        // after the last boundary account which is not greater than it.
+        let first_shard_index = boundary_accounts
+            .partition_point(|boundary_account| boundary_account.as_str() <= prefix);
+        // Boundary accounts extending the prefix are sorted right after the
+        // ones not greater than it, each of them starts another shard.
+        let last_shard_index = boundary_accounts.partition_point(|boundary_account| {
+            boundary_account.as_str() <= prefix || boundary_account.as_str().starts_with(prefix)
+        });
+        let first_shard_id = self.get_shard_id(first_shard_index).ok()?;
+        let last_shard_id = self.get_shard_id(last_shard_index).ok()?;
+        Some(first_shard_id..=last_shard_id)
+    }
+
+    /// Returns the largest number of the given accounts that fall into a
+    /// single shard. Given a sample of the existing accounts, this estimates
+    /// how unevenly the accounts are distributed between the shards.
+    pub fn max_accounts_per_shard_estimate(&self, account_ids: &[AccountId]) -> usize {
+        self.num_accounts_per_shard_index(account_ids).into_iter().max().unwrap_or(0)
+    }
+
+    /// Returns the shard id of the shard with the most of the given accounts,
+    /// the first one in the order of the layout on ties. This is the best
+    /// candidate for the next split given a sample of the existing accounts.
+    /// Returns None if there are no accounts, and always for V0, where shards
+    /// can't be split.
+    pub fn recommend_split_shard(&self, account_ids: &[AccountId]) -> Option<ShardId> {
+        if let Self::V0(_) = self {
+            return None;
+        }
+        let mut best: Option<(ShardIndex, usize)> = None;
+        for (shard_index, num_accounts) in
+            self.num_accounts_per_shard_index(account_ids).into_iter().enumerate()
+        {
+            if num_accounts > best.map_or(0, |(_, best_num_accounts)| best_num_accounts) {
+                best = Some((shard_index, num_accounts));
+            }
+        }
+        let (shard_index, _) = best?;
+        self.get_shard_id(shard_index).ok()
+    }
+
+    /// Counts the given accounts falling into every shard, by shard index.
+    fn num_accounts_per_shard_index(&self, account_ids: &[AccountId]) -> Vec<usize> {
+        let mut num_accounts = vec![0; self.num_shards() as usize];
+        for account_id in account_ids {
+            let shard_id = account_id_to_shard_id(account_id, self);
+            let shard_index =
+                self.get_shard_index(shard_id).expect("accounts always map to a shard");
+            num_accounts[shard_index] += 1;
+        }
+        num_accounts
+    }
+
+    /// Returns true if the account maps to a shard of the layout, i.e. its
+    /// account range has a shard id and that shard id maps back to the same
+    /// shard index. This only fails for V2 layouts whose shard ids don't match
+    /// the boundary accounts, e.g. ones deserialized from a malformed config.
+    /// V0 and V1 layouts accept any account.
+    pub fn contains_account(&self, account_id: &AccountId) -> bool {
+        match self {
+            Self::V0(_) | Self::V1(_) => true,
+            Self::V2(v2) => {
+                let shard_index = v2
+                    .boundary_accounts
+                    .iter()
+                    .take_while(|boundary_account| *boundary_account <= account_id)
+                    .count();
+                v2.shard_ids
+                    .get(shard_index)
+                    .is_some_and(|shard_id| v2.id_to_index_map.get(shard_id) == Some(&shard_index))
+            }
+        }
+    }
+
+    /// Returns true if the account is a boundary account, i.e. the first
+    /// account of the range of one of the shards.
+    pub fn is_boundary_account(&self, account_id: &AccountId) -> bool {
+        match self {
+            Self::V0(_) => false,
+            Self::V1(v1) => v1.boundary_accounts.binary_search(account_id).is_ok(),
+            Self::V2(v2) => v2.boundary_accounts.binary_search(account_id).is_ok(),
+        }
+    }
+
     /// Get the shard id for a given shard index. The shard id should be used to
     /// identify the shard and starting from the ShardLayoutV2 it is unique.
     pub fn get_shard_id(&self, shard_index: ShardIndex) -> Result<ShardId, ShardLayoutError> {
@@ -1009,9 +1149,10 @@ impl ShardInfo {
 mod tests {
     use crate::epoch_manager::{AllEpochConfig, EpochConfig, ValidatorSelectionConfig};
     use crate::shard_layout::{
-        account_id_to_shard_id, new_shard_ids_vec, new_shards_split_map, ShardLayout,
-        ShardLayoutV1, ShardUId,
+        account_id_to_shard_id, account_id_to_shard_uid, new_shard_ids_vec, new_shards_split_map,
+        ShardLayout, ShardLayoutError, ShardLayoutV1, ShardUId,
     };
+    use assert_matches::assert_matches;
     use itertools::Itertools;
     use near_primitives_core::types::ProtocolVersion;
     use near_primitives_core::types::{AccountId, ShardId};
@@ -1221,6 +1362,122 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_shard_uid_for_prefix() {
+        let shard_layout = get_test_shard_layout_v2();
+        let uid = |shard_id: u64| {
+            Some(ShardUId::from_shard_id_and_layout(ShardId::new(shard_id), &shard_layout))
+        };
+
+        // prefixes within a single range
+        assert_eq!(shard_layout.shard_uid_for_prefix("a"), uid(3));
+        assert_eq!(shard_layout.shard_uid_for_prefix("cb"), uid(3));
+        assert_eq!(shard_layout.shard_uid_for_prefix("cd"), uid(8));
+        assert_eq!(shard_layout.shard_uid_for_prefix("m"), uid(4));
+        assert_eq!(shard_layout.shard_uid_for_prefix("z"), uid(7));
+
+        // prefixes equal to or extending a boundary account
+        assert_eq!(shard_layout.shard_uid_for_prefix("ccc"), uid(8));
+        assert_eq!(shard_layout.shard_uid_for_prefix("kkka"), uid(4));
+        assert_eq!(shard_layout.shard_uid_for_prefix("ppp.near"), uid(7));
+
+        // prefixes spanning a boundary
+        assert_eq!(shard_layout.shard_uid_for_prefix(""), None);
+        assert_eq!(shard_layout.shard_uid_for_prefix("c"), None);
+        assert_eq!(shard_layout.shard_uid_for_prefix("cc"), None);
+        assert_eq!(shard_layout.shard_uid_for_prefix("pp"), None);
+
+        // the result agrees with the assignment of full account ids
+        for account in ["aaa", "ccc.near", "kkk", "kkkz", "pppp", "zzz"] {
+            let account_id: AccountId = account.parse().unwrap();
+            let expected = account_id_to_shard_uid(&account_id, &shard_layout);
+            assert_eq!(shard_layout.shard_uid_for_prefix(account), Some(expected));
+        }
+
+        // hash based layouts can't tell the shard from a prefix
+        assert_eq!(ShardLayout::v0(4, 0).shard_uid_for_prefix("aaa"), None);
+    }
+
+    #[test]
+    fn test_prefix_shard_range() {
+        let shard_layout = get_test_shard_layout_v2();
+        let range = |first: u64, last: u64| Some(ShardId::new(first)..=ShardId::new(last));
+
+        // prefixes within a single range
+        assert_eq!(shard_layout.prefix_shard_range("a"), range(3, 3));
+        assert_eq!(shard_layout.prefix_shard_range("kkka"), range(4, 4));
+        assert_eq!(shard_layout.shard_id_for_prefix("kkka"), Some(ShardId::new(4)));
+        assert_eq!(shard_layout.shard_id_for_prefix("z"), Some(ShardId::new(7)));
+
+        // prefixes spanning one or more boundaries
+        assert_eq!(shard_layout.prefix_shard_range("c"), range(3, 8));
+        assert_eq!(shard_layout.prefix_shard_range("pp"), range(4, 7));
+        assert_eq!(shard_layout.prefix_shard_range(""), range(3, 7));
+        assert_eq!(shard_layout.shard_id_for_prefix("c"), None);
+
+        assert_eq!(ShardLayout::v0(4, 0).prefix_shard_range("aaa"), None);
+    }
+
+    #[test]
+    fn test_accounts_per_shard() {
+        let shard_layout = get_test_shard_layout_v2();
+        let account_ids: Vec<AccountId> = ["aaa", "bbb", "ccc", "kkk", "kkl", "kkm", "zzz"]
+            .iter()
+            .map(|account| account.parse().unwrap())
+            .collect_vec();
+
+        // "kkk", "kkl" and "kkm" are in the third shard, with id 4
+        assert_eq!(shard_layout.max_accounts_per_shard_estimate(&account_ids), 3);
+        assert_eq!(shard_layout.recommend_split_shard(&account_ids), Some(ShardId::new(4)));
+
+        // ties go to the first shard of the layout
+        let account_ids = account_ids[..3].to_vec();
+        assert_eq!(shard_layout.max_accounts_per_shard_estimate(&account_ids), 2);
+        assert_eq!(shard_layout.recommend_split_shard(&account_ids), Some(ShardId::new(3)));
+        let account_ids = account_ids[1..].to_vec();
+        assert_eq!(shard_layout.recommend_split_shard(&account_ids), Some(ShardId::new(3)));
+
+        assert_eq!(shard_layout.max_accounts_per_shard_estimate(&[]), 0);
+        assert_eq!(shard_layout.recommend_split_shard(&[]), None);
+        assert_eq!(ShardLayout::v0(4, 0).recommend_split_shard(&account_ids), None);
+    }
+
+    #[test]
+    fn test_contains_and_is_boundary_account() {
+        let shard_layout = get_test_shard_layout_v2();
+        for account in ["aaa", "ccc", "ccd", "kkk", "ppp", "zzz"] {
+            let account_id: AccountId = account.parse().unwrap();
+            assert!(shard_layout.contains_account(&account_id));
+        }
+        for account in ["ccc", "kkk", "ppp"] {
+            assert!(shard_layout.is_boundary_account(&account.parse().unwrap()));
+        }
+        for account in ["aaa", "cc", "cccc", "kkk.near", "zzz"] {
+            assert!(!shard_layout.is_boundary_account(&account.parse().unwrap()));
+        }
+
+        // A layout with fewer shard ids than account ranges doesn't contain
+        // the accounts of the last range.
+        let mut malformed_layout = get_test_shard_layout_v2();
+        let ShardLayout::V2(v2) = &mut malformed_layout else { unreachable!() };
+        let last_shard_id = v2.shard_ids.pop().unwrap();
+        v2.id_to_index_map.remove(&last_shard_id);
+        assert!(malformed_layout.contains_account(&"aaa".parse().unwrap()));
+        assert!(!malformed_layout.contains_account(&"zzz".parse().unwrap()));
+
+        // A shard id that doesn't map back to its index isn't a shard of the
+        // layout either.
+        let mut malformed_layout = get_test_shard_layout_v2();
+        let ShardLayout::V2(v2) = &mut malformed_layout else { unreachable!() };
+        v2.id_to_index_map.insert(ShardId::new(3), 1);
+        assert!(!malformed_layout.contains_account(&"aaa".parse().unwrap()));
+
+        let shard_layout = ShardLayout::v0(4, 0);
+        let account_id: AccountId = "ccc".parse().unwrap();
+        assert!(shard_layout.contains_account(&account_id));
+        assert!(!shard_layout.is_boundary_account(&account_id));
+    }
+
     fn get_test_shard_layout_v2() -> ShardLayout {
         let b0 = "ccc".parse().unwrap();
         let b1 = "kkk".parse().unwrap();
@@ -1541,6 +1798,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_split_shard_uid() {
+        let base_layout = ShardLayout::derive_shard_layout(
+            &ShardLayout::v2(vec![], vec![ShardId::new(0)], None),
+            "test1.near".parse().unwrap(),
+        );
+        let left_parent = ShardUId::from_shard_id_and_layout(ShardId::new(1), &base_layout);
+        let right_parent = ShardUId::from_shard_id_and_layout(ShardId::new(2), &base_layout);
+
+        // Valid split of the right shard.
+        let split_layout =
+            ShardLayout::split_shard_uid(right_parent, "test3.near".parse().unwrap(), &base_layout)
+                .unwrap();
+        assert_eq!(
+            split_layout,
+            ShardLayout::derive_shard_layout(&base_layout, "test3.near".parse().unwrap())
+        );
+
+        // The parent is not a shard of the base layout.
+        let missing_parent = ShardUId::from_shard_id_and_layout(ShardId::new(7), &base_layout);
+        assert_matches!(
+            ShardLayout::split_shard_uid(
+                missing_parent,
+                "test3.near".parse().unwrap(),
+                &base_layout
+            ),
+            Err(ShardLayoutError::InvalidShardUIdError { .. })
+        );
+        let wrong_version_parent = ShardUId::new(base_layout.version() + 1, ShardId::new(2));
+        assert_matches!(
+            ShardLayout::split_shard_uid(
+                wrong_version_parent,
+                "test3.near".parse().unwrap(),
+                &base_layout
+            ),
+            Err(ShardLayoutError::InvalidShardUIdError { .. })
+        );
+
+        // The boundary account belongs to another shard.
+        assert_matches!(
+            ShardLayout::split_shard_uid(left_parent, "test3.near".parse().unwrap(), &base_layout),
+            Err(ShardLayoutError::InvalidBoundaryAccountError { .. })
+        );
+
+        // The boundary account is already the boundary of the parent.
+        assert_matches!(
+            ShardLayout::split_shard_uid(right_parent, "test1.near".parse().unwrap(), &base_layout),
+            Err(ShardLayoutError::InvalidBoundaryAccountError { .. })
+        );
+    }
+
     // Check that the ShardLayout::multi_shard method returns interesting shard
     // layouts. A shard layout is interesting if it has non-contiguous shard
     // ids.
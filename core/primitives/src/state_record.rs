@@ -9,7 +9,7 @@ use crate::trie_key::trie_key_parsers::{
 };
 use crate::trie_key::{col, TrieKey};
 use crate::types::{AccountId, StoreKey, StoreValue};
-use borsh::BorshDeserialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 use near_crypto::PublicKey;
 use serde_with::base64::Base64;
 use serde_with::serde_as;
@@ -17,7 +17,16 @@ use std::fmt::{Display, Formatter};
 
 /// Record in the state storage.
 #[serde_as]
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Eq, PartialEq)]
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+)]
 pub enum StateRecord {
     /// Account information.
     Account { account_id: AccountId, account: Account },
@@ -122,6 +131,22 @@ impl StateRecord {
         }
         .to_string()
     }
+
+    /// Returns the account that determines which shard the record belongs to.
+    /// Delayed receipts stay in the queue of the shard that delayed them, which
+    /// after resharding doesn't have to be the shard of the receiver, so there
+    /// is no such account for them.
+    pub fn primary_account_id(&self) -> Option<&AccountId> {
+        match self {
+            StateRecord::Account { account_id, .. }
+            | StateRecord::AccessKey { account_id, .. }
+            | StateRecord::Contract { account_id, .. }
+            | StateRecord::ReceivedData { account_id, .. }
+            | StateRecord::Data { account_id, .. } => Some(account_id),
+            StateRecord::PostponedReceipt(receipt) => Some(receipt.receiver_id()),
+            StateRecord::DelayedReceipt(_) => None,
+        }
+    }
 }
 
 impl Display for StateRecord {
@@ -55,6 +55,10 @@ impl FlatStorageChunkView {
         self.flat_storage.get_head_hash()
     }
 
+    pub fn get_block_hash(&self) -> CryptoHash {
+        self.block_hash
+    }
+
     pub fn shard_uid(&self) -> ShardUId {
         self.flat_storage.shard_uid()
     }
@@ -87,6 +87,16 @@ fn genesis_state_from_dump(store: Store, home_dir: &Path) -> Vec<StateRoot> {
     state_roots
 }
 
+/// Writes the genesis state stored in `store` to `home_dir`, in the format
+/// that [`initialize_genesis_state`] loads instead of the genesis records when
+/// it finds a state dump there.
+pub fn save_genesis_state_dump(store: &Store, home_dir: &Path) -> std::io::Result<()> {
+    let state_roots = get_genesis_state_roots(store)?
+        .ok_or_else(|| std::io::Error::other("Genesis state roots not found in storage"))?;
+    fs::write(home_dir.join(GENESIS_ROOTS_FILE), borsh::to_vec(&state_roots)?)?;
+    store.save_state_to_file(&home_dir.join(STATE_DUMP_FILE))
+}
+
 fn genesis_state_from_genesis(
     store: Store,
     genesis: &Genesis,
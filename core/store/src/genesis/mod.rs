@@ -4,7 +4,9 @@
 mod initialization;
 mod state_applier;
 
-pub use initialization::{initialize_genesis_state, initialize_sharded_genesis_state};
+pub use initialization::{
+    initialize_genesis_state, initialize_sharded_genesis_state, save_genesis_state_dump,
+};
 pub use state_applier::compute_genesis_storage_usage;
 pub use state_applier::compute_storage_usage;
 pub use state_applier::GenesisStateApplier;
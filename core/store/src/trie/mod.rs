@@ -785,6 +785,10 @@ impl Trie {
         self.flat_storage_chunk_view.is_some()
     }
 
+    pub fn get_flat_storage_chunk_view(&self) -> Option<&FlatStorageChunkView> {
+        self.flat_storage_chunk_view.as_ref()
+    }
+
     pub fn internal_get_storage_as_caching_storage(&self) -> Option<&TrieCachingStorage> {
         self.storage.as_caching_storage()
     }
@@ -2,8 +2,9 @@ use std::collections::BTreeMap;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytesize::ByteSize;
+use near_parameters::RuntimeConfig;
 use near_primitives::errors::StorageError;
-use near_primitives::receipt::TrieQueueIndices;
+use near_primitives::receipt::{ReceiptOrStateStoredReceipt, TrieQueueIndices};
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{Gas, ShardId};
 use near_primitives::version::ProtocolFeature;
@@ -12,7 +13,7 @@ use near_vm_runner::logic::ProtocolVersion;
 
 use crate::{get, set, TrieUpdate};
 
-use super::receipts_column_helper::TrieQueue;
+use super::receipts_column_helper::{ShardsOutgoingReceiptBuffer, TrieQueue};
 use super::TrieAccess;
 
 /// Keeps metadata about receipts stored in the outgoing buffers.
@@ -89,10 +90,168 @@ impl OutgoingMetadatas {
         metadata.update_on_receipt_popped(receipt_size, receipt_gas, state_update)
     }
 
+    /// Rebuild the metadata of all shards from scratch, based on the receipts that are actually
+    /// stored in the outgoing `buffers`. Used to recover when the metadata got out of sync
+    /// with the buffers. The receipts are read from `trie` and the new metadata is written to
+    /// `state_update`. All existing receipt groups are removed and the tracked receipts (the ones
+    /// for which `update_on_receipt_pushed` would be called) are pushed again, in buffer order.
+    /// Their size and gas are read from the receipt metadata stored in the state.
+    ///
+    /// Receipts buffered before the metadata was introduced are not tracked, the metadata of
+    /// their shard stays incomplete until they are forwarded. They can only be at the front of
+    /// a buffer, an untracked receipt after a tracked one is an inconsistent state.
+    pub fn recompute_from_buffer(
+        &mut self,
+        buffers: &ShardsOutgoingReceiptBuffer,
+        trie: &dyn TrieAccess,
+        config: &RuntimeConfig,
+        state_update: &mut TrieUpdate,
+    ) -> Result<(), StorageError> {
+        let mut tracked_receipts = BTreeMap::new();
+        for shard_id in buffers.shards() {
+            let mut receipts = Vec::new();
+            let mut num_untracked: u64 = 0;
+            for receipt_result in buffers.iter_shard(shard_id, trie, false) {
+                let receipt = receipt_result?;
+                match &receipt {
+                    ReceiptOrStateStoredReceipt::StateStoredReceipt(state_stored_receipt)
+                        if receipt.should_update_outgoing_metadatas() =>
+                    {
+                        let metadata = state_stored_receipt.metadata();
+                        receipts
+                            .push((ByteSize::b(metadata.congestion_size), metadata.congestion_gas));
+                    }
+                    _ if !receipts.is_empty() => {
+                        return Err(StorageError::StorageInconsistentState(format!(
+                            "Receipt {} without metadata follows receipts with metadata in the outgoing buffer to shard {}",
+                            receipt.get_receipt().receipt_id(),
+                            shard_id
+                        )));
+                    }
+                    _ => num_untracked += 1,
+                }
+            }
+            // Without state stored receipts no receipt has metadata, only
+            // warn about the ones that were buffered before they were enabled.
+            if num_untracked > 0 && config.use_state_stored_receipt {
+                tracing::warn!(
+                    target: "store",
+                    %shard_id,
+                    num_untracked,
+                    "Outgoing buffer has receipts without metadata, the recomputed metadata doesn't include them"
+                );
+            }
+            tracked_receipts.insert(shard_id, receipts);
+        }
+
+        for metadata in self.metadatas.values_mut() {
+            metadata.clear(state_update)?;
+        }
+        for (shard_id, receipts) in tracked_receipts {
+            for (receipt_size, receipt_gas) in receipts {
+                self.update_on_receipt_pushed(shard_id, receipt_size, receipt_gas, state_update)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge the metadata of two child shards back into the metadata of their parent, e.g. when
+    /// a resharding is rolled back. The children's receipt groups are read from `left_trie` and
+    /// `right_trie` and written to `state_update`, which should be the state of the parent.
+    /// For every receiver shard, the groups of the left child are followed by the groups of the
+    /// right child, so the outgoing buffers must be merged in the same order.
+    /// Existing parent metadata is overwritten for every receiver shard known to either child.
+    pub fn merge_with_parent(
+        left: &OutgoingMetadatas,
+        left_trie: &dyn TrieAccess,
+        right: &OutgoingMetadatas,
+        right_trie: &dyn TrieAccess,
+        state_update: &mut TrieUpdate,
+    ) -> Result<OutgoingMetadatas, StorageError> {
+        assert_eq!(
+            left.groups_config, right.groups_config,
+            "Merged metadatas must use the same receipt groups config."
+        );
+
+        // Read all groups before writing anything, `state_update` might share keys with
+        // the children tries.
+        let mut merged_groups: BTreeMap<ShardId, Vec<ReceiptGroup>> = BTreeMap::new();
+        for (child, trie) in [(left, left_trie), (right, right_trie)] {
+            for (shard_id, metadata) in &child.metadatas {
+                let groups = merged_groups.entry(*shard_id).or_default();
+                for group in metadata.iter(trie, false) {
+                    groups.push(group?);
+                }
+            }
+        }
+
+        let mut metadatas = BTreeMap::new();
+        for (shard_id, groups) in merged_groups {
+            let mut metadata = ReceiptGroupsQueue::load(&*state_update, shard_id)?
+                .unwrap_or_else(|| ReceiptGroupsQueue::new(shard_id));
+            metadata.clear(state_update)?;
+            for child in [left, right] {
+                if let Some(child_metadata) = child.metadatas.get(&shard_id) {
+                    add_size_checked(
+                        &mut metadata.data.total_size,
+                        ByteSize::b(child_metadata.total_size()),
+                    );
+                    metadata.data.total_gas = metadata
+                        .data
+                        .total_gas
+                        .checked_add(child_metadata.total_gas())
+                        .expect("Overflow! - Total gas doesn't fit into u128!");
+                    metadata.data.total_receipts_num = metadata
+                        .data
+                        .total_receipts_num
+                        .checked_add(child_metadata.total_receipts_num())
+                        .expect("Overflow! - Number of receipts doesn't fit into u64!");
+                }
+            }
+            for group in &groups {
+                metadata.push_back(state_update, group).expect("Integer overflow on push");
+            }
+            metadata.save_data(state_update);
+            metadatas.insert(shard_id, metadata);
+        }
+        Ok(Self { metadatas, groups_config: left.groups_config })
+    }
+
     /// Get metadata for the outgoing buffer to this shard.
     pub fn get_metadata_for_shard(&self, shard_id: &ShardId) -> Option<&ReceiptGroupsQueue> {
         self.metadatas.get(shard_id)
     }
+
+    /// Iterate over the sizes of receipt groups stored in the metadata of all shards.
+    /// Groups are yielded in shard ID order, and in queue order within a shard.
+    pub fn iter_receipt_groups_all_shards<'a>(
+        &'a self,
+        trie: &'a dyn TrieAccess,
+        side_effects: bool,
+    ) -> impl Iterator<Item = (ShardId, Result<ByteSize, StorageError>)> + 'a {
+        self.metadatas.iter().flat_map(move |(shard_id, metadata)| {
+            metadata
+                .iter_receipt_group_sizes(trie, side_effects)
+                .map(move |size_res| (*shard_id, size_res.map(ByteSize::b)))
+        })
+    }
+
+    /// Total size of the metadata stored in the trie, i.e. the Borsh-serialized
+    /// size of the queue data and of all receipt groups of every shard.
+    /// Doesn't include the size of the trie keys.
+    pub fn total_metadata_size_bytes(&self) -> u64 {
+        self.metadatas.values().map(ReceiptGroupsQueue::stored_size_bytes).sum()
+    }
+
+    /// Ratio of the size of the stored metadata to `total_buffered_bytes`,
+    /// the total size of the receipts in the outgoing buffers.
+    /// Returns 0 when there are no buffered receipts.
+    pub fn storage_overhead_ratio(&self, total_buffered_bytes: u64) -> f64 {
+        if total_buffered_bytes == 0 {
+            return 0.0;
+        }
+        self.total_metadata_size_bytes() as f64 / total_buffered_bytes as f64
+    }
 }
 
 /// Information about a group of consecutive receipts stored in the outgoing buffer.
@@ -346,6 +505,17 @@ impl ReceiptGroupsQueue {
         })
     }
 
+    /// Remove all receipt groups from the queue and reset the totals.
+    pub fn clear(&mut self, state_update: &mut TrieUpdate) -> Result<(), StorageError> {
+        let num_groups = self.len();
+        self.pop_n(state_update, num_groups)?;
+        self.data.total_size = 0;
+        self.data.total_gas = 0;
+        self.data.total_receipts_num = 0;
+        self.save_data(state_update);
+        Ok(())
+    }
+
     /// Iterate over the sizes of receipt groups stored in the queue.
     pub fn iter_receipt_group_sizes<'a>(
         &'a self,
@@ -369,6 +539,17 @@ impl ReceiptGroupsQueue {
     pub fn total_receipts_num(&self) -> u64 {
         self.data.total_receipts_num
     }
+
+    /// Borsh-serialized size of the queue data and of all receipt groups
+    /// stored in the trie. All groups have the same serialized size, so the
+    /// groups don't have to be read from the trie.
+    pub fn stored_size_bytes(&self) -> u64 {
+        let data = ReceiptGroupsQueueData::V0(self.data.clone());
+        let data_size = borsh::object_length(&data).expect("borsh serialization can't fail");
+        let group = ReceiptGroup::new(ByteSize::b(0), 0);
+        let group_size = borsh::object_length(&group).expect("borsh serialization can't fail");
+        data_size as u64 + self.data.indices.len() * group_size as u64
+    }
 }
 
 impl TrieQueue for ReceiptGroupsQueue {
@@ -428,15 +609,28 @@ mod tests {
         BandwidthRequest, BandwidthRequestValues, BandwidthSchedulerParams,
     };
     use near_primitives::shard_layout::{ShardLayout, ShardUId};
-    use near_primitives::types::{Gas, ShardId};
+    use near_primitives::types::{Gas, ShardId, StateChangeCause};
+    use near_primitives::version::ProtocolFeature;
     use rand::{Rng, SeedableRng};
     use rand_chacha::ChaCha20Rng;
 
     use crate::test_utils::TestTriesBuilder;
+    use crate::trie::receipts_column_helper::ShardsOutgoingReceiptBuffer;
     use crate::trie::receipts_column_helper::TrieQueue;
-    use crate::{Trie, TrieUpdate};
+    use crate::{ShardTries, Trie, TrieUpdate};
+    use assert_matches::assert_matches;
+    use near_parameters::RuntimeConfig;
+    use near_primitives::errors::StorageError;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::receipt::{
+        DataReceipt, Receipt, ReceiptEnum, ReceiptOrStateStoredReceipt, ReceiptV1,
+        StateStoredReceipt, StateStoredReceiptMetadata, StateStoredReceiptV1,
+    };
+    use std::borrow::Cow;
 
-    use super::{ReceiptGroup, ReceiptGroupV0, ReceiptGroupsConfig, ReceiptGroupsQueue};
+    use super::{
+        OutgoingMetadatas, ReceiptGroup, ReceiptGroupV0, ReceiptGroupsConfig, ReceiptGroupsQueue,
+    };
     use testlib::bandwidth_scheduler::get_random_receipt_size_for_test;
 
     #[test]
@@ -527,6 +721,264 @@ mod tests {
         assert_eq!(group_sizes(&queue, trie_update), Vec::<u64>::new());
     }
 
+    #[test]
+    fn test_iter_receipt_groups_all_shards() {
+        let trie_update = &mut make_trie_update();
+        let config =
+            ReceiptGroupsConfig { size_upper_bound: ByteSize::kb(100), gas_upper_bound: Gas::MAX };
+        let mut metadatas = OutgoingMetadatas::new(config);
+
+        let shard0 = ShardId::new(0);
+        let shard1 = ShardId::new(1);
+        let shard2 = ShardId::new(2);
+
+        // Push in non-sorted shard order, the iterator should yield groups sorted by shard ID.
+        metadatas.update_on_receipt_pushed(shard2, ByteSize::kb(30), 1, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard0, ByteSize::kb(80), 1, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard0, ByteSize::kb(50), 1, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard1, ByteSize::kb(10), 1, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard2, ByteSize::kb(20), 1, trie_update).unwrap();
+
+        let groups: Vec<(ShardId, ByteSize)> = metadatas
+            .iter_receipt_groups_all_shards(trie_update, false)
+            .map(|(shard_id, size_res)| (shard_id, size_res.unwrap()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (shard0, ByteSize::kb(80)),
+                (shard0, ByteSize::kb(50)),
+                (shard1, ByteSize::kb(10)),
+                (shard2, ByteSize::kb(50)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_total_metadata_size_bytes() {
+        let trie_update = &mut make_trie_update();
+        let config =
+            ReceiptGroupsConfig { size_upper_bound: ByteSize::kb(100), gas_upper_bound: Gas::MAX };
+        let mut metadatas = OutgoingMetadatas::new(config);
+        assert_eq!(metadatas.total_metadata_size_bytes(), 0);
+        assert_eq!(metadatas.storage_overhead_ratio(0), 0.0);
+
+        // Queue data: version + indices + total size + total gas + total receipts number.
+        let queue_data_size = 1 + 16 + 8 + 16 + 8;
+        // Receipt group: version + size + gas.
+        let group_size = 1 + 8 + 16;
+
+        // Two groups for shard 0 and one group for shard 1.
+        let shard0 = ShardId::new(0);
+        let shard1 = ShardId::new(1);
+        metadatas.update_on_receipt_pushed(shard0, ByteSize::kb(80), 1, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard0, ByteSize::kb(50), 1, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard1, ByteSize::kb(10), 1, trie_update).unwrap();
+        let expected_size = 2 * queue_data_size + 3 * group_size;
+        assert_eq!(metadatas.total_metadata_size_bytes(), expected_size);
+
+        let total_buffered_bytes = ByteSize::kb(140).as_u64();
+        assert_eq!(
+            metadatas.storage_overhead_ratio(total_buffered_bytes),
+            expected_size as f64 / total_buffered_bytes as f64
+        );
+    }
+
+    #[test]
+    fn test_merge_with_parent() {
+        let left_trie_update = &mut make_trie_update();
+        let right_trie_update = &mut make_trie_update();
+        let parent_trie_update = &mut make_trie_update();
+        let config =
+            ReceiptGroupsConfig { size_upper_bound: ByteSize::kb(100), gas_upper_bound: Gas::MAX };
+
+        let shard0 = ShardId::new(0);
+        let shard1 = ShardId::new(1);
+        let shard2 = ShardId::new(2);
+
+        let mut left = OutgoingMetadatas::new(config);
+        left.update_on_receipt_pushed(shard0, ByteSize::kb(80), 1, left_trie_update).unwrap();
+        left.update_on_receipt_pushed(shard0, ByteSize::kb(50), 2, left_trie_update).unwrap();
+        left.update_on_receipt_pushed(shard1, ByteSize::kb(10), 3, left_trie_update).unwrap();
+
+        let mut right = OutgoingMetadatas::new(config);
+        right.update_on_receipt_pushed(shard0, ByteSize::kb(20), 4, right_trie_update).unwrap();
+        right.update_on_receipt_pushed(shard2, ByteSize::kb(30), 5, right_trie_update).unwrap();
+        right.update_on_receipt_pushed(shard2, ByteSize::kb(40), 6, right_trie_update).unwrap();
+
+        // Stale parent metadata must be replaced by the merged one.
+        let mut stale = OutgoingMetadatas::new(config);
+        stale.update_on_receipt_pushed(shard0, ByteSize::kb(90), 7, parent_trie_update).unwrap();
+
+        let merged = OutgoingMetadatas::merge_with_parent(
+            &left,
+            &*left_trie_update,
+            &right,
+            &*right_trie_update,
+            parent_trie_update,
+        )
+        .unwrap();
+
+        let groups: Vec<(ShardId, ByteSize)> = merged
+            .iter_receipt_groups_all_shards(parent_trie_update, false)
+            .map(|(shard_id, size_res)| (shard_id, size_res.unwrap()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (shard0, ByteSize::kb(80)),
+                (shard0, ByteSize::kb(50)),
+                (shard0, ByteSize::kb(20)),
+                (shard1, ByteSize::kb(10)),
+                (shard2, ByteSize::kb(70)),
+            ]
+        );
+
+        let shard0_metadata = merged.get_metadata_for_shard(&shard0).unwrap();
+        assert_eq!(shard0_metadata.total_size(), ByteSize::kb(150).as_u64());
+        assert_eq!(shard0_metadata.total_gas(), 7);
+        assert_eq!(shard0_metadata.total_receipts_num(), 3);
+
+        // The merged metadata is persisted in the parent state.
+        let reloaded = OutgoingMetadatas::load(
+            &*parent_trie_update,
+            [shard0, shard1, shard2],
+            config,
+            ProtocolFeature::BandwidthScheduler.protocol_version(),
+        )
+        .unwrap();
+        for shard_id in [shard0, shard1, shard2] {
+            let expected = merged.get_metadata_for_shard(&shard_id).unwrap();
+            let actual = reloaded.get_metadata_for_shard(&shard_id).unwrap();
+            assert_eq!(actual.total_size(), expected.total_size());
+            assert_eq!(actual.total_gas(), expected.total_gas());
+            assert_eq!(actual.total_receipts_num(), expected.total_receipts_num());
+            assert_eq!(actual.len(), expected.len());
+        }
+    }
+
+    fn buffered_receipt(
+        size: ByteSize,
+        gas: Gas,
+        tracked: bool,
+    ) -> ReceiptOrStateStoredReceipt<'static> {
+        let receipt = Receipt::V1(ReceiptV1 {
+            predecessor_id: "alice".parse().unwrap(),
+            receiver_id: "bob".parse().unwrap(),
+            receipt_id: CryptoHash::default(),
+            receipt: ReceiptEnum::Data(DataReceipt { data_id: CryptoHash::default(), data: None }),
+            priority: 0,
+        });
+        if !tracked {
+            return ReceiptOrStateStoredReceipt::Receipt(Cow::Owned(receipt));
+        }
+        let metadata =
+            StateStoredReceiptMetadata { congestion_gas: gas, congestion_size: size.as_u64() };
+        ReceiptOrStateStoredReceipt::StateStoredReceipt(StateStoredReceipt::V1(
+            StateStoredReceiptV1 { receipt: Cow::Owned(receipt), metadata },
+        ))
+    }
+
+    /// Stores the given receipts in the outgoing buffers and returns the
+    /// tries together with the shard and the state root holding them.
+    fn store_buffered_receipts(
+        receipts: &[(ShardId, ReceiptOrStateStoredReceipt)],
+    ) -> (ShardTries, ShardUId, CryptoHash) {
+        let shard_layout = ShardLayout::multi_shard(2, 1);
+        let shard_uid = shard_layout.shard_uids().next().unwrap();
+        let tries = TestTriesBuilder::new().with_shard_layout(shard_layout).build();
+        let mut trie_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+        let mut buffers = ShardsOutgoingReceiptBuffer::load(&trie_update).unwrap();
+        for (shard_id, receipt) in receipts {
+            buffers.to_shard(*shard_id).push_back(&mut trie_update, receipt).unwrap();
+        }
+        trie_update.commit(StateChangeCause::InitialState);
+        let trie_changes = trie_update.finalize().unwrap().trie_changes;
+        let mut store_update = tries.store_update();
+        let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+        store_update.commit().unwrap();
+        (tries, shard_uid, root)
+    }
+
+    #[test]
+    fn test_recompute_from_buffer() {
+        let config =
+            ReceiptGroupsConfig { size_upper_bound: ByteSize::kb(100), gas_upper_bound: Gas::MAX };
+        let runtime_config = RuntimeConfig::test();
+        let shard0 = ShardId::new(0);
+        let shard1 = ShardId::new(1);
+
+        // The receipt to shard 1 without metadata was buffered before the
+        // metadata was introduced, it isn't tracked.
+        let (tries, shard_uid, root) = store_buffered_receipts(&[
+            (shard0, buffered_receipt(ByteSize::kb(80), 1, true)),
+            (shard1, buffered_receipt(ByteSize::kb(5), 5, false)),
+            (shard0, buffered_receipt(ByteSize::kb(50), 2, true)),
+            (shard1, buffered_receipt(ByteSize::kb(10), 3, true)),
+            (shard1, buffered_receipt(ByteSize::kb(20), 4, true)),
+        ]);
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+        let buffers = ShardsOutgoingReceiptBuffer::load(&trie).unwrap();
+        let trie_update = &mut tries.new_trie_update(shard_uid, root);
+
+        // Metadata which doesn't match the buffers: a missing receipt to shard 0,
+        // a receipt that isn't in the buffer to shard 1 and metadata for a shard without
+        // a buffer.
+        let shard2 = ShardId::new(2);
+        let mut metadatas = OutgoingMetadatas::new(config);
+        metadatas.update_on_receipt_pushed(shard0, ByteSize::kb(80), 1, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard1, ByteSize::kb(99), 9, trie_update).unwrap();
+        metadatas.update_on_receipt_pushed(shard2, ByteSize::kb(30), 5, trie_update).unwrap();
+
+        metadatas.recompute_from_buffer(&buffers, &trie, &runtime_config, trie_update).unwrap();
+
+        let groups: Vec<(ShardId, ByteSize)> = metadatas
+            .iter_receipt_groups_all_shards(trie_update, false)
+            .map(|(shard_id, size_res)| (shard_id, size_res.unwrap()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (shard0, ByteSize::kb(80)),
+                (shard0, ByteSize::kb(50)),
+                (shard1, ByteSize::kb(30))
+            ]
+        );
+        let metadata0 = metadatas.get_metadata_for_shard(&shard0).unwrap();
+        assert_eq!(metadata0.total_receipts_num(), 2);
+        assert_eq!(metadata0.total_gas(), 3);
+        let metadata1 = metadatas.get_metadata_for_shard(&shard1).unwrap();
+        assert_eq!(metadata1.total_receipts_num(), 2);
+        assert_eq!(metadata1.total_size(), ByteSize::kb(30).as_u64());
+        assert_eq!(metadata1.total_gas(), 7);
+        let metadata2 = metadatas.get_metadata_for_shard(&shard2).unwrap();
+        assert_eq!(metadata2.total_receipts_num(), 0);
+
+        // The recomputed metadata is stored in the trie.
+        let reloaded = ReceiptGroupsQueue::load(&*trie_update, shard1).unwrap().unwrap();
+        assert_eq!(reloaded.total_receipts_num(), 2);
+        assert_eq!(reloaded.total_gas(), 7);
+    }
+
+    /// A receipt without metadata can't follow receipts with metadata in an
+    /// outgoing buffer, recomputing the metadata of such a buffer fails.
+    #[test]
+    fn test_recompute_from_buffer_untracked_after_tracked() {
+        let shard0 = ShardId::new(0);
+        let (tries, shard_uid, root) = store_buffered_receipts(&[
+            (shard0, buffered_receipt(ByteSize::kb(10), 1, true)),
+            (shard0, buffered_receipt(ByteSize::kb(20), 2, false)),
+        ]);
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+        let buffers = ShardsOutgoingReceiptBuffer::load(&trie).unwrap();
+        let trie_update = &mut tries.new_trie_update(shard_uid, root);
+
+        let mut metadatas = OutgoingMetadatas::new(ReceiptGroupsConfig::default_config());
+        let result =
+            metadatas.recompute_from_buffer(&buffers, &trie, &RuntimeConfig::test(), trie_update);
+        assert_matches!(result, Err(StorageError::StorageInconsistentState(_)));
+    }
+
     /// Equivalent to the `ReceiptGroup` struct, used in testing.
     #[derive(Debug, Clone, Copy)]
     struct TestReceiptGroup {
@@ -285,6 +285,11 @@ impl ShardsOutgoingReceiptBuffer {
         Ok(Self { shards_indices })
     }
 
+    /// Same as loading the buffers from a trie without any buffered receipts.
+    pub fn empty() -> Self {
+        Self { shards_indices: BufferedReceiptIndices::default() }
+    }
+
     pub fn to_shard(&mut self, shard_id: ShardId) -> OutgoingReceiptBuffer {
         OutgoingReceiptBuffer { shard_id, parent: self }
     }
@@ -298,6 +303,67 @@ impl ShardsOutgoingReceiptBuffer {
         self.shards_indices.shard_buffers.get(&shard_id).map(TrieQueueIndices::len)
     }
 
+    /// Read-only iterator over the receipts in the buffer to `shard_id`, in
+    /// order. Unlike [`Self::to_shard`], it doesn't need mutable access.
+    pub fn iter_shard<'a>(
+        &self,
+        shard_id: ShardId,
+        trie: &'a dyn TrieAccess,
+        side_effects: bool,
+    ) -> impl Iterator<Item = Result<ReceiptOrStateStoredReceipt<'static>, StorageError>> + 'a {
+        let indices = self.shards_indices.shard_buffers.get(&shard_id).cloned().unwrap_or_default();
+        (indices.first_index..indices.next_available_index).map(move |index| {
+            let key = TrieKey::BufferedReceipt { index, receiving_shard: shard_id };
+            let value = if side_effects { get(trie, &key) } else { get_pure(trie, &key) };
+            value?.ok_or_else(|| {
+                StorageError::StorageInconsistentState(format!(
+                    "Buffered receipt #{index} to shard {shard_id} should be in the state"
+                ))
+            })
+        })
+    }
+
+    /// Removes the buffers to all shards not in `shards_to_keep`, together
+    /// with the receipts stored in them. Used to clean up the buffers to shards
+    /// that no longer exist after resharding.
+    pub fn retain_shards(
+        &mut self,
+        shards_to_keep: &[ShardId],
+        state_update: &mut TrieUpdate,
+    ) -> Result<(), StorageError> {
+        let shards_to_remove: Vec<ShardId> = self
+            .shards()
+            .into_iter()
+            .filter(|shard_id| !shards_to_keep.contains(shard_id))
+            .collect();
+        if shards_to_remove.is_empty() {
+            return Ok(());
+        }
+        for shard_id in shards_to_remove {
+            let mut buffer = self.to_shard(shard_id);
+            let len = buffer.len();
+            buffer.pop_n(state_update, len)?;
+            self.shards_indices.shard_buffers.remove(&shard_id);
+        }
+        self.write_indices(state_update);
+        Ok(())
+    }
+
+    /// Initializes an empty buffer to a new shard, e.g. a child shard created
+    /// by resharding. Does nothing if the buffer already exists.
+    pub fn add_shard(
+        &mut self,
+        new_shard_id: ShardId,
+        state_update: &mut TrieUpdate,
+    ) -> Result<(), StorageError> {
+        if self.shards_indices.shard_buffers.contains_key(&new_shard_id) {
+            return Ok(());
+        }
+        self.shards_indices.shard_buffers.insert(new_shard_id, TrieQueueIndices::default());
+        self.write_indices(state_update);
+        Ok(())
+    }
+
     fn write_indices(&self, state_update: &mut TrieUpdate) {
         set(state_update, TrieKey::BufferedReceiptIndices, &self.shards_indices);
     }
@@ -484,6 +550,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_outgoing_receipt_buffer_retain_and_add_shards() {
+        let mut rng = rand::thread_rng();
+        let input_receipts = gen_receipts(&mut rng, 10);
+        let mut trie = init_state();
+        let mut shard_buffers =
+            ShardsOutgoingReceiptBuffer::load(&trie).expect("creating buffers must not fail");
+        for id in 0..3u32 {
+            let mut buffer = shard_buffers.to_shard(ShardId::from(id));
+            check_push_to_receipt_queue(&input_receipts, &mut trie, &mut buffer);
+        }
+
+        shard_buffers.retain_shards(&[ShardId::new(1)], &mut trie).unwrap();
+        shard_buffers.add_shard(ShardId::new(3), &mut trie).unwrap();
+        // adding an existing buffer must not reset it
+        shard_buffers.add_shard(ShardId::new(1), &mut trie).unwrap();
+
+        // the changes are persisted
+        let mut shard_buffers =
+            ShardsOutgoingReceiptBuffer::load(&trie).expect("creating buffers must not fail");
+        assert_eq!(shard_buffers.shards(), vec![ShardId::new(1), ShardId::new(3)]);
+        assert_eq!(shard_buffers.buffer_len(ShardId::new(3)), Some(0));
+
+        // the receipts of the removed buffers are removed from the state
+        for id in [0u32, 2] {
+            for index in 0..input_receipts.len() as u64 {
+                let key = TrieKey::BufferedReceipt { index, receiving_shard: ShardId::from(id) };
+                let receipt: Option<ReceiptOrStateStoredReceipt> = get(&trie, &key).unwrap();
+                assert!(receipt.is_none(), "receipt #{index} to shard {id} was not removed");
+            }
+        }
+
+        // the kept buffer is intact
+        let mut buffer = shard_buffers.to_shard(ShardId::new(1));
+        check_receipt_queue_contains_receipts(&input_receipts, &mut trie, &mut buffer);
+    }
+
     /// Add given receipts to the  receipts queue, then use `ReceiptIterator` to
     /// read them back and assert it has the same receipts in the same order.
     #[track_caller]
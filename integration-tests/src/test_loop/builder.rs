@@ -1,12 +1,14 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use near_async::futures::FutureSpawner;
 use near_async::messaging::{noop, IntoMultiSender, IntoSender, LateBoundSender};
+use near_async::test_loop::data::{TestLoopData, TestLoopDataHandle};
 use near_async::test_loop::sender::TestLoopSender;
 use near_async::test_loop::TestLoopV2;
 use near_async::time::{Clock, Duration};
-use near_chain::runtime::NightshadeRuntime;
+use near_chain::runtime::{ApplyResultHook, NightshadeRuntime};
 use near_chain::state_snapshot_actor::{
     get_delete_snapshot_callback, get_make_snapshot_callback, SnapshotCallbacks, StateSnapshotActor,
 };
@@ -21,18 +23,23 @@ use near_client::client_actor::ClientActorInner;
 use near_client::gc_actor::GCActor;
 use near_client::sync_jobs_actor::SyncJobsActor;
 use near_client::{Client, PartialWitnessActor, ViewClientActorInner};
+use near_crypto::KeyType;
 use near_epoch_manager::shard_tracker::{ShardTracker, TrackedConfig};
 use near_epoch_manager::{EpochManager, EpochManagerAdapter};
 use near_network::test_loop::{TestLoopNetworkSharedState, TestLoopPeerManagerActor};
 use near_parameters::RuntimeConfigStore;
-use near_primitives::epoch_manager::EpochConfigStore;
+use near_primitives::epoch_info::RngSeed;
+use near_primitives::epoch_manager::{EpochConfig, EpochConfigStore};
 use near_primitives::network::PeerId;
 use near_primitives::sharding::ShardChunkHeader;
+use near_primitives::stateless_validation::ChunkProductionKey;
 use near_primitives::test_utils::create_test_signer;
-use near_primitives::types::{AccountId, ShardId};
+use near_primitives::types::{AccountId, BlockHeight, ProtocolVersion, ShardId};
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_store::adapter::StoreAdapter;
 use near_store::config::StateSnapshotType;
 use near_store::genesis::initialize_genesis_state;
+use near_store::genesis::save_genesis_state_dump;
 use near_store::test_utils::{create_test_split_store, create_test_store};
 use near_store::{ShardUId, Store, StoreConfig, TrieConfig};
 use near_vm_runner::logic::ProtocolVersion;
@@ -40,7 +47,10 @@ use near_vm_runner::{ContractRuntimeCache, FilesystemContractRuntimeCache};
 use nearcore::state_sync::StateSyncDumper;
 use tempfile::TempDir;
 
-use super::env::{ClientToShardsManagerSender, TestData, TestLoopChunksStorage, TestLoopEnv};
+use super::env::{
+    ChunkValidationHook, ClientToPartialWitnessSender, ClientToShardsManagerSender,
+    ShardAssignmentTrace, TestData, TestLoopChunksStorage, TestLoopEnv,
+};
 use super::utils::network::{chunk_endorsement_dropper, chunk_endorsement_dropper_by_hash};
 use near_chain::resharding::resharding_actor::ReshardingActor;
 
@@ -61,10 +71,75 @@ enum DropConditionKind {
     ChunksProducedByHeight(HashMap<ShardId, Vec<bool>>),
 }
 
+/// Stage of block production at which a hook registered with
+/// `TestLoopBuilder::with_block_production_hook` is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BlockProductionStage {
+    /// A new chunk was produced by any of the clients.
+    AfterChunkProduced,
+    /// The head of any of the clients moved to a new height.
+    AfterBlockProduced,
+    /// The final head of any of the clients moved to a new height.
+    AfterBlockFinalized,
+}
+
+type BlockProductionHook = Box<dyn FnMut(&TestLoopData)>;
+
+/// Calls the block production hooks when the corresponding stage is observed.
+/// The progress is checked before every test loop event, so a hook sees the
+/// state right after the event which made the progress.
+struct BlockProductionHooksDispatcher {
+    hooks: Vec<(BlockProductionStage, BlockProductionHook)>,
+    client_handles: Vec<TestLoopDataHandle<ClientActorInner>>,
+    chunks_storage: Arc<Mutex<TestLoopChunksStorage>>,
+    num_chunks: usize,
+    head_height: BlockHeight,
+    final_head_height: BlockHeight,
+}
+
+impl BlockProductionHooksDispatcher {
+    fn on_event(&mut self, data: &TestLoopData) {
+        let num_chunks = self.chunks_storage.lock().unwrap().num_chunks();
+        let mut head_height = self.head_height;
+        let mut final_head_height = self.final_head_height;
+        for handle in &self.client_handles {
+            let chain = &data.get(handle).client.chain;
+            head_height = head_height.max(chain.head().map_or(0, |tip| tip.height));
+            final_head_height =
+                final_head_height.max(chain.final_head().map_or(0, |tip| tip.height));
+        }
+
+        let mut stages = vec![];
+        if num_chunks > self.num_chunks {
+            stages.push(BlockProductionStage::AfterChunkProduced);
+        }
+        if head_height > self.head_height {
+            stages.push(BlockProductionStage::AfterBlockProduced);
+        }
+        if final_head_height > self.final_head_height {
+            stages.push(BlockProductionStage::AfterBlockFinalized);
+        }
+        self.num_chunks = num_chunks;
+        self.head_height = head_height;
+        self.final_head_height = final_head_height;
+
+        for stage in stages {
+            for (hook_stage, hook) in &mut self.hooks {
+                if *hook_stage == stage {
+                    hook(data);
+                }
+            }
+        }
+    }
+}
+
 pub(crate) struct TestLoopBuilder {
     test_loop: TestLoopV2,
     genesis: Option<Genesis>,
     epoch_config_store: Option<EpochConfigStore>,
+    /// Epoch configs to build the epoch config store from, see
+    /// `with_epoch_config_per_protocol_version`.
+    epoch_configs_per_protocol_version: BTreeMap<ProtocolVersion, Arc<EpochConfig>>,
     clients: Vec<AccountId>,
     /// Overrides the stores; rather than constructing fresh new stores, use
     /// the provided ones (to test with existing data).
@@ -93,6 +168,20 @@ pub(crate) struct TestLoopBuilder {
     track_all_shards: bool,
     /// Whether to load mem tries for the tracked shards.
     load_mem_tries_for_tracked_shards: bool,
+    /// Seed for all the randomness used by the clients in the test loop.
+    random_seed: u64,
+    /// Hooks to call at the given stages of block production.
+    block_production_hooks: Vec<(BlockProductionStage, BlockProductionHook)>,
+    /// Directory to export the genesis state to, or import it from if it was
+    /// exported by a previous run. See `with_genesis_snapshot_export`.
+    genesis_snapshot_dir: Option<PathBuf>,
+    /// Hook transforming every state witness before it is validated.
+    chunk_validation_hook: Option<Arc<Mutex<ChunkValidationHook>>>,
+    /// Hook called with the result of every chunk applied by any of the clients.
+    apply_result_hook: Option<Arc<Mutex<ApplyResultHook>>>,
+    /// Chunk producers of every shard at every block height, recorded if
+    /// enabled with `with_shard_assignment_trace`.
+    shard_assignment_trace: Option<Arc<Mutex<ShardAssignmentTrace>>>,
 }
 
 /// Checks whether chunk is validated by the given account.
@@ -280,6 +369,7 @@ impl TestLoopBuilder {
             test_loop: TestLoopV2::new(),
             genesis: None,
             epoch_config_store: None,
+            epoch_configs_per_protocol_version: BTreeMap::new(),
             clients: vec![],
             stores_override: None,
             test_loop_data_dir: None,
@@ -292,6 +382,12 @@ impl TestLoopBuilder {
             warmup: true,
             track_all_shards: false,
             load_mem_tries_for_tracked_shards: true,
+            random_seed: 0,
+            block_production_hooks: vec![],
+            genesis_snapshot_dir: None,
+            chunk_validation_hook: None,
+            apply_result_hook: None,
+            shard_assignment_trace: None,
         }
     }
 
@@ -311,6 +407,24 @@ impl TestLoopBuilder {
         self
     }
 
+    /// Use `epoch_config` starting from `protocol_version`. Can be called once
+    /// per protocol version the chain goes through, the epoch config store is
+    /// built from all of them. Can't be combined with `epoch_config_store`.
+    pub(crate) fn with_epoch_config_per_protocol_version(
+        mut self,
+        protocol_version: ProtocolVersion,
+        epoch_config: EpochConfig,
+    ) -> Self {
+        let previous = self
+            .epoch_configs_per_protocol_version
+            .insert(protocol_version, Arc::new(epoch_config));
+        assert!(
+            previous.is_none(),
+            "epoch config set twice for protocol version {protocol_version}"
+        );
+        self
+    }
+
     pub(crate) fn runtime_config_store(mut self, runtime_config_store: RuntimeConfigStore) -> Self {
         self.runtime_config_store = Some(runtime_config_store);
         self
@@ -413,6 +527,67 @@ impl TestLoopBuilder {
         self
     }
 
+    /// Sets the seed used for all the randomness in the test loop clients.
+    /// Two runs with the same seed and the same setup produce the same chain.
+    /// The seed is logged when the test loop is built, so that a failing run
+    /// can be reproduced.
+    ///
+    /// The epoch randomness, e.g. for shuffling the shard assignment of chunk
+    /// producers, comes from the VRF outputs of the block producers. A
+    /// non-zero seed is therefore also used to derive the validator keys, and
+    /// the public keys of the genesis validators are replaced accordingly.
+    pub fn with_random_seed(mut self, seed: u64) -> Self {
+        self.random_seed = seed;
+        self
+    }
+
+    /// Registers a hook to inspect the test loop data when block production
+    /// reaches the given stage on any of the clients. Hooks are dispatched
+    /// through the every event callback of the test loop, so they can't be
+    /// combined with `TestLoopV2::set_every_event_callback`.
+    pub fn with_block_production_hook(
+        mut self,
+        at_stage: BlockProductionStage,
+        hook: Box<dyn FnMut(&TestLoopData)>,
+    ) -> Self {
+        self.block_production_hooks.push((at_stage, hook));
+        self
+    }
+
+    /// Registers a hook that transforms every state witness produced by any of
+    /// the clients before it is distributed to the chunk validators. Without
+    /// a hook the witnesses are validated as produced.
+    pub fn with_chunk_validation_hook(mut self, hook: ChunkValidationHook) -> Self {
+        self.chunk_validation_hook = Some(Arc::new(Mutex::new(hook)));
+        self
+    }
+
+    /// Registers a hook called with the shard id, the block height and the
+    /// `ApplyResult` of every chunk applied by any of the clients, after it is
+    /// applied. The same chunk is seen once per client applying it, including
+    /// chunk validators and clients applying it again on a fork.
+    pub fn with_apply_result_hook(mut self, hook: ApplyResultHook) -> Self {
+        self.apply_result_hook = Some(Arc::new(Mutex::new(hook)));
+        self
+    }
+
+    /// Records the chunk producer assigned to every shard at every block
+    /// height, as seen by the client with the highest head. The trace is
+    /// available through `TestLoopEnv::shard_assignment_trace`.
+    pub fn with_shard_assignment_trace(mut self) -> Self {
+        self.shard_assignment_trace = Some(Default::default());
+        self
+    }
+
+    /// Exports the genesis state of the clients to `path` on the first run and
+    /// loads it from there on later runs with the same seed and genesis,
+    /// instead of computing it again from the genesis records. Useful for tests
+    /// with expensive genesis states, e.g. with a lot of accounts.
+    pub fn with_genesis_snapshot_export(mut self, path: PathBuf) -> Self {
+        self.genesis_snapshot_dir = Some(path);
+        self
+    }
+
     /// Overrides the tempdir (which contains state dump, etc.) instead
     /// of creating a new one.
     pub fn test_loop_data_dir(mut self, dir: TempDir) -> Self {
@@ -422,7 +597,7 @@ impl TestLoopBuilder {
 
     /// Build the test loop environment.
     pub(crate) fn build(self) -> TestLoopEnv {
-        self.ensure_genesis().ensure_clients().build_impl()
+        self.ensure_genesis().ensure_epoch_config_store().ensure_clients().build_impl()
     }
 
     fn ensure_genesis(self) -> Self {
@@ -430,6 +605,18 @@ impl TestLoopBuilder {
         self
     }
 
+    fn ensure_epoch_config_store(mut self) -> Self {
+        if !self.epoch_configs_per_protocol_version.is_empty() {
+            assert!(
+                self.epoch_config_store.is_none(),
+                "Epoch config store and epoch configs per protocol version can't both be provided"
+            );
+            let epoch_configs = std::mem::take(&mut self.epoch_configs_per_protocol_version);
+            self.epoch_config_store = Some(EpochConfigStore::test(epoch_configs));
+        }
+        self
+    }
+
     fn ensure_clients(self) -> Self {
         assert!(!self.clients.is_empty(), "Clients must be provided to the test loop");
         assert!(
@@ -440,6 +627,8 @@ impl TestLoopBuilder {
     }
 
     fn build_impl(mut self) -> TestLoopEnv {
+        tracing::info!(target: "test", random_seed = self.random_seed, "building test loop");
+        self.apply_random_seed_to_genesis_validators();
         let mut datas = Vec::new();
         let mut network_adapters = Vec::new();
         let mut epoch_manager_adapters = Vec::new();
@@ -455,6 +644,8 @@ impl TestLoopBuilder {
             epoch_manager_adapters.push(epoch_manager_adapter);
         }
         self.setup_network(&datas, &network_adapters, &epoch_manager_adapters);
+        self.setup_shard_assignment_trace(&datas);
+        self.setup_block_production_hooks(&datas);
 
         let env = TestLoopEnv { test_loop: self.test_loop, datas, tempdir };
         if self.warmup {
@@ -464,6 +655,112 @@ impl TestLoopBuilder {
         }
     }
 
+    fn setup_shard_assignment_trace(&mut self, datas: &[TestData]) {
+        let Some(trace) = self.shard_assignment_trace.clone() else {
+            return;
+        };
+        let client_handles: Vec<_> =
+            datas.iter().map(|data| data.client_sender.actor_handle()).collect();
+        let hook = move |data: &TestLoopData| {
+            let client = client_handles
+                .iter()
+                .map(|handle| &data.get(handle).client)
+                .max_by_key(|client| client.chain.head().map_or(0, |tip| tip.height))
+                .unwrap();
+            let tip = client.chain.head().unwrap();
+            let shard_layout = client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            let mut trace = trace.lock().unwrap();
+            for shard_id in shard_layout.shard_ids() {
+                let key = ChunkProductionKey {
+                    shard_id,
+                    epoch_id: tip.epoch_id,
+                    height_created: tip.height,
+                };
+                let chunk_producer =
+                    client.epoch_manager.get_chunk_producer_info(&key).unwrap().take_account_id();
+                trace.push((tip.height, shard_id, chunk_producer));
+            }
+        };
+        self.block_production_hooks
+            .push((BlockProductionStage::AfterBlockProduced, Box::new(hook)));
+    }
+
+    fn setup_block_production_hooks(&mut self, datas: &[TestData]) {
+        if self.block_production_hooks.is_empty() {
+            return;
+        }
+        let mut dispatcher = BlockProductionHooksDispatcher {
+            hooks: std::mem::take(&mut self.block_production_hooks),
+            client_handles: datas.iter().map(|data| data.client_sender.actor_handle()).collect(),
+            chunks_storage: self.chunks_storage.clone(),
+            num_chunks: 0,
+            head_height: 0,
+            final_head_height: 0,
+        };
+        self.test_loop.set_every_event_callback(move |data| dispatcher.on_event(data));
+    }
+
+    /// Replaces the public keys of the genesis validators with the keys derived
+    /// from the random seed, see [`Self::validator_signer`].
+    fn apply_random_seed_to_genesis_validators(&mut self) {
+        if self.random_seed == 0 {
+            return;
+        }
+        let genesis = self.genesis.as_ref().unwrap();
+        let public_keys = genesis
+            .config
+            .validators
+            .iter()
+            .map(|validator| self.validator_signer(&validator.account_id).public_key())
+            .collect::<Vec<_>>();
+        let genesis = self.genesis.as_mut().unwrap();
+        for (validator, public_key) in genesis.config.validators.iter_mut().zip(public_keys) {
+            validator.public_key = public_key;
+        }
+    }
+
+    /// The validator signer of `account_id`. With the default seed of 0, this
+    /// is the usual test signer whose key matches the genesis built by
+    /// `TestGenesisBuilder`. Other seeds derive a different key per seed.
+    fn validator_signer(&self, account_id: &AccountId) -> ValidatorSigner {
+        if self.random_seed == 0 {
+            return create_test_signer(account_id.as_str());
+        }
+        InMemoryValidatorSigner::from_seed(
+            account_id.clone(),
+            KeyType::ED25519,
+            &format!("{}.{}", account_id, self.random_seed),
+        )
+        .into()
+    }
+
+    /// The seed is spread over the first bytes of the rng seed, so that the
+    /// default seed of 0 matches the all-zeroes seed used before.
+    fn rng_seed(&self) -> RngSeed {
+        let mut rng_seed = [0; 32];
+        rng_seed[..8].copy_from_slice(&self.random_seed.to_le_bytes());
+        rng_seed
+    }
+
+    /// Initializes the genesis state from the snapshot if there is one for the
+    /// current seed and genesis, and exports a snapshot otherwise.
+    fn initialize_genesis_state(&self, store: &Store, genesis: &Genesis) {
+        let Some(genesis_snapshot_dir) = &self.genesis_snapshot_dir else {
+            initialize_genesis_state(store.clone(), genesis, None);
+            return;
+        };
+        let snapshot_dir =
+            genesis_snapshot_dir.join(format!("{}-{}", self.random_seed, genesis.json_hash()));
+        let has_snapshot = snapshot_dir.exists();
+        initialize_genesis_state(store.clone(), genesis, Some(&snapshot_dir));
+        if !has_snapshot {
+            tracing::info!(target: "test", ?snapshot_dir, "exporting genesis snapshot");
+            std::fs::create_dir_all(&snapshot_dir).expect("Unable to create genesis snapshot dir");
+            save_genesis_state_dump(store, &snapshot_dir)
+                .expect("Unable to export genesis snapshot");
+        }
+    }
+
     fn setup_client(
         &mut self,
         idx: usize,
@@ -549,7 +846,7 @@ impl TestLoopBuilder {
                 let hot_store = create_test_store();
                 (hot_store, None)
             };
-        initialize_genesis_state(store.clone(), &genesis, None);
+        self.initialize_genesis_state(&store, &genesis);
 
         let sync_jobs_actor = SyncJobsActor::new(client_adapter.as_multi_sender());
         let chain_genesis = ChainGenesis::new(&genesis.config);
@@ -572,6 +869,7 @@ impl TestLoopBuilder {
             self.runtime_config_store.clone(),
             TrieConfig::from_store_config(&store_config),
             StateSnapshotType::EveryEpoch,
+            self.apply_result_hook.clone(),
         );
 
         let state_snapshot = StateSnapshotActor::new(
@@ -591,7 +889,7 @@ impl TestLoopBuilder {
             SnapshotCallbacks { make_snapshot_callback, delete_snapshot_callback };
 
         let validator_signer = MutableConfigValue::new(
-            Some(Arc::new(create_test_signer(self.clients[idx].as_str()))),
+            Some(Arc::new(self.validator_signer(&self.clients[idx]))),
             "validator_signer",
         );
 
@@ -601,6 +899,11 @@ impl TestLoopBuilder {
             chunks_storage: self.chunks_storage.clone(),
         });
 
+        let client_to_partial_witness_sender = Arc::new(ClientToPartialWitnessSender {
+            sender: partial_witness_adapter.clone(),
+            chunk_validation_hook: self.chunk_validation_hook.clone(),
+        });
+
         // Generate a PeerId. It doesn't matter what this is. We're just making it based on
         // the account ID, so that it is stable across multiple runs in the same test.
         let peer_id = PeerId::new(create_test_signer(self.clients[idx].as_str()).public_key());
@@ -616,10 +919,10 @@ impl TestLoopBuilder {
             client_to_shards_manager_sender.as_sender(),
             validator_signer.clone(),
             true,
-            [0; 32],
+            self.rng_seed(),
             Some(snapshot_callbacks),
             Arc::new(self.test_loop.async_computation_spawner(|_| Duration::milliseconds(80))),
-            partial_witness_adapter.as_multi_sender(),
+            client_to_partial_witness_sender.as_multi_sender(),
             resharding_sender.as_multi_sender(),
             Arc::new(self.test_loop.future_spawner()),
             client_adapter.as_multi_sender(),
@@ -649,6 +952,7 @@ impl TestLoopBuilder {
                     self.runtime_config_store.clone(),
                     TrieConfig::from_store_config(&store_config),
                     StateSnapshotType::EveryEpoch,
+                    None,
                 );
                 (view_epoch_manager, view_shard_tracker, view_runtime_adapter)
             } else {
@@ -771,6 +1075,7 @@ impl TestLoopBuilder {
             shards_manager_sender,
             partial_witness_sender,
             state_sync_dumper_handle,
+            shard_assignment_trace: self.shard_assignment_trace.clone(),
         };
         (data, network_adapter, epoch_manager)
     }
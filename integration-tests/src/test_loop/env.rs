@@ -6,14 +6,15 @@ use near_async::time::Duration;
 use near_chunks::adapter::ShardsManagerRequestFromClient;
 use near_chunks::shards_manager_actor::ShardsManagerActor;
 use near_client::client_actor::ClientActorInner;
-use near_client::{PartialWitnessActor, ViewClientActorInner};
+use near_client::{DistributeStateWitnessRequest, PartialWitnessActor, ViewClientActorInner};
 use near_jsonrpc::ViewClientSenderForRpc;
 use near_network::shards_manager::ShardsManagerRequestFromNetwork;
 use near_network::state_witness::PartialWitnessSenderForNetwork;
 use near_network::test_loop::{ClientSenderForTestLoopNetwork, ViewClientSenderForTestLoopNetwork};
 use near_primitives::network::PeerId;
 use near_primitives::sharding::{ChunkHash, ShardChunkHeader};
-use near_primitives::types::AccountId;
+use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
+use near_primitives::types::{AccountId, ShardId};
 use near_primitives_core::types::BlockHeight;
 use nearcore::state_sync::StateSyncDumper;
 use std::collections::HashMap;
@@ -62,6 +63,16 @@ impl TestLoopEnv {
         Self { test_loop, datas, tempdir }
     }
 
+    /// Chunk producer of every shard at every block height, in the order the
+    /// blocks were produced. Requires `TestLoopBuilder::with_shard_assignment_trace`.
+    pub fn shard_assignment_trace(&self) -> ShardAssignmentTrace {
+        let trace = self.datas[0]
+            .shard_assignment_trace
+            .as_ref()
+            .expect("shard assignment trace must be enabled in the test loop builder");
+        trace.lock().unwrap().clone()
+    }
+
     /// Used to finish off remaining events that are still in the loop. This can be necessary if the
     /// destructor of some components wait for certain condition to become true. Otherwise, the
     /// destructors may end up waiting forever. This also helps avoid a panic when destructing
@@ -106,6 +117,11 @@ impl TestLoopChunksStorage {
         self.storage.get(chunk_hash)
     }
 
+    /// Number of distinct chunks produced so far.
+    pub fn num_chunks(&self) -> usize {
+        self.storage.len()
+    }
+
     /// If chunk height is too low, don't drop chunk, allow the chain to warm
     /// up.
     pub fn can_drop_chunk(&self, chunk_header: &ShardChunkHeader) -> bool {
@@ -137,6 +153,32 @@ impl CanSend<ShardsManagerRequestFromClient> for ClientToShardsManagerSender {
     }
 }
 
+/// Transforms a state witness after it is produced and before it is
+/// distributed to the chunk validators.
+pub type ChunkValidationHook = Box<dyn FnMut(&ChunkStateWitness) -> ChunkStateWitness + Send>;
+
+/// Custom implementation of `Sender` for messages from `Client` to
+/// `PartialWitnessActor` that allows to replace every produced state witness
+/// by the output of a hook. Without a hook the witnesses are sent unchanged.
+pub struct ClientToPartialWitnessSender {
+    pub sender: Arc<LateBoundSender<TestLoopSender<PartialWitnessActor>>>,
+    /// Hook shared between all test loop nodes.
+    pub chunk_validation_hook: Option<Arc<Mutex<ChunkValidationHook>>>,
+}
+
+impl CanSend<DistributeStateWitnessRequest> for ClientToPartialWitnessSender {
+    fn send(&self, mut message: DistributeStateWitnessRequest) {
+        if let Some(hook) = &self.chunk_validation_hook {
+            message.state_witness = (hook.lock().unwrap())(&message.state_witness);
+        }
+        self.sender.send(message);
+    }
+}
+
+/// `(height, shard_id, chunk_producer)` entries recorded when
+/// `TestLoopBuilder::with_shard_assignment_trace` is enabled.
+pub type ShardAssignmentTrace = Vec<(BlockHeight, ShardId, AccountId)>;
+
 #[derive(Clone)]
 pub struct TestData {
     pub account_id: AccountId,
@@ -146,6 +188,8 @@ pub struct TestData {
     pub shards_manager_sender: TestLoopSender<ShardsManagerActor>,
     pub partial_witness_sender: TestLoopSender<PartialWitnessActor>,
     pub state_sync_dumper_handle: TestLoopDataHandle<StateSyncDumper>,
+    /// Shard assignment trace shared between all test loop nodes, if enabled.
+    pub shard_assignment_trace: Option<Arc<Mutex<ShardAssignmentTrace>>>,
 }
 
 impl From<&TestData> for AccountId {
@@ -100,11 +100,12 @@ fn slow_test_bandwidth_scheduler_request_generation() {
         genesis_builder.add_user_account_simple(account.clone(), 100_000_0000 * ONE_NEAR);
     }
 
-    let (genesis, epoch_config_store) = genesis_builder.build();
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
 
     let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
         .clients(vec![node_account])
         .build();
 
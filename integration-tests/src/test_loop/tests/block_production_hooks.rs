@@ -0,0 +1,98 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use itertools::Itertools;
+use near_async::test_loop::data::TestLoopDataHandle;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_client::client_actor::ClientActorInner;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::{AccountId, BlockHeight};
+
+use crate::test_loop::builder::{BlockProductionStage, TestLoopBuilder};
+use crate::test_loop::utils::ONE_NEAR;
+
+/// Checks that the block production hooks are called at every stage and that
+/// they observe the heads and final heads moving forward.
+#[test]
+fn test_block_production_hooks() {
+    init_test_logger();
+
+    let client_handle: Rc<RefCell<Option<TestLoopDataHandle<ClientActorInner>>>> =
+        Default::default();
+    let num_chunks_produced = Rc::new(Cell::new(0));
+    let head_heights: Rc<RefCell<Vec<BlockHeight>>> = Default::default();
+    let final_head_heights: Rc<RefCell<Vec<BlockHeight>>> = Default::default();
+
+    let builder = TestLoopBuilder::new()
+        .with_block_production_hook(BlockProductionStage::AfterChunkProduced, {
+            let num_chunks_produced = num_chunks_produced.clone();
+            Box::new(move |_| num_chunks_produced.set(num_chunks_produced.get() + 1))
+        })
+        .with_block_production_hook(BlockProductionStage::AfterBlockProduced, {
+            let client_handle = client_handle.clone();
+            let head_heights = head_heights.clone();
+            Box::new(move |data| {
+                // The hooks are also called during warmup, before the handle is known.
+                if let Some(handle) = client_handle.borrow().as_ref() {
+                    let head = data.get(handle).client.chain.head().unwrap();
+                    head_heights.borrow_mut().push(head.height);
+                }
+            })
+        })
+        .with_block_production_hook(BlockProductionStage::AfterBlockFinalized, {
+            let client_handle = client_handle.clone();
+            let final_head_heights = final_head_heights.clone();
+            Box::new(move |data| {
+                if let Some(handle) = client_handle.borrow().as_ref() {
+                    let final_head = data.get(handle).client.chain.final_head().unwrap();
+                    final_head_heights.borrow_mut().push(final_head.height);
+                }
+            })
+        });
+
+    let initial_balance = 10000 * ONE_NEAR;
+    let accounts =
+        (0..4).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let clients = accounts.iter().take(2).cloned().collect_vec();
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .gas_prices_free()
+        .gas_limit_one_petagas()
+        .shard_layout_simple_v1(&["account2"])
+        .transaction_validity_period(1000)
+        .epoch_length(5)
+        .validators_desired_roles(&clients.iter().map(|t| t.as_str()).collect_vec(), &[]);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), initial_balance);
+    }
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+
+    let mut env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(clients)
+        .build();
+    let handle = env.datas[0].client_sender.actor_handle();
+    *client_handle.borrow_mut() = Some(handle.clone());
+
+    env.test_loop.run_until(
+        |test_loop_data| test_loop_data.get(&handle).client.chain.head().unwrap().height > 10020,
+        Duration::seconds(10),
+    );
+
+    assert!(num_chunks_produced.get() > 0);
+    let head_heights = head_heights.take();
+    let final_head_heights = final_head_heights.take();
+    tracing::info!(target: "test", ?head_heights, ?final_head_heights, "observed heights");
+    assert!(!head_heights.is_empty());
+    assert!(!final_head_heights.is_empty());
+    assert!(final_head_heights.last() < head_heights.last());
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
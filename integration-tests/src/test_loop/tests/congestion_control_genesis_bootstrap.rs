@@ -41,10 +41,11 @@ fn test_congestion_control_genesis_bootstrap() {
         genesis_builder.add_user_account_simple(clients[i].clone(), initial_balance);
     }
 
-    let (genesis, epoch_config_store) = genesis_builder.build();
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
     let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
         .clients(clients.clone())
         .build();
 
@@ -0,0 +1,139 @@
+use itertools::Itertools;
+use near_async::test_loop::data::TestLoopData;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_client::Client;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::AccountId;
+use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
+use near_store::ShardUId;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::transactions::{
+    call_contract, check_txs, deploy_contract, make_accounts,
+};
+use crate::test_loop::utils::{ONE_NEAR, TGAS};
+
+/// Upgrades the network from the original congestion control, which buffers
+/// receipts as they are, to `StateStoredReceipt`, which buffers them together
+/// with their congestion metadata, while the outgoing buffer of a shard is
+/// full. Checks that the receipts buffered before the upgrade are all forwarded
+/// and executed after it.
+#[cfg_attr(not(feature = "test_features"), ignore)]
+#[test]
+fn test_congestion_control_v2_to_v3_upgrade() {
+    init_test_logger();
+
+    let old_protocol = ProtocolFeature::StateStoredReceipt.protocol_version() - 1;
+    assert!(ProtocolFeature::CongestionControl.enabled(old_protocol));
+    let epoch_length = 10;
+    let accounts = make_accounts(8);
+    let clients = accounts.iter().take(4).cloned().collect_vec();
+    let rpc_id = clients[0].clone();
+    // The contract is on the first shard and is called from the last one.
+    let contract_id = accounts[0].clone();
+    let sender_ids = accounts[5..].to_vec();
+
+    let builder = TestLoopBuilder::new();
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version(old_protocol)
+        .genesis_height(10000)
+        .epoch_length(epoch_length)
+        .shard_layout_simple_v1(&["account3", "account5"])
+        .gas_prices_free()
+        .gas_limit_one_petagas()
+        .transaction_validity_period(1000)
+        .validators_desired_roles(
+            &clients.iter().map(|account| account.as_str()).collect_vec(),
+            &[],
+        )
+        // Let a single call through per chunk, so that the calls made at every
+        // height fill the outgoing buffer of the senders' shard.
+        .congestion_control_max_outgoing_gas(500 * TGAS);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), 10000 * ONE_NEAR);
+    }
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+    let sender_shard_uid = ShardUId::from_shard_id_and_layout(
+        genesis.config.shard_layout.account_id_to_shard_id(&sender_ids[0]),
+        &genesis.config.shard_layout,
+    );
+
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(clients)
+        .track_all_shards()
+        .build();
+    let client_handle = node_datas[0].client_sender.actor_handle();
+
+    let code = near_test_contracts::rs_contract().to_vec();
+    let tx = deploy_contract(&mut test_loop, &node_datas, &rpc_id, &contract_id, code, 1);
+    test_loop.run_for(Duration::seconds(5));
+    check_txs(&test_loop, &node_datas, &rpc_id, &[tx]);
+
+    // Keep calling the contract until a few blocks after the upgrade.
+    let mut txs = vec![];
+    let mut nonce = 10;
+    let mut buffered_gas_before_upgrade = 0;
+    let mut heights_after_upgrade = 0;
+    while heights_after_upgrade < 5 {
+        for sender_id in &sender_ids {
+            nonce += 1;
+            txs.push(call_contract(
+                &mut test_loop,
+                &node_datas,
+                &rpc_id,
+                sender_id,
+                &contract_id,
+                "burn_gas_raw".to_owned(),
+                (250 * TGAS).to_le_bytes().to_vec(),
+                nonce,
+            ));
+        }
+        test_loop.run_for(Duration::seconds(1));
+
+        let client = &test_loop.data.get(&client_handle).client;
+        let tip = client.chain.head().unwrap();
+        let protocol_version =
+            client.epoch_manager.get_epoch_protocol_version(&tip.epoch_id).unwrap();
+        if protocol_version == old_protocol {
+            buffered_gas_before_upgrade =
+                buffered_receipts_gas(client, sender_shard_uid, &tip.last_block_hash);
+            assert!(tip.height < 10000 + 6 * epoch_length, "the protocol upgrade didn't happen");
+        } else {
+            assert_eq!(protocol_version, PROTOCOL_VERSION);
+            assert!(ProtocolFeature::StateStoredReceipt.enabled(protocol_version));
+            heights_after_upgrade += 1;
+        }
+    }
+    assert_ne!(buffered_gas_before_upgrade, 0, "no receipt was buffered before the upgrade");
+
+    // All the buffered receipts get forwarded with the new logic.
+    test_loop.run_until(
+        |test_loop_data: &mut TestLoopData| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+            buffered_receipts_gas(client, sender_shard_uid, &tip.last_block_hash) == 0
+        },
+        Duration::seconds(200),
+    );
+    // Give the forwarded receipts time to execute.
+    test_loop.run_for(Duration::seconds(10));
+    check_txs(&test_loop, &node_datas, &rpc_id, &txs);
+
+    TestLoopEnv { test_loop, datas: node_datas, tempdir }
+        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Gas of the receipts in the outgoing buffers of the shard after applying the
+/// chunk of the given block.
+fn buffered_receipts_gas(client: &Client, shard_uid: ShardUId, block_hash: &CryptoHash) -> u128 {
+    let chunk_extra = client.chain.get_chunk_extra(block_hash, &shard_uid).unwrap();
+    chunk_extra.congestion_info().unwrap().buffered_receipts_gas()
+}
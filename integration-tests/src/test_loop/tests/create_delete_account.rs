@@ -82,10 +82,14 @@ fn test_create_delete_account() {
     for account in &accounts {
         genesis_builder.add_user_account_simple(account.clone(), initial_balance);
     }
-    let (genesis, epoch_config_store) = genesis_builder.build();
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
 
-    let mut env =
-        builder.genesis(genesis).epoch_config_store(epoch_config_store).clients(clients).build();
+    let mut env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(clients)
+        .build();
 
     // Launch a task to check that all chunks are produced.
     // Needed to make sure that chunks are valid. Currently, if chunk
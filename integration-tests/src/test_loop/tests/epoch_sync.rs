@@ -4,6 +4,7 @@ use near_chain_configs::test_genesis::TestGenesisBuilder;
 use near_chain_configs::{Genesis, GenesisConfig};
 use near_client::test_utils::test_loop::ClientQueries;
 use near_o11y::testonly::init_test_logger;
+use near_parameters::RuntimeConfigStore;
 use near_primitives::epoch_manager::EpochConfigStore;
 use near_primitives::types::{AccountId, BlockHeightDelta};
 use near_store::{DBCol, Store};
@@ -30,6 +31,7 @@ struct TestNetworkSetup {
     tempdir: TempDir,
     genesis: Genesis,
     epoch_config_store: EpochConfigStore,
+    runtime_config_store: RuntimeConfigStore,
     accounts: Vec<AccountId>,
     stores: Vec<Store>,
 }
@@ -60,11 +62,12 @@ fn setup_initial_blockchain(
     for account in &accounts {
         genesis_builder.add_user_account_simple(account.clone(), initial_balance);
     }
-    let (genesis, epoch_config_store) = genesis_builder.build();
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
 
     let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
         .genesis(genesis.clone())
         .epoch_config_store(epoch_config_store.clone())
+        .runtime_config_store(runtime_config_store.clone())
         .clients(clients)
         .build();
 
@@ -123,12 +126,26 @@ fn setup_initial_blockchain(
     let tempdir = TestLoopEnv { test_loop, datas: node_datas, tempdir }
         .shutdown_and_drain_remaining_events(Duration::seconds(5));
 
-    TestNetworkSetup { tempdir, genesis, epoch_config_store, accounts, stores }
+    TestNetworkSetup {
+        tempdir,
+        genesis,
+        epoch_config_store,
+        runtime_config_store,
+        accounts,
+        stores,
+    }
 }
 
 fn bootstrap_node_via_epoch_sync(setup: TestNetworkSetup, source_node: usize) -> TestNetworkSetup {
     tracing::info!("Starting new TestLoopEnv with new node");
-    let TestNetworkSetup { genesis, epoch_config_store, accounts, mut stores, tempdir } = setup;
+    let TestNetworkSetup {
+        genesis,
+        epoch_config_store,
+        runtime_config_store,
+        accounts,
+        mut stores,
+        tempdir,
+    } = setup;
     let num_existing_clients = stores.len();
     let clients = accounts.iter().take(num_existing_clients + 1).cloned().collect_vec();
     stores.push(create_test_store()); // new node starts empty.
@@ -136,6 +153,7 @@ fn bootstrap_node_via_epoch_sync(setup: TestNetworkSetup, source_node: usize) ->
     let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = TestLoopBuilder::new()
         .genesis(genesis.clone())
         .epoch_config_store(epoch_config_store.clone())
+        .runtime_config_store(runtime_config_store.clone())
         .clients(clients)
         .stores_override_hot_only(stores)
         .test_loop_data_dir(tempdir)
@@ -268,7 +286,14 @@ fn bootstrap_node_via_epoch_sync(setup: TestNetworkSetup, source_node: usize) ->
     let tempdir = TestLoopEnv { test_loop, datas: node_datas, tempdir }
         .shutdown_and_drain_remaining_events(Duration::seconds(5));
 
-    TestNetworkSetup { tempdir, genesis, epoch_config_store, accounts, stores }
+    TestNetworkSetup {
+        tempdir,
+        genesis,
+        epoch_config_store,
+        runtime_config_store,
+        accounts,
+        stores,
+    }
 }
 
 // Test that a new node that only has genesis can use Epoch Sync to bring itself
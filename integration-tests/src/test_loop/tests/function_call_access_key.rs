@@ -0,0 +1,130 @@
+use near_async::messaging::Handler;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_client::Query;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::errors::{InvalidAccessKeyError, InvalidTxError};
+use near_primitives::test_utils::create_user_test_signer;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, Balance, BlockReference};
+use near_primitives::views::{AccessKeyPermissionView, QueryRequest, QueryResponseKind};
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::transactions::{execute_tx, get_shared_block_hash};
+use crate::test_loop::utils::{ONE_NEAR, TGAS};
+
+/// Returns the allowance of the function call access key of the test signer
+/// of `account_id`, as seen by the view client of the first node.
+fn query_allowance(env: &mut TestLoopEnv, account_id: &AccountId) -> Option<Balance> {
+    let view_client_handle = env.datas[0].view_client_sender.actor_handle();
+    let view_client = env.test_loop.data.get_mut(&view_client_handle);
+    let query = Query::new(
+        BlockReference::latest(),
+        QueryRequest::ViewAccessKey {
+            account_id: account_id.clone(),
+            public_key: create_user_test_signer(account_id).public_key(),
+        },
+    );
+    let response = view_client.handle(query).unwrap();
+    let QueryResponseKind::AccessKey(access_key) = response.kind else {
+        panic!("unexpected query response for the access key of {account_id}");
+    };
+    let AccessKeyPermissionView::FunctionCall { allowance, .. } = access_key.permission else {
+        panic!("the access key of {account_id} should be a function call key");
+    };
+    allowance
+}
+
+/// Creates a transaction calling `method_name` on `receiver_id` without deposit.
+fn call(
+    nonce: u64,
+    signer_id: &AccountId,
+    receiver_id: &AccountId,
+    method_name: &str,
+    env: &TestLoopEnv,
+) -> SignedTransaction {
+    SignedTransaction::call(
+        nonce,
+        signer_id.clone(),
+        receiver_id.clone(),
+        &create_user_test_signer(signer_id).into(),
+        0,
+        method_name.to_string(),
+        vec![],
+        10 * TGAS,
+        get_shared_block_hash(&env.datas, &env.test_loop),
+    )
+}
+
+/// Checks that an account created with a function call key in genesis can
+/// only call the allowed methods and that the fees are taken from the key
+/// allowance.
+#[test]
+fn test_function_call_access_key_from_genesis() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let initial_balance = 10000 * ONE_NEAR;
+    let initial_allowance = ONE_NEAR;
+    let validator: AccountId = "account0".parse().unwrap();
+    let user: AccountId = "account1".parse().unwrap();
+    let allowed_method = "main";
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .gas_prices(100_000_000, 100_000_000_000)
+        .gas_limit_one_petagas()
+        .transaction_validity_period(1000)
+        .epoch_length(10)
+        .validators_desired_roles(&[validator.as_str()], &[])
+        .add_user_account_simple(validator.clone(), initial_balance)
+        .add_user_account_with_function_call_key(
+            user.clone(),
+            initial_balance,
+            validator.clone(),
+            vec![allowed_method.to_string()],
+            Some(initial_allowance),
+        );
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+
+    let mut env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(vec![validator.clone()])
+        .build();
+    env.test_loop.run_for(Duration::seconds(3));
+    assert_eq!(query_allowance(&mut env, &user), Some(initial_allowance));
+
+    // The receiver has no contract deployed, so the call itself fails, but
+    // the fees are charged anyway.
+    let tx = call(1, &user, &validator, allowed_method, &env);
+    let outcome = execute_tx(&mut env.test_loop, tx, &env.datas, Duration::seconds(5)).unwrap();
+    let tokens_burnt = outcome.transaction_outcome.outcome.tokens_burnt;
+    assert!(tokens_burnt > 0);
+    let allowance = query_allowance(&mut env, &user).unwrap();
+    tracing::info!(target: "test", initial_allowance, allowance, tokens_burnt, "allowance after call");
+    assert!(
+        initial_allowance - allowance >= tokens_burnt,
+        "the transaction fees should be taken from the allowance"
+    );
+
+    // Methods that are not listed in the key can't be called.
+    let tx = call(2, &user, &validator, "other_method", &env);
+    let result = execute_tx(&mut env.test_loop, tx, &env.datas, Duration::seconds(5));
+    assert!(
+        matches!(
+            result,
+            Err(InvalidTxError::InvalidAccessKeyError(
+                InvalidAccessKeyError::MethodNameMismatch { .. }
+            ))
+        ),
+        "calling a method not allowed by the key should be rejected, got {result:?}"
+    );
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain::ChainStoreAccess;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::types::{AccountId, StateRoot};
+use near_store::get_genesis_state_roots;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::utils::ONE_NEAR;
+
+/// Runs a few blocks of a test loop with a genesis snapshot exported to or
+/// imported from `snapshot_dir` and returns the genesis state roots of the
+/// clients.
+fn run_with_genesis_snapshot(snapshot_dir: &Path) -> Vec<Vec<StateRoot>> {
+    let builder = TestLoopBuilder::new().with_genesis_snapshot_export(snapshot_dir.to_path_buf());
+
+    let initial_balance = 10000 * ONE_NEAR;
+    let accounts =
+        (0..100).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let clients = accounts.iter().take(2).cloned().collect_vec();
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .gas_prices_free()
+        .gas_limit_one_petagas()
+        .shard_layout_simple_v1(&["account50"])
+        .transaction_validity_period(1000)
+        .epoch_length(5)
+        .validators_desired_roles(&clients.iter().map(|t| t.as_str()).collect_vec(), &[]);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), initial_balance);
+    }
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+
+    let mut env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(clients)
+        .build();
+    let handle = env.datas[0].client_sender.actor_handle();
+    env.test_loop.run_until(
+        |test_loop_data| test_loop_data.get(&handle).client.chain.head().unwrap().height > 10010,
+        Duration::seconds(10),
+    );
+
+    let genesis_state_roots = env
+        .datas
+        .iter()
+        .map(|data| {
+            let client = &env.test_loop.data.get(&data.client_sender.actor_handle()).client;
+            get_genesis_state_roots(client.chain.chain_store.store()).unwrap().unwrap()
+        })
+        .collect_vec();
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+    genesis_state_roots
+}
+
+/// Checks that a genesis snapshot exported by one run is reused by a later run
+/// with the same seed and genesis, and that both runs start from the same state.
+#[test]
+fn test_genesis_snapshot_export() {
+    init_test_logger();
+    let snapshot_dir = tempfile::tempdir().unwrap();
+
+    let exported_state_roots = run_with_genesis_snapshot(snapshot_dir.path());
+    let num_snapshots = std::fs::read_dir(snapshot_dir.path()).unwrap().count();
+    assert_eq!(num_snapshots, 1);
+
+    let imported_state_roots = run_with_genesis_snapshot(snapshot_dir.path());
+    let num_snapshots = std::fs::read_dir(snapshot_dir.path()).unwrap().count();
+    assert_eq!(num_snapshots, 1, "the second run should reuse the exported snapshot");
+    assert_eq!(exported_state_roots, imported_state_roots);
+}
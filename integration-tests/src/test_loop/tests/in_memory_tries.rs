@@ -45,12 +45,13 @@ fn test_load_memtrie_after_empty_chunks() {
     for account in &accounts {
         genesis_builder.add_user_account_simple(account.clone(), initial_balance);
     }
-    let (genesis, epoch_config_store) = genesis_builder.build();
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
     let shard_layout = genesis.config.shard_layout.clone();
 
     let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
         .clients(client_accounts)
         .build();
 
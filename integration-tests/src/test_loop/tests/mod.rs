@@ -1,19 +1,25 @@
 mod bandwidth_scheduler;
 mod bandwidth_scheduler_protocol_upgrade;
+mod block_production_hooks;
 mod chunk_validator_kickout;
 mod congestion_control;
 mod congestion_control_genesis_bootstrap;
+mod congestion_control_protocol_upgrade;
 mod contract_distribution_cross_shard;
 mod contract_distribution_simple;
 mod create_delete_account;
 mod epoch_sync;
 mod fix_min_stake_ratio;
+mod function_call_access_key;
+mod genesis_snapshot;
 mod in_memory_tries;
 mod max_receipt_size;
 mod multinode_stateless_validators;
 mod multinode_test_loop_example;
 mod protocol_upgrade;
+mod random_seed;
 mod resharding_v3;
+mod rewards;
 mod simple_test_loop_example;
 mod state_sync;
 mod syncing;
@@ -14,7 +14,6 @@ use near_vm_runner::logic::ProtocolVersion;
 use std::cell::{Cell, RefCell};
 use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
-use std::sync::Arc;
 
 use crate::test_loop::builder::TestLoopBuilder;
 use crate::test_loop::env::TestLoopEnv;
@@ -70,7 +69,7 @@ pub(crate) fn test_protocol_upgrade(
     for account in accounts {
         genesis_builder.add_user_account_simple(account.clone(), initial_balance);
     }
-    let (genesis, genesis_epoch_config_store) = genesis_builder.build();
+    let (genesis, genesis_epoch_config_store, runtime_config_store) = genesis_builder.build();
     let genesis_epoch_info = genesis_epoch_config_store.get_config(old_protocol);
 
     let mainnet_epoch_config_store = EpochConfigStore::for_chain_id("mainnet", None).unwrap();
@@ -98,11 +97,6 @@ pub(crate) fn test_protocol_upgrade(
     adjust_epoch_config(&mut old_epoch_config);
     adjust_epoch_config(&mut new_epoch_config);
 
-    let epoch_config_store = EpochConfigStore::test(BTreeMap::from_iter(vec![
-        (old_protocol, Arc::new(old_epoch_config)),
-        (new_protocol, Arc::new(new_epoch_config)),
-    ]));
-
     // Translate shard ids to shard uids
     let chunk_ranges_to_drop: HashMap<ShardUId, std::ops::Range<i64>> = missing_chunk_ranges
         .iter()
@@ -115,7 +109,9 @@ pub(crate) fn test_protocol_upgrade(
 
     let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
         .genesis(genesis)
-        .epoch_config_store(epoch_config_store)
+        .with_epoch_config_per_protocol_version(old_protocol, old_epoch_config)
+        .with_epoch_config_per_protocol_version(new_protocol, new_epoch_config)
+        .runtime_config_store(runtime_config_store)
         .drop_protocol_upgrade_chunks(new_protocol, chunk_ranges_to_drop)
         .clients(clients)
         .build();
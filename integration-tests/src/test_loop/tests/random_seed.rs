@@ -0,0 +1,91 @@
+use itertools::Itertools;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_o11y::testonly::init_test_logger;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::AccountId;
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::{ShardAssignmentTrace, TestLoopEnv};
+use crate::test_loop::utils::ONE_NEAR;
+
+/// Runs a small chain with the given seed and returns the hashes of all the
+/// blocks on the canonical chain, starting from genesis, and the chunk
+/// producer of every shard at every height.
+fn run_with_seed(seed: u64) -> (Vec<CryptoHash>, ShardAssignmentTrace) {
+    let builder = TestLoopBuilder::new().with_random_seed(seed).with_shard_assignment_trace();
+
+    let initial_balance = 10000 * ONE_NEAR;
+    let accounts =
+        (0..8).map(|i| format!("account{}", i).parse().unwrap()).collect::<Vec<AccountId>>();
+    let clients = accounts.iter().take(4).cloned().collect_vec();
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .gas_prices_free()
+        .gas_limit_one_petagas()
+        .shard_layout_simple_v1(&["account3", "account5"])
+        .transaction_validity_period(1000)
+        .epoch_length(5)
+        .validators_desired_roles(&clients.iter().map(|t| t.as_str()).collect_vec(), &[])
+        .shuffle_shard_assignment_for_chunk_producers(true);
+    for account in &accounts {
+        genesis_builder.add_user_account_simple(account.clone(), initial_balance);
+    }
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+
+    let env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(clients)
+        .build();
+    let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = env;
+
+    let client_handle = node_datas[0].client_sender.actor_handle();
+    test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height > 10020
+        },
+        Duration::seconds(10),
+    );
+
+    let chain = &test_loop.data.get(&client_handle).client.chain;
+    let head = chain.head().unwrap();
+    let mut block_hashes = vec![head.last_block_hash];
+    let mut header = chain.get_block_header(&head.last_block_hash).unwrap();
+    while header.height() > chain.genesis().height() {
+        block_hashes.push(*header.prev_hash());
+        header = chain.get_block_header(header.prev_hash()).unwrap();
+    }
+    block_hashes.reverse();
+
+    let env = TestLoopEnv { test_loop, datas: node_datas, tempdir };
+    let shard_assignment_trace = env.shard_assignment_trace();
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+    (block_hashes, shard_assignment_trace)
+}
+
+#[test]
+fn test_same_random_seed_produces_same_blocks() {
+    init_test_logger();
+
+    let seed = 42;
+    let first_run = run_with_seed(seed);
+    let second_run = run_with_seed(seed);
+    assert_eq!(first_run, second_run, "runs with random seed {} diverged", seed);
+}
+
+/// The chunk producers are shuffled between shards at every epoch, so runs
+/// with different seeds must end up with different schedules.
+#[test]
+fn test_different_random_seeds_produce_different_schedules() {
+    init_test_logger();
+
+    let (_, first_schedule) = run_with_seed(1);
+    let (_, second_schedule) = run_with_seed(2);
+    assert_ne!(first_schedule, second_schedule, "random seed doesn't affect the shuffling");
+}
@@ -1,43 +1,59 @@
 use borsh::BorshDeserialize;
 use itertools::Itertools;
+use near_async::messaging::Handler;
 use near_async::test_loop::data::{TestLoopData, TestLoopDataHandle};
 use near_async::time::Duration;
+use near_chain::stateless_validation::chunk_validation::{
+    pre_validate_chunk_state_witness, validate_chunk_state_witness, MainStateTransitionCache,
+};
 use near_chain::ChainStoreAccess;
 use near_chain_configs::test_genesis::TestGenesisBuilder;
-use near_chain_configs::DEFAULT_GC_NUM_EPOCHS_TO_KEEP;
+use near_chain_configs::{DEFAULT_GC_NUM_EPOCHS_TO_KEEP, MIN_GC_NUM_EPOCHS_TO_KEEP};
 use near_client::Client;
 use near_o11y::testonly::init_test_logger;
 use near_primitives::block::Tip;
+use near_primitives::congestion_info::CongestionControl;
 use near_primitives::epoch_manager::EpochConfigStore;
 use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout};
+use near_primitives::shard_layout::{account_id_to_shard_id, account_id_to_shard_uid, ShardLayout};
 use near_primitives::state_record::StateRecord;
-use near_primitives::types::{AccountId, BlockHeightDelta, Gas, ShardId};
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockHeightDelta, BlockId, BlockReference, EpochId, Gas, ShardId,
+};
 use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
 use near_store::adapter::StoreAdapter;
 use near_store::db::refcount::decode_value_with_rc;
-use near_store::{get, DBCol, ShardUId, Trie};
-use std::collections::{BTreeMap, HashMap, HashSet};
-use std::sync::Arc;
+use near_store::genesis::compute_storage_usage;
+use near_store::trie::outgoing_metadata::ReceiptGroupsQueue;
+use near_store::trie::receipts_column_helper::ShardsOutgoingReceiptBuffer;
+use near_store::{get, get_account, DBCol, ShardUId, Trie};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex};
 
 use crate::test_loop::builder::TestLoopBuilder;
-use crate::test_loop::env::{TestData, TestLoopEnv};
-use crate::test_loop::utils::transactions::{
-    get_shared_block_hash, get_smallest_height_head, run_tx, submit_tx,
-};
+use crate::test_loop::env::{ShardAssignmentTrace, TestData, TestLoopEnv};
+use crate::test_loop::utils::transactions::{get_smallest_height_head, submit_tx};
 use crate::test_loop::utils::{ONE_NEAR, TGAS};
 use assert_matches::assert_matches;
 use near_client::client_actor::ClientActorInner;
+use near_client::{ProcessTxResponse, Query};
 use near_crypto::Signer;
 use near_epoch_manager::EpochManagerAdapter;
-use near_parameters::{RuntimeConfig, RuntimeConfigStore};
+use near_fmt::AbbrBytes;
+use near_primitives::bandwidth_scheduler::{BandwidthRequests, BandwidthSchedulerParams};
+use near_primitives::errors::{InvalidTxError, StorageError};
 use near_primitives::receipt::{BufferedReceiptIndices, DelayedReceiptIndices};
 use near_primitives::state::FlatStateValue;
+use near_primitives::stateless_validation::state_witness::ChunkStateWitness;
 use near_primitives::test_utils::create_user_test_signer;
 use near_primitives::transaction::SignedTransaction;
+use near_primitives::trie_key::trie_key_parsers::parse_account_id_from_raw_key;
 use near_primitives::trie_key::TrieKey;
-use near_primitives::views::FinalExecutionStatus;
-use std::cell::Cell;
+use near_primitives::views::{FinalExecutionStatus, QueryRequest, QueryResponseKind};
+use node_runtime::ApplyResult;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use std::u64;
 
 fn client_tracking_shard<'a>(clients: &'a [&Client], tip: &Tip, shard_id: ShardId) -> &'a Client {
@@ -117,6 +133,78 @@ fn check_state_shard_uid_mapping_after_resharding(client: &Client, parent_shard_
     }
 }
 
+/// Asserts that, after garbage collection had the time to run, `DBCol::State`
+/// still holds no data keyed by the children ShardUIds of `parent_shard_uid`.
+/// The children are mapped to the parent ShardUId, so all of their state,
+/// including the nodes written after resharding, must live under the parent
+/// prefix and be readable through the children ShardUIds.
+fn check_no_state_data_in_children_shards_after_gc(client: &Client, parent_shard_uid: ShardUId) {
+    let tip = client.chain.head().unwrap();
+    let shard_layout = client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+    let children_shard_uids =
+        shard_layout.get_children_shards_uids(parent_shard_uid.shard_id()).unwrap();
+
+    let store = client.chain.chain_store.store().trie_store();
+    let mut num_parent_entries = 0;
+    for kv in store.store().iter_raw_bytes(DBCol::State) {
+        let (key, _) = kv.unwrap();
+        let shard_uid = ShardUId::try_from_slice(&key[0..8]).unwrap();
+        assert!(
+            !children_shard_uids.contains(&shard_uid),
+            "found State data keyed by child shard {shard_uid} after GC"
+        );
+        if shard_uid != parent_shard_uid {
+            continue;
+        }
+        num_parent_entries += 1;
+        let node_hash = CryptoHash::try_from_slice(&key[8..]).unwrap();
+        let parent_value = store.get(parent_shard_uid, &node_hash).unwrap();
+        // Whatever survived GC must be reachable through the mapping.
+        for child_shard_uid in &children_shard_uids {
+            let child_value = store.get(*child_shard_uid, &node_hash).unwrap();
+            assert_eq!(child_value, parent_value);
+        }
+    }
+    let tracks_children = children_shard_uids.iter().any(|child_shard_uid| {
+        client.chain.get_chunk_extra(&tip.last_block_hash, child_shard_uid).is_ok()
+    });
+    if tracks_children {
+        assert!(num_parent_entries > 0, "no State data left under the parent ShardUId");
+    }
+}
+
+/// Asserts that the epoch heights of the blocks between `from_block` and `to_block` (both
+/// inclusive, `from_block` being an ancestor of `to_block`) increase by exactly one at every
+/// epoch boundary, i.e. that no epoch was skipped.
+fn assert_no_skipped_epochs_during_resharding(
+    client: &Client,
+    from_block: &CryptoHash,
+    to_block: &CryptoHash,
+) {
+    let epoch_manager = &client.epoch_manager;
+    let epoch_height_of = |epoch_id| epoch_manager.get_epoch_info(epoch_id).unwrap().epoch_height();
+
+    // Walk back from `to_block`, recording the epoch height every time the epoch changes.
+    let mut header = client.chain.get_block_header(to_block).unwrap();
+    let mut epoch_heights = vec![epoch_height_of(header.epoch_id())];
+    while header.hash() != from_block {
+        let prev_header = client.chain.get_block_header(header.prev_hash()).unwrap();
+        if prev_header.epoch_id() != header.epoch_id() {
+            epoch_heights.push(epoch_height_of(prev_header.epoch_id()));
+        }
+        header = prev_header;
+    }
+    epoch_heights.reverse();
+
+    for (prev_epoch_height, epoch_height) in epoch_heights.iter().tuple_windows() {
+        assert_eq!(
+            prev_epoch_height + 1,
+            *epoch_height,
+            "epoch heights between {from_block} and {to_block} are not contiguous: {epoch_heights:?}"
+        );
+    }
+}
+
 /// Signature of functions callable from inside the inner loop of the resharding suite of tests.
 type LoopActionFn =
     Box<dyn Fn(&[TestData], &mut TestLoopData, TestLoopDataHandle<ClientActorInner>)>;
@@ -139,10 +227,25 @@ struct TestReshardingParameters {
     // epoch length, but it's good to also check what happens with shorter ones.
     all_chunks_expected: bool,
     /// Optionally deploy the test contract
-    /// (see nearcore/runtime/near-test-contracts/test-contract-rs/src/lib.rs) on the provided account.
+    /// (see nearcore/runtime/near-test-contracts/test-contract-rs/src/lib.rs) on the provided account at genesis.
     deploy_test_contract: Option<AccountId>,
     /// Enable a stricter limit on outgoing gas to easily trigger congestion control.
     limit_outgoing_gas: bool,
+    /// Number of epochs the clients keep before garbage collecting them. The test waits for
+    /// that many epochs after resharding so that garbage collection is tested as well.
+    gc_num_epochs_to_keep: u64,
+    /// Check at the end of the test that the outgoing buffers inherited from the parent shard
+    /// have been drained.
+    check_outgoing_buffers_drained: bool,
+    /// Record the buffered receipts gas of every applied chunk and check at the end of the test
+    /// that receipts got buffered and that all buffers have been drained since.
+    check_buffered_gas_trajectory: bool,
+    /// Validate the state witnesses produced in the resharding epoch, and the first witnesses of
+    /// the children shards, independently of the chunk validators.
+    validate_resharding_state_witnesses: bool,
+    /// Number of shards before the resharding. If not set, the layouts of
+    /// `resharding_v3_shard_layouts` are used.
+    num_shards: Option<usize>,
 }
 
 impl TestReshardingParameters {
@@ -184,6 +287,7 @@ impl TestReshardingParameters {
 
         let block_and_chunk_producers = clients.clone();
         let load_mem_tries_for_tracked_shards = true;
+        let gc_num_epochs_to_keep = DEFAULT_GC_NUM_EPOCHS_TO_KEEP;
 
         Self {
             accounts,
@@ -194,6 +298,7 @@ impl TestReshardingParameters {
             track_all_shards,
             all_chunks_expected,
             load_mem_tries_for_tracked_shards,
+            gc_num_epochs_to_keep,
             ..Default::default()
         }
     }
@@ -203,6 +308,7 @@ impl TestReshardingParameters {
         chunk_ranges_to_drop: HashMap<ShardUId, std::ops::Range<i64>>,
     ) -> Self {
         self.chunk_ranges_to_drop = chunk_ranges_to_drop;
+        self.all_chunks_expected = false;
         self
     }
 
@@ -224,6 +330,29 @@ impl TestReshardingParameters {
         self
     }
 
+    /// Adds a loop action that only runs when the chain head height is a multiple of `n`.
+    /// Useful for expensive checks, e.g. ones reading the trie, that don't need to run at every
+    /// test loop iteration.
+    fn add_loop_action_every_n_blocks(
+        mut self,
+        loop_action: LoopActionFn,
+        n: BlockHeightDelta,
+    ) -> Self {
+        assert!(n > 0, "loop action interval must be positive");
+        self.loop_actions.push(Box::new(
+            move |node_datas: &[TestData],
+                  test_loop_data: &mut TestLoopData,
+                  client_handle: TestLoopDataHandle<ClientActorInner>| {
+                let tip = test_loop_data.get(&client_handle).client.chain.head().unwrap();
+                if tip.height % n != 0 {
+                    return;
+                }
+                loop_action(node_datas, test_loop_data, client_handle);
+            },
+        ));
+        self
+    }
+
     fn shuffle_shard_assignment(mut self) -> Self {
         self.shuffle_shard_assignment_for_chunk_producers = true;
         self
@@ -249,6 +378,43 @@ impl TestReshardingParameters {
         self
     }
 
+    fn check_outgoing_buffers_drained(mut self) -> Self {
+        self.check_outgoing_buffers_drained = true;
+        self
+    }
+
+    fn check_buffered_gas_trajectory(mut self) -> Self {
+        self.check_buffered_gas_trajectory = true;
+        self
+    }
+
+    /// Starts from a shard layout with `num_shards` shards, with boundaries spread evenly over the
+    /// accounts, instead of the default layout.
+    fn with_num_shards(mut self, num_shards: usize) -> Self {
+        assert!(
+            num_shards > 0 && num_shards <= self.accounts.len(),
+            "number of shards must be between 1 and the number of accounts {}, got {num_shards}",
+            self.accounts.len()
+        );
+        self.num_shards = Some(num_shards);
+        self
+    }
+
+    fn validate_resharding_state_witnesses(mut self) -> Self {
+        self.validate_resharding_state_witnesses = true;
+        self
+    }
+
+    fn epoch_length(mut self, epoch_length: BlockHeightDelta) -> Self {
+        self.epoch_length = epoch_length;
+        self
+    }
+
+    fn gc_num_epochs_to_keep(mut self, gc_num_epochs_to_keep: u64) -> Self {
+        self.gc_num_epochs_to_keep = gc_num_epochs_to_keep;
+        self
+    }
+
     fn load_mem_tries_for_tracked_shards(
         mut self,
         load_mem_tries_for_tracked_shards: bool,
@@ -256,6 +422,84 @@ impl TestReshardingParameters {
         self.load_mem_tries_for_tracked_shards = load_mem_tries_for_tracked_shards;
         self
     }
+
+    /// Checks that the parameters make sense together, so that a misconfigured test fails
+    /// upfront instead of in the middle of the test loop.
+    fn validate(&self) -> Result<(), Vec<ParameterConflict>> {
+        let mut conflicts = vec![];
+        if self.all_chunks_expected && !self.chunk_ranges_to_drop.is_empty() {
+            conflicts.push(ParameterConflict::AllChunksExpectedWithDroppedChunks);
+        }
+        for client in &self.clients {
+            if !self.block_and_chunk_producers.contains(client) {
+                conflicts.push(ParameterConflict::ClientNotProducer(client.clone()));
+            }
+        }
+        // #12195 prevents number of BPs bigger than `epoch_length`.
+        if self.epoch_length < self.clients.len() as BlockHeightDelta {
+            conflicts.push(ParameterConflict::EpochShorterThanClients {
+                epoch_length: self.epoch_length,
+                num_clients: self.clients.len(),
+            });
+        }
+        // The ranges are relative to the protocol upgrade, chunks can only be dropped in the
+        // last epoch with the old protocol version and the first one with the new version.
+        let epoch_length = self.epoch_length as i64;
+        for (shard_uid, range) in &self.chunk_ranges_to_drop {
+            if range.is_empty() || range.start < -epoch_length || range.end > epoch_length {
+                conflicts.push(ParameterConflict::ImpossibleChunkDropRange {
+                    shard_uid: *shard_uid,
+                    range: range.clone(),
+                });
+            }
+        }
+        // The witnesses are validated by the first client, which needs the chunk extras of
+        // every shard.
+        if self.validate_resharding_state_witnesses && !self.track_all_shards {
+            conflicts.push(ParameterConflict::WitnessValidationWithoutAllShardsTracked);
+        }
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(conflicts)
+        }
+    }
+}
+
+/// Inconsistency between the values of [`TestReshardingParameters`].
+enum ParameterConflict {
+    /// Chunks are dropped while all chunks are expected to be included.
+    AllChunksExpectedWithDroppedChunks,
+    /// The client is not a block and chunk producer.
+    ClientNotProducer(AccountId),
+    /// The epoch is too short for all the clients to produce a block in it.
+    EpochShorterThanClients { epoch_length: BlockHeightDelta, num_clients: usize },
+    /// The range of chunks to drop is empty or it exceeds the epochs around the resharding.
+    ImpossibleChunkDropRange { shard_uid: ShardUId, range: std::ops::Range<i64> },
+    /// State witnesses are validated but the clients don't track all shards.
+    WitnessValidationWithoutAllShardsTracked,
+}
+
+impl std::fmt::Display for ParameterConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AllChunksExpectedWithDroppedChunks => {
+                write!(f, "all chunks are expected but some chunks are dropped")
+            }
+            Self::ClientNotProducer(client) => {
+                write!(f, "client {client} is not a block and chunk producer")
+            }
+            Self::EpochShorterThanClients { epoch_length, num_clients } => {
+                write!(f, "epoch length {epoch_length} is shorter than the number of clients {num_clients}")
+            }
+            Self::ImpossibleChunkDropRange { shard_uid, range } => {
+                write!(f, "chunks of shard {shard_uid} can't be dropped in range {range:?}")
+            }
+            Self::WitnessValidationWithoutAllShardsTracked => {
+                write!(f, "state witnesses can only be validated if all shards are tracked")
+            }
+        }
+    }
 }
 
 // Returns a callable function that, when invoked inside a test loop iteration, can force the creation of a chain fork.
@@ -368,6 +612,48 @@ fn check_delayed_receipts_exist_in_memtrie(
     assert_ne!(indices.len(), 0);
 }
 
+/// Asserts that the delayed receipt queue of the given shard, at the state after `block_hash`,
+/// holds at most `max_receipts` receipts.
+fn assert_receipt_queue_bounded(
+    client: &Client,
+    shard_uid: &ShardUId,
+    block_hash: &CryptoHash,
+    max_receipts: u64,
+) {
+    let memtrie = get_memtrie_for_shard(client, shard_uid, block_hash);
+    let indices: DelayedReceiptIndices =
+        get(&memtrie, &TrieKey::DelayedReceiptIndices).unwrap().unwrap_or_default();
+    assert!(
+        indices.len() <= max_receipts,
+        "delayed receipt queue of shard {shard_uid} has {} receipts, expected at most {max_receipts}",
+        indices.len()
+    );
+}
+
+/// Returns a loop action that checks at every block height that the delayed receipt queue of the
+/// shard containing `account` never grows beyond `max_receipts`, before and after resharding.
+fn check_receipt_queue_bounded(account: AccountId, max_receipts: u64) -> LoopActionFn {
+    let latest_height = Cell::new(0);
+    Box::new(
+        move |_: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+
+            // Run this action only once at every block height.
+            if latest_height.get() == tip.height {
+                return;
+            }
+            latest_height.set(tip.height);
+
+            let shard_layout = client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            let shard_uid = account_id_to_shard_uid(&account, &shard_layout);
+            assert_receipt_queue_bounded(client, &shard_uid, &tip.last_block_hash, max_receipts);
+        },
+    )
+}
+
 /// Asserts that a non zero amount of buffered receipts exist in MemTrie for the given shard.
 fn check_buffered_receipts_exist_in_memtrie(
     client: &Client,
@@ -462,6 +748,203 @@ fn call_burn_gas_contract(
     )
 }
 
+/// Returns a loop action that sends a money transfer from each of `signer_ids` at every block
+/// height, up to one block after resharding, using consecutive nonces for each account.
+/// A few blocks after resharding it checks that all transfers succeeded and that a transaction
+/// reusing the latest nonce of each account is rejected, i.e. the nonces were neither lost nor
+/// reset when the accounts moved to the children shards.
+fn send_transfers_and_check_nonces(signer_ids: Vec<AccountId>) -> LoopActionFn {
+    const TX_CHECK_BLOCKS_AFTER_RESHARDING: u64 = 5;
+
+    let resharding_height = Cell::new(None);
+    let latest_height = Cell::new(0);
+    let done = Cell::new(false);
+    // The genesis access keys have nonce 0.
+    let nonces = RefCell::new(vec![0; signer_ids.len()]);
+    let txs = Cell::new(vec![]);
+    // TODO: to be fixed when all shard tracking gets disabled.
+    let rpc_id: AccountId = "account0".parse().unwrap();
+    let receiver_id: AccountId = "account0".parse().unwrap();
+
+    Box::new(
+        move |node_datas: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            if done.get() {
+                return;
+            }
+            let client_actor = &mut test_loop_data.get_mut(&client_handle);
+            let tip = client_actor.client.chain.head().unwrap();
+
+            // Run this action only once at every block height.
+            if latest_height.get() == tip.height {
+                return;
+            }
+            latest_height.set(tip.height);
+
+            if let Some(height) = resharding_height.get() {
+                if tip.height > height + TX_CHECK_BLOCKS_AFTER_RESHARDING {
+                    for (tx, tx_height) in txs.take() {
+                        let tx_outcome =
+                            client_actor.client.chain.get_partial_transaction_result(&tx);
+                        let status = tx_outcome.as_ref().map(|o| o.status.clone());
+                        let status = status.unwrap();
+                        tracing::debug!(target: "test", ?tx_height, ?tx, ?status, "transaction status");
+                        assert_matches!(status, FinalExecutionStatus::SuccessValue(_));
+                    }
+                    // A transaction reusing the latest nonce must be rejected, which proves that
+                    // the nonce was carried over to the child shard.
+                    for (signer_id, &nonce) in signer_ids.iter().zip(nonces.borrow().iter()) {
+                        let signer: Signer = create_user_test_signer(signer_id).into();
+                        let tx = SignedTransaction::send_money(
+                            nonce,
+                            signer_id.clone(),
+                            receiver_id.clone(),
+                            &signer,
+                            1,
+                            tip.last_block_hash,
+                        );
+                        let response = client_actor.client.process_tx(tx, false, true);
+                        tracing::debug!(target: "test", ?signer_id, nonce, ?response, "stale nonce response");
+                        assert_matches!(
+                            response,
+                            ProcessTxResponse::InvalidTx(InvalidTxError::InvalidNonce { ak_nonce, .. })
+                                if ak_nonce == nonce
+                        );
+                    }
+                    done.set(true);
+                    return;
+                }
+            } else if next_block_has_new_shard_layout(
+                client_actor.client.epoch_manager.clone(),
+                &tip,
+            ) {
+                tracing::debug!(target: "test", height=tip.height, "resharding height set");
+                resharding_height.set(Some(tip.height));
+            }
+
+            // Before resharding and one block after: send one transfer per account.
+            if tip.height <= resharding_height.get().unwrap_or(1000) + 1 {
+                let mut nonces = nonces.borrow_mut();
+                for (signer_id, nonce) in signer_ids.iter().zip(nonces.iter_mut()) {
+                    let signer: Signer = create_user_test_signer(signer_id).into();
+                    *nonce += 1;
+                    let tx = SignedTransaction::send_money(
+                        *nonce,
+                        signer_id.clone(),
+                        receiver_id.clone(),
+                        &signer,
+                        1,
+                        tip.last_block_hash,
+                    );
+                    let mut txs_vec = txs.take();
+                    tracing::debug!(target: "test", height=tip.height, tx_hash=?tx.get_hash(), ?signer_id, nonce=*nonce, "submitting transaction");
+                    txs_vec.push((tx.get_hash(), tip.height));
+                    txs.set(txs_vec);
+                    submit_tx(&node_datas, &rpc_id, tx);
+                }
+            }
+        },
+    )
+}
+
+/// Returns a loop action that calls the `increment_counter` method of the test contract deployed
+/// on `contract_id` once before resharding, by `increment_before`, and once in the first block after
+/// resharding, by `increment_after`. It checks that the value returned by the second call is the
+/// sum of both increments, i.e. that the contract state was carried over to the child shard.
+fn increment_counter_across_resharding(
+    contract_id: AccountId,
+    increment_before: u64,
+    increment_after: u64,
+) -> LoopActionFn {
+    const BLOCKS_AFTER_RESHARDING: u64 = 1;
+    const TX_CHECK_BLOCKS: u64 = 4;
+
+    let resharding_height = Cell::new(None);
+    let latest_height = Cell::new(0);
+    let nonce = Cell::new(102);
+    // The transactions submitted so far, with the expected counter value after each of them.
+    let txs = RefCell::new(vec![]);
+    let done = Cell::new(false);
+    // TODO: to be fixed when all shard tracking gets disabled.
+    let rpc_id: AccountId = "account0".parse().unwrap();
+
+    Box::new(
+        move |node_datas: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            if done.get() {
+                return;
+            }
+            let client_actor = &mut test_loop_data.get_mut(&client_handle);
+            let tip = client_actor.client.chain.head().unwrap();
+
+            // Run this action only once at every block height.
+            if latest_height.get() == tip.height {
+                return;
+            }
+            latest_height.set(tip.height);
+
+            let increment = match resharding_height.get() {
+                None if txs.borrow().is_empty() => Some(increment_before),
+                None => {
+                    if next_block_has_new_shard_layout(
+                        client_actor.client.epoch_manager.clone(),
+                        &tip,
+                    ) {
+                        tracing::debug!(target: "test", height=tip.height, "resharding height set");
+                        resharding_height.set(Some(tip.height));
+                    }
+                    None
+                }
+                Some(height) if tip.height == height + BLOCKS_AFTER_RESHARDING => {
+                    Some(increment_after)
+                }
+                Some(height) if tip.height > height + BLOCKS_AFTER_RESHARDING + TX_CHECK_BLOCKS => {
+                    for (tx, expected_counter) in txs.take() {
+                        let tx_outcome =
+                            client_actor.client.chain.get_partial_transaction_result(&tx);
+                        let status = tx_outcome.as_ref().map(|o| o.status.clone());
+                        let status = status.unwrap();
+                        tracing::debug!(target: "test", ?tx, ?status, expected_counter, "counter transaction status");
+                        assert_eq!(
+                            status,
+                            FinalExecutionStatus::SuccessValue(
+                                expected_counter.to_le_bytes().to_vec()
+                            )
+                        );
+                    }
+                    done.set(true);
+                    None
+                }
+                Some(_) => None,
+            };
+
+            let Some(increment) = increment else {
+                return;
+            };
+            let expected_counter =
+                txs.borrow().last().map_or(0, |(_, counter)| *counter) + increment;
+            let signer: Signer = create_user_test_signer(&contract_id).into();
+            nonce.set(nonce.get() + 1);
+            let tx = SignedTransaction::call(
+                nonce.get(),
+                contract_id.clone(),
+                contract_id.clone(),
+                &signer,
+                0,
+                "increment_counter".to_owned(),
+                increment.to_le_bytes().to_vec(),
+                10 * TGAS,
+                tip.last_block_hash,
+            );
+            tracing::debug!(target: "test", height=tip.height, tx_hash=?tx.get_hash(), increment, "submitting counter transaction");
+            txs.borrow_mut().push((tx.get_hash(), expected_counter));
+            submit_tx(&node_datas, &rpc_id, tx);
+        },
+    )
+}
+
 // We want to understand if the most recent block is a resharding block.
 // To do this check if the latest block is an epoch start and compare the two epochs' shard layouts.
 fn next_block_has_new_shard_layout(epoch_manager: Arc<dyn EpochManagerAdapter>, tip: &Tip) -> bool {
@@ -490,90 +973,941 @@ fn get_memtrie_for_shard(
     memtrie
 }
 
-/// Asserts that for each child shard:
-/// MemTrie, FlatState and DiskTrie all contain the same key-value pairs.
-fn assert_state_sanity_for_children_shard(parent_shard_uid: ShardUId, client: &Client) {
-    let final_head = client.chain.final_head().unwrap();
+/// Asserts that the outgoing buffers of the parent shard, which are inherited by its children at
+/// resharding, have all been drained at the state after `after_block_hash`, i.e. that every
+/// receipt buffered by the parent was forwarded by one of the children.
+fn check_outgoing_buffer_migration_complete(
+    client: &Client,
+    parent_shard_uid: ShardUId,
+    after_block_hash: &CryptoHash,
+) {
+    let epoch_id = client.epoch_manager.get_epoch_id(after_block_hash).unwrap();
+    let shard_layout = client.epoch_manager.get_shard_layout(&epoch_id).unwrap();
+    let children_shard_uids =
+        shard_layout.get_children_shards_uids(parent_shard_uid.shard_id()).unwrap();
+    for child_shard_uid in children_shard_uids {
+        let state_root =
+            *client.chain.get_chunk_extra(after_block_hash, &child_shard_uid).unwrap().state_root();
+        let trie = client
+            .runtime_adapter
+            .get_trie_for_shard(child_shard_uid.shard_id(), after_block_hash, state_root, false)
+            .unwrap();
+        let buffers = ShardsOutgoingReceiptBuffer::load(&trie).unwrap();
+        for shard_id in buffers.shards() {
+            let buffer_len = buffers.buffer_len(shard_id).unwrap();
+            assert_eq!(
+                buffer_len, 0,
+                "child shard {child_shard_uid} of {parent_shard_uid} still has {buffer_len} receipts buffered to shard {shard_id}"
+            );
+        }
+    }
+}
 
-    for child_shard_uid in client
-        .epoch_manager
-        .get_shard_layout(&final_head.epoch_id)
-        .unwrap()
-        .get_children_shards_uids(parent_shard_uid.shard_id())
-        .unwrap()
-    {
-        let memtrie = get_memtrie_for_shard(client, &child_shard_uid, &final_head.prev_block_hash);
-        let memtrie_state =
-            memtrie.lock_for_iter().iter().unwrap().collect::<Result<HashSet<_>, _>>().unwrap();
+/// Asserts that the `storage_usage` of every account in the shard, at the state after `block_hash`,
+/// matches the storage computed from all the state records of that account.
+fn check_storage_usage_consistency(client: &Client, shard_uid: ShardUId, block_hash: &CryptoHash) {
+    let epoch_id = client.epoch_manager.get_epoch_id(block_hash).unwrap();
+    let protocol_version = client.epoch_manager.get_epoch_protocol_version(&epoch_id).unwrap();
+    let runtime_config = client.runtime_adapter.get_runtime_config(protocol_version).unwrap();
+    let state_root = *client.chain.get_chunk_extra(block_hash, &shard_uid).unwrap().state_root();
+    let trie = client
+        .runtime_adapter
+        .get_trie_for_shard(shard_uid.shard_id(), block_hash, state_root, false)
+        .unwrap();
+
+    let mut records = vec![];
+    for item in trie.lock_for_iter().iter().unwrap() {
+        let (key, value) = item.unwrap();
+        if let Some(state_record) = StateRecord::from_raw_key_value(key, value) {
+            records.push(state_record);
+        }
+    }
+    let expected_storage_usage =
+        compute_storage_usage(&records, &runtime_config.fees.storage_usage_config);
+    for record in &records {
+        let StateRecord::Account { account_id, account } = record else {
+            continue;
+        };
+        assert_eq!(
+            account.storage_usage(),
+            expected_storage_usage[account_id],
+            "storage usage mismatch for {account_id} in shard {shard_uid}"
+        );
+    }
+}
+
+/// Asserts that, at the final head of `client`, every trie key with an account prefix in the
+/// children of `parent_shard_uid` is stored in the child that owns the account: accounts below
+/// `boundary_account` in the left child and the others in the right child.
+fn check_trie_key_prefix_completeness(
+    client: &Client,
+    parent_shard_uid: ShardUId,
+    left_child_uid: ShardUId,
+    right_child_uid: ShardUId,
+    boundary_account: &AccountId,
+) {
+    let final_head = client.chain.final_head().unwrap();
+    let shard_layout = client.epoch_manager.get_shard_layout(&final_head.epoch_id).unwrap();
+    assert_eq!(
+        shard_layout.get_children_shards_uids(parent_shard_uid.shard_id()).unwrap(),
+        vec![left_child_uid, right_child_uid]
+    );
 
+    for child_shard_uid in [left_child_uid, right_child_uid] {
         let state_root = *client
             .chain
-            .get_chunk_extra(&final_head.prev_block_hash, &child_shard_uid)
+            .get_chunk_extra(&final_head.last_block_hash, &child_shard_uid)
             .unwrap()
             .state_root();
-
-        // To get a view on disk tries we can leverage the fact that get_view_trie_for_shard() never
-        // uses memtries.
         let trie = client
             .runtime_adapter
-            .get_view_trie_for_shard(
+            .get_trie_for_shard(
                 child_shard_uid.shard_id(),
-                &final_head.prev_block_hash,
+                &final_head.last_block_hash,
                 state_root,
+                false,
             )
             .unwrap();
-        assert!(!trie.has_memtries());
-        let trie_state =
-            trie.lock_for_iter().iter().unwrap().collect::<Result<HashSet<_>, _>>().unwrap();
-
-        let flat_store_chunk_view = client
-            .chain
-            .runtime_adapter
-            .get_flat_storage_manager()
-            .chunk_view(child_shard_uid, final_head.last_block_hash)
-            .unwrap();
-        let flat_store_state = flat_store_chunk_view
-            .iter_range(None, None)
-            .map_ok(|(key, value)| {
-                let value = match value {
-                    FlatStateValue::Ref(value) => client
-                        .chain
-                        .chain_store()
-                        .store()
-                        .trie_store()
-                        .get(child_shard_uid, &value.hash)
-                        .unwrap()
-                        .to_vec(),
-                    FlatStateValue::Inlined(data) => data,
-                };
-                (key, value)
-            })
-            .collect::<Result<HashSet<_>, _>>()
-            .unwrap();
-
-        let diff_memtrie_flat_store = memtrie_state.symmetric_difference(&flat_store_state);
-        let diff_memtrie_trie = memtrie_state.symmetric_difference(&trie_state);
-        let diff = diff_memtrie_flat_store.chain(diff_memtrie_trie);
-        if diff.clone().count() == 0 {
-            continue;
+        let mut keys_per_account: BTreeMap<AccountId, usize> = BTreeMap::new();
+        for item in trie.lock_for_iter().iter().unwrap() {
+            let (key, _) = item.unwrap();
+            if let Some(account_id) = parse_account_id_from_raw_key(&key).unwrap() {
+                *keys_per_account.entry(account_id).or_default() += 1;
+            }
         }
-        for (key, value) in diff {
-            tracing::error!(target: "test", shard=?child_shard_uid, key=?key, ?value, "Difference in state between trie, memtrie and flat store!");
+        tracing::debug!(target: "test", ?child_shard_uid, ?keys_per_account, "trie keys per account");
+        for (account_id, num_keys) in keys_per_account {
+            let expected_child =
+                if &account_id < boundary_account { left_child_uid } else { right_child_uid };
+            assert_eq!(
+                child_shard_uid, expected_child,
+                "{num_keys} trie keys of {account_id} are stored in child shard {child_shard_uid} instead of {expected_child}"
+            );
         }
-        assert!(false, "trie, memtrie and flat store state mismatch!");
     }
 }
 
-/// Base setup to check sanity of Resharding V3.
-/// TODO(#11881): add the following scenarios:
-/// - Nodes must not track all shards. State sync must succeed.
-/// - Set up chunk validator-only nodes. State witness must pass validation.
-/// - Consistent tx load. All txs must succeed.
-/// - Delayed receipts, congestion control computation.
-/// - Cross-shard receipts of all kinds, crossing resharding boundary.
-/// - Shard layout v2 -> v2 transition.
+/// Asserts that for each child shard:
+/// MemTrie, FlatState and DiskTrie all contain the same key-value pairs.
+fn assert_state_sanity_for_children_shard(parent_shard_uid: ShardUId, client: &Client) {
+    let final_head = client.chain.final_head().unwrap();
+
+    for child_shard_uid in client
+        .epoch_manager
+        .get_shard_layout(&final_head.epoch_id)
+        .unwrap()
+        .get_children_shards_uids(parent_shard_uid.shard_id())
+        .unwrap()
+    {
+        assert_state_sanity(client, child_shard_uid, &final_head.last_block_hash);
+    }
+}
+
+/// Asserts that for the parent shard, at the given block before resharding:
+/// MemTrie, FlatState and DiskTrie all contain the same key-value pairs.
+fn assert_state_sanity_for_parent_before_resharding(
+    parent_shard_uid: ShardUId,
+    client: &Client,
+    block_hash: &CryptoHash,
+) {
+    assert_state_sanity(client, parent_shard_uid, block_hash);
+}
+
+/// Maximum number of differing keys listed in a `TrieComparisonReport`. The total number of
+/// differences is always reported.
+const MAX_REPORTED_TRIE_DIFFERENCES: usize = 100;
+
+/// A key whose value is not the same in MemTrie, DiskTrie and FlatState. `None` means that the
+/// key is missing from the corresponding storage.
+#[derive(Debug)]
+struct TrieDifference {
+    key: Vec<u8>,
+    memtrie_value: Option<Vec<u8>>,
+    disk_trie_value: Option<Vec<u8>>,
+    flat_store_value: Option<Vec<u8>>,
+}
+
+/// Result of comparing MemTrie, DiskTrie and FlatState of a shard at a given block.
+#[derive(Debug)]
+struct TrieComparisonReport {
+    shard_uid: ShardUId,
+    block_hash: CryptoHash,
+    num_keys: usize,
+    num_differences: usize,
+    /// At most `MAX_REPORTED_TRIE_DIFFERENCES` differing keys, in key order.
+    differences: Vec<TrieDifference>,
+}
+
+impl TrieComparisonReport {
+    fn is_consistent(&self) -> bool {
+        self.num_differences == 0
+    }
+
+    /// Panics with the full list of reported differences if the storages don't match.
+    fn assert_consistent(&self) {
+        assert!(self.is_consistent(), "{self}");
+    }
+}
+
+impl std::fmt::Display for TrieComparisonReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "shard {} at block {}: {} of {} keys differ between memtrie, disk trie and flat store",
+            self.shard_uid, self.block_hash, self.num_differences, self.num_keys
+        )?;
+        for diff in &self.differences {
+            writeln!(
+                f,
+                "  key {}: memtrie={} disk_trie={} flat_store={}",
+                hex::encode(&diff.key),
+                AbbrBytes(diff.memtrie_value.as_deref()),
+                AbbrBytes(diff.disk_trie_value.as_deref()),
+                AbbrBytes(diff.flat_store_value.as_deref()),
+            )?;
+        }
+        if self.num_differences > self.differences.len() {
+            writeln!(f, "  ... and {} more", self.num_differences - self.differences.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Failure to read one of the storages being compared.
+#[derive(Debug)]
+enum ComparisonError {
+    Chain(near_chain::Error),
+    Storage(StorageError),
+    MissingFlatStorage(ShardUId),
+}
+
+impl std::fmt::Display for ComparisonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Chain(err) => write!(f, "chain error: {err}"),
+            Self::Storage(err) => write!(f, "storage error: {err}"),
+            Self::MissingFlatStorage(shard_uid) => {
+                write!(f, "flat storage for shard {shard_uid} is not available")
+            }
+        }
+    }
+}
+
+impl From<near_chain::Error> for ComparisonError {
+    fn from(err: near_chain::Error) -> Self {
+        Self::Chain(err)
+    }
+}
+
+impl From<StorageError> for ComparisonError {
+    fn from(err: StorageError) -> Self {
+        Self::Storage(err)
+    }
+}
+
+/// Compares MemTrie, FlatState and DiskTrie of the given shard at the state after `block_hash`
+/// and reports every key whose value is not the same in all three.
+fn assert_memtrie_equals_disk_trie(
+    shard_uid: ShardUId,
+    client: &Client,
+    block_hash: &CryptoHash,
+) -> Result<TrieComparisonReport, ComparisonError> {
+    let block_header = client.chain.get_block_header(block_hash)?;
+    let prev_block_hash = block_header.prev_hash();
+
+    let memtrie = get_memtrie_for_shard(client, &shard_uid, prev_block_hash);
+    let memtrie_state = memtrie.lock_for_iter().iter()?.collect::<Result<BTreeMap<_, _>, _>>()?;
+
+    let state_root = *client.chain.get_chunk_extra(prev_block_hash, &shard_uid)?.state_root();
+
+    // To get a view on disk tries we can leverage the fact that get_view_trie_for_shard() never
+    // uses memtries.
+    let trie = client.runtime_adapter.get_view_trie_for_shard(
+        shard_uid.shard_id(),
+        prev_block_hash,
+        state_root,
+    )?;
+    assert!(!trie.has_memtries());
+    let trie_state = trie.lock_for_iter().iter()?.collect::<Result<BTreeMap<_, _>, _>>()?;
+
+    let flat_store_chunk_view = client
+        .chain
+        .runtime_adapter
+        .get_flat_storage_manager()
+        .chunk_view(shard_uid, *block_hash)
+        .ok_or(ComparisonError::MissingFlatStorage(shard_uid))?;
+    let trie_store = client.chain.chain_store().store().trie_store();
+    let mut flat_store_state = BTreeMap::new();
+    for item in flat_store_chunk_view.iter_range(None, None) {
+        let (key, value) = item.map_err(StorageError::from)?;
+        let value = match value {
+            FlatStateValue::Ref(value) => trie_store.get(shard_uid, &value.hash)?.to_vec(),
+            FlatStateValue::Inlined(data) => data,
+        };
+        flat_store_state.insert(key, value);
+    }
+
+    let all_keys: BTreeSet<&Vec<u8>> =
+        memtrie_state.keys().chain(trie_state.keys()).chain(flat_store_state.keys()).collect();
+    let mut report = TrieComparisonReport {
+        shard_uid,
+        block_hash: *block_hash,
+        num_keys: all_keys.len(),
+        num_differences: 0,
+        differences: vec![],
+    };
+    for key in all_keys {
+        let memtrie_value = memtrie_state.get(key);
+        let disk_trie_value = trie_state.get(key);
+        let flat_store_value = flat_store_state.get(key);
+        if memtrie_value == disk_trie_value && memtrie_value == flat_store_value {
+            continue;
+        }
+        report.num_differences += 1;
+        if report.differences.len() < MAX_REPORTED_TRIE_DIFFERENCES {
+            report.differences.push(TrieDifference {
+                key: key.clone(),
+                memtrie_value: memtrie_value.cloned(),
+                disk_trie_value: disk_trie_value.cloned(),
+                flat_store_value: flat_store_value.cloned(),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Asserts that MemTrie, FlatState and DiskTrie of the given shard all contain the same
+/// key-value pairs at the state after `block_hash`.
+fn assert_state_sanity(client: &Client, shard_uid: ShardUId, block_hash: &CryptoHash) {
+    let report = assert_memtrie_equals_disk_trie(shard_uid, client, block_hash)
+        .unwrap_or_else(|err| panic!("failed to compare state of shard {shard_uid}: {err}"));
+    report.assert_consistent();
+}
+
+/// Whether the witness is for a chunk of the resharding epoch, or proves the split of the parent
+/// state as an implicit transition.
+fn is_resharding_state_witness(
+    epoch_manager: &dyn EpochManagerAdapter,
+    witness: &ChunkStateWitness,
+) -> bool {
+    if !witness.implicit_transitions.is_empty() {
+        return true;
+    }
+    let shard_layout = epoch_manager.get_shard_layout(&witness.epoch_id).unwrap();
+    let next_epoch_id = epoch_manager
+        .get_next_epoch_id_from_prev_block(witness.chunk_header.prev_block_hash())
+        .unwrap();
+    shard_layout != epoch_manager.get_shard_layout(&next_epoch_id).unwrap()
+}
+
+/// Returns a loop action that validates the recorded resharding state witnesses with the chain of
+/// the client, the same way chunk validators do. A witness is validated as soon as the client
+/// knows its previous block, other witnesses are dropped.
+fn validate_recorded_resharding_state_witnesses(
+    recorded_witnesses: Arc<Mutex<Vec<ChunkStateWitness>>>,
+    num_validated: Rc<Cell<usize>>,
+) -> LoopActionFn {
+    Box::new(
+        move |_: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let mut recorded_witnesses = recorded_witnesses.lock().unwrap();
+            let mut pending_witnesses = vec![];
+            for witness in recorded_witnesses.drain(..) {
+                if client.chain.get_block_header(witness.chunk_header.prev_block_hash()).is_err() {
+                    pending_witnesses.push(witness);
+                    continue;
+                }
+                if !is_resharding_state_witness(client.epoch_manager.as_ref(), &witness) {
+                    continue;
+                }
+                let chunk_hash = witness.chunk_header.chunk_hash();
+                let pre_validation_output = pre_validate_chunk_state_witness(
+                    &witness,
+                    &client.chain,
+                    client.epoch_manager.as_ref(),
+                    client.runtime_adapter.as_ref(),
+                )
+                .unwrap_or_else(|err| {
+                    panic!("pre-validation of the witness for chunk {chunk_hash:?} failed: {err}")
+                });
+                validate_chunk_state_witness(
+                    witness,
+                    pre_validation_output,
+                    client.epoch_manager.as_ref(),
+                    client.runtime_adapter.as_ref(),
+                    &MainStateTransitionCache::default(),
+                )
+                .unwrap_or_else(|err| {
+                    panic!("validation of the witness for chunk {chunk_hash:?} failed: {err}")
+                });
+                num_validated.set(num_validated.get() + 1);
+            }
+            *recorded_witnesses = pending_witnesses;
+        },
+    )
+}
+
+/// Returns a loop action that checks the state sanity of the parent shard at the resharding
+/// block, i.e. before the split occurs.
+fn check_state_sanity_for_parent_before_resharding(parent_shard_uid: ShardUId) -> LoopActionFn {
+    let done = Cell::new(false);
+    Box::new(
+        move |_: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            if done.get() {
+                return;
+            }
+
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+            if !next_block_has_new_shard_layout(client.epoch_manager.clone(), &tip) {
+                return;
+            }
+
+            assert_state_sanity_for_parent_before_resharding(
+                parent_shard_uid,
+                client,
+                &tip.last_block_hash,
+            );
+            done.set(true);
+        },
+    )
+}
+
+/// Returns a loop action that looks for the clients which didn't track the parent shard in the
+/// resharding epoch but track one of its children right after resharding. Those clients have to
+/// state sync the children during the resharding epoch instead of splitting the parent. Once the
+/// final head is in the new epoch, checks that they track a child shard and that the state of each
+/// tracked child is the same across memtrie, flat storage and disk trie, like
+/// [`assert_state_sanity_for_children_shard`] does for a client tracking all children. The number
+/// of checked clients is stored in `num_synced_clients`.
+fn check_state_sync_during_resharding(
+    parent_shard_uid: ShardUId,
+    num_synced_clients: Rc<Cell<usize>>,
+) -> LoopActionFn {
+    const FINAL_BLOCKS_AFTER_RESHARDING: u64 = 3;
+
+    let resharding_height = Cell::new(None);
+    let syncing_clients = RefCell::new(vec![]);
+    let done = Cell::new(false);
+    Box::new(
+        move |node_datas: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            if done.get() {
+                return;
+            }
+
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+            let parent_shard_id = parent_shard_uid.shard_id();
+            let Some(height) = resharding_height.get() else {
+                if !next_block_has_new_shard_layout(client.epoch_manager.clone(), &tip) {
+                    return;
+                }
+                resharding_height.set(Some(tip.height));
+                for (index, node_data) in node_datas.iter().enumerate() {
+                    let client =
+                        &test_loop_data.get(&node_data.client_sender.actor_handle()).client;
+                    let signer = client.validator_signer.get();
+                    let account_id = signer.as_ref().map(|s| s.validator_id());
+                    let tracks_parent = client.shard_tracker.care_about_shard(
+                        account_id,
+                        &tip.prev_block_hash,
+                        parent_shard_id,
+                        true,
+                    );
+                    let will_track_child = client.shard_tracker.will_care_about_shard(
+                        account_id,
+                        &tip.prev_block_hash,
+                        parent_shard_id,
+                        true,
+                    );
+                    if !tracks_parent && will_track_child {
+                        syncing_clients.borrow_mut().push(index);
+                    }
+                }
+                tracing::info!(target: "test", syncing_clients=?syncing_clients.borrow(), "clients state syncing children during resharding");
+                return;
+            };
+
+            let final_head = client.chain.final_head().unwrap();
+            if final_head.height < height + FINAL_BLOCKS_AFTER_RESHARDING {
+                return;
+            }
+            done.set(true);
+            num_synced_clients.set(syncing_clients.borrow().len());
+
+            for index in syncing_clients.borrow().iter() {
+                let client =
+                    &test_loop_data.get(&node_datas[*index].client_sender.actor_handle()).client;
+                let signer = client.validator_signer.get();
+                let account_id = signer.as_ref().map(|s| s.validator_id());
+                let final_head = client.chain.final_head().unwrap();
+                let children_shard_uids = client
+                    .epoch_manager
+                    .get_shard_layout(&final_head.epoch_id)
+                    .unwrap()
+                    .get_children_shards_uids(parent_shard_id)
+                    .unwrap();
+                let tracked_children = children_shard_uids
+                    .into_iter()
+                    .filter(|child_shard_uid| {
+                        client.shard_tracker.care_about_shard(
+                            account_id,
+                            &final_head.prev_block_hash,
+                            child_shard_uid.shard_id(),
+                            true,
+                        )
+                    })
+                    .collect_vec();
+                assert!(
+                    !tracked_children.is_empty(),
+                    "client {index} doesn't track any child of {parent_shard_uid} after state sync"
+                );
+                for child_shard_uid in tracked_children {
+                    assert_state_sanity(client, child_shard_uid, &final_head.last_block_hash);
+                }
+            }
+        },
+    )
+}
+
+/// Returns a loop action that, a couple of blocks after resharding, queries the balance and
+/// storage usage of each of `account_ids` through the view client and checks that they match
+/// the account state read directly from the trie of the shard that the account belongs to.
+fn check_view_client_queries_after_resharding(account_ids: Vec<AccountId>) -> LoopActionFn {
+    const BLOCKS_AFTER_RESHARDING: u64 = 2;
+
+    let resharding_height = Cell::new(None);
+    let done = Cell::new(false);
+    Box::new(
+        move |node_datas: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            if done.get() {
+                return;
+            }
+
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+            let Some(height) = resharding_height.get() else {
+                if next_block_has_new_shard_layout(client.epoch_manager.clone(), &tip) {
+                    resharding_height.set(Some(tip.height));
+                }
+                return;
+            };
+            if tip.height < height + BLOCKS_AFTER_RESHARDING {
+                return;
+            }
+            done.set(true);
+
+            let shard_layout = client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            let expected_accounts = account_ids
+                .iter()
+                .map(|account_id| {
+                    let shard_uid = account_id_to_shard_uid(account_id, &shard_layout);
+                    let trie = get_memtrie_for_shard(client, &shard_uid, &tip.last_block_hash);
+                    let account = get_account(&trie, account_id).unwrap().unwrap();
+                    (account_id.clone(), account)
+                })
+                .collect_vec();
+
+            let view_client_handle = node_datas[0].view_client_sender.actor_handle();
+            let view_client = test_loop_data.get_mut(&view_client_handle);
+            for (account_id, account) in expected_accounts {
+                let query = Query::new(
+                    BlockReference::BlockId(BlockId::Hash(tip.last_block_hash)),
+                    QueryRequest::ViewAccount { account_id: account_id.clone() },
+                );
+                let response = view_client.handle(query).unwrap();
+                let QueryResponseKind::ViewAccount(account_view) = response.kind else {
+                    panic!("unexpected query response for {account_id}");
+                };
+                tracing::debug!(target: "test", ?account_id, ?account_view, "view client account");
+                assert_eq!(account_view.amount, account.amount());
+                assert_eq!(account_view.storage_usage, account.storage_usage());
+            }
+        },
+    )
+}
+
+/// Returns a loop action that checks the chunk producers of the first epoch after resharding.
+/// Each of the `children_shard_uids` must have at least one chunk producer and no chunk producer
+/// may be assigned to more shards than needed to cover all shards of the new layout with the
+/// chunk producers of the epoch.
+fn check_chunk_producers_after_resharding(children_shard_uids: Vec<ShardUId>) -> LoopActionFn {
+    let resharding_height = Cell::new(None);
+    let done = Cell::new(false);
+    Box::new(
+        move |_: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            if done.get() {
+                return;
+            }
+
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+            let Some(height) = resharding_height.get() else {
+                if next_block_has_new_shard_layout(client.epoch_manager.clone(), &tip) {
+                    resharding_height.set(Some(tip.height));
+                }
+                return;
+            };
+            if tip.height <= height {
+                return;
+            }
+            done.set(true);
+
+            let epoch_manager = &client.epoch_manager;
+            let shard_layout = epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            for shard_uid in &children_shard_uids {
+                assert!(
+                    shard_layout.shard_uids().contains(shard_uid),
+                    "child shard {shard_uid} is not in the new shard layout"
+                );
+                let chunk_producers = epoch_manager
+                    .get_epoch_chunk_producers_for_shard(&tip.epoch_id, shard_uid.shard_id())
+                    .unwrap();
+                tracing::debug!(target: "test", ?shard_uid, ?chunk_producers, "child shard chunk producers");
+                assert!(
+                    !chunk_producers.is_empty(),
+                    "no chunk producers for child shard {shard_uid}"
+                );
+            }
+
+            let mut shards_per_chunk_producer: HashMap<AccountId, usize> = HashMap::new();
+            for shard_id in shard_layout.shard_ids() {
+                let chunk_producers = epoch_manager
+                    .get_epoch_chunk_producers_for_shard(&tip.epoch_id, shard_id)
+                    .unwrap();
+                for chunk_producer in chunk_producers {
+                    *shards_per_chunk_producer.entry(chunk_producer).or_default() += 1;
+                }
+            }
+            let num_chunk_producers =
+                epoch_manager.get_epoch_chunk_producers(&tip.epoch_id).unwrap().len();
+            let max_shards_per_chunk_producer =
+                shard_layout.shard_ids().count().div_ceil(num_chunk_producers);
+            for (chunk_producer, num_shards) in shards_per_chunk_producer {
+                assert!(
+                    num_shards <= max_shards_per_chunk_producer,
+                    "{chunk_producer} produces chunks for {num_shards} shards, expected at most {max_shards_per_chunk_producer}"
+                );
+            }
+        },
+    )
+}
+
+/// Asserts that at some epoch boundary the set of chunk producers of at least one shard, which
+/// exists on both sides of the boundary, changed. Used to check that shard assignment shuffling
+/// actually took effect. Heights whose block is no longer known to `client` are skipped.
+fn assert_shard_assignment_changed_at_epoch_boundary(
+    client: &Client,
+    trace: &ShardAssignmentTrace,
+) {
+    let mut assignments: Vec<(EpochId, BTreeMap<ShardId, BTreeSet<AccountId>>)> = vec![];
+    for (height, shard_id, chunk_producer) in trace {
+        let Ok(header) = client.chain.get_block_header_by_height(*height) else {
+            continue;
+        };
+        let epoch_id = *header.epoch_id();
+        if assignments.last().map_or(true, |(last_epoch_id, _)| *last_epoch_id != epoch_id) {
+            assignments.push((epoch_id, BTreeMap::new()));
+        }
+        let (_, assignment) = assignments.last_mut().unwrap();
+        assignment.entry(*shard_id).or_default().insert(chunk_producer.clone());
+    }
+    tracing::debug!(target: "test", ?assignments, "shard assignments per epoch");
+
+    let changed = assignments.windows(2).any(|epochs| {
+        let (prev, next) = (&epochs[0].1, &epochs[1].1);
+        next.iter().any(|(shard_id, producers)| {
+            prev.get(shard_id).is_some_and(|prev_producers| prev_producers != producers)
+        })
+    });
+    assert!(changed, "shard assignment never changed at an epoch boundary: {assignments:?}");
+}
+
+/// Returns a loop action that checks the bandwidth requests in the chunk headers of the first
+/// block after resharding. Every chunk must request bandwidth for each of the `expected_children`
+/// it has enough buffered receipts for, and none of the chunks may request bandwidth for the
+/// parent shard, which doesn't exist anymore.
+fn check_bandwidth_requests_coverage_after_resharding(
+    parent_shard_uid: ShardUId,
+    expected_children: Vec<ShardUId>,
+) -> LoopActionFn {
+    let resharding_block_hash = Cell::new(None);
+    let done = Cell::new(false);
+    Box::new(
+        move |_: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            if done.get() {
+                return;
+            }
+
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+            if next_block_has_new_shard_layout(client.epoch_manager.clone(), &tip) {
+                resharding_block_hash.set(Some(tip.last_block_hash));
+                return;
+            }
+            if resharding_block_hash.get() != Some(tip.prev_block_hash) {
+                return;
+            }
+            done.set(true);
+
+            let epoch_manager = &client.epoch_manager;
+            let shard_layout = epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            let protocol_version = epoch_manager.get_epoch_protocol_version(&tip.epoch_id).unwrap();
+            let runtime_config =
+                client.runtime_adapter.get_runtime_config(protocol_version).unwrap();
+            let num_shards = NonZeroU64::new(shard_layout.num_shards()).unwrap();
+            let base_bandwidth =
+                BandwidthSchedulerParams::new(num_shards, &runtime_config).base_bandwidth;
+            let parent_shard_id: u16 = parent_shard_uid.shard_id().into();
+
+            let block = client.chain.get_block(&tip.last_block_hash).unwrap();
+            for chunk in block.chunks().iter_raw() {
+                if chunk.height_included() != block.header().height() {
+                    continue;
+                }
+                let Some(BandwidthRequests::V1(bandwidth_requests)) = chunk.bandwidth_requests()
+                else {
+                    // The bandwidth scheduler is not enabled, there is nothing to check.
+                    return;
+                };
+                let requested_shards: HashSet<u16> =
+                    bandwidth_requests.requests.iter().map(|request| request.to_shard).collect();
+                let shard_uid = ShardUId::from_shard_id_and_layout(chunk.shard_id(), &shard_layout);
+                tracing::debug!(target: "test", ?shard_uid, ?requested_shards, "bandwidth requests after resharding");
+                assert!(
+                    !requested_shards.contains(&parent_shard_id),
+                    "shard {shard_uid} requested bandwidth for the parent shard {parent_shard_uid}"
+                );
+
+                // The requests in the chunk header are generated from the state at the
+                // resharding block.
+                let memtrie = get_memtrie_for_shard(client, &shard_uid, &tip.prev_block_hash);
+                let indices: BufferedReceiptIndices =
+                    get(&memtrie, &TrieKey::BufferedReceiptIndices).unwrap().unwrap_or_default();
+                for child_shard_uid in &expected_children {
+                    let child_shard_id = child_shard_uid.shard_id();
+                    let buffer_len =
+                        indices.shard_buffers.get(&child_shard_id).map_or(0, |buffer| buffer.len());
+                    if buffer_len == 0 {
+                        continue;
+                    }
+                    // Without full metadata a basic request is always made, otherwise a request is
+                    // only made when the buffered receipts don't fit in the base bandwidth.
+                    let metadata = ReceiptGroupsQueue::load(&memtrie, child_shard_id).unwrap();
+                    let needs_request = match metadata {
+                        Some(metadata) if metadata.total_receipts_num() == buffer_len => {
+                            metadata.total_size() > base_bandwidth
+                        }
+                        _ => true,
+                    };
+                    let child_shard_id: u16 = child_shard_id.into();
+                    assert!(
+                        !needs_request || requested_shards.contains(&child_shard_id),
+                        "shard {shard_uid} didn't request bandwidth for child shard {child_shard_uid}"
+                    );
+                }
+            }
+        },
+    )
+}
+
+/// Returns a loop action that records the height at which each cross-shard transaction gets
+/// included in a chunk and asserts that it reaches a final execution status within `max_blocks`
+/// blocks, i.e. that the receipts it produces are delivered to the receiver shard in time, also
+/// across the resharding boundary.
+fn check_cross_shard_receipt_delivery_latency(max_blocks: BlockHeightDelta) -> LoopActionFn {
+    let latest_height = Cell::new(0);
+    let pending_txs: RefCell<Vec<(CryptoHash, BlockHeight)>> = RefCell::new(vec![]);
+    Box::new(
+        move |_: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+
+            // Run this action only once at every block height.
+            if latest_height.get() == tip.height {
+                return;
+            }
+            latest_height.set(tip.height);
+
+            // Record the cross-shard transactions included in the new chunks of the tip.
+            let block = client.chain.get_block(&tip.last_block_hash).unwrap();
+            let shard_layout = client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            let mut pending = pending_txs.borrow_mut();
+            for chunk_header in block.chunks().iter_raw() {
+                if !chunk_header.is_new_chunk(tip.height) {
+                    continue;
+                }
+                let Ok(chunk) = client.chain.get_chunk(&chunk_header.chunk_hash()) else {
+                    continue;
+                };
+                for tx in chunk.transactions() {
+                    let signer_shard_id =
+                        account_id_to_shard_id(tx.transaction.signer_id(), &shard_layout);
+                    let receiver_shard_id =
+                        account_id_to_shard_id(tx.transaction.receiver_id(), &shard_layout);
+                    if signer_shard_id != receiver_shard_id {
+                        pending.push((tx.get_hash(), tip.height));
+                    }
+                }
+            }
+
+            // Drop the transactions that reached a final status and check the others are not late.
+            pending.retain(|(tx_hash, included_height)| {
+                let status = client
+                    .chain
+                    .get_partial_transaction_result(tx_hash)
+                    .map_or(FinalExecutionStatus::NotStarted, |outcome| outcome.status);
+                let is_final = matches!(
+                    status,
+                    FinalExecutionStatus::SuccessValue(_) | FinalExecutionStatus::Failure(_)
+                );
+                if is_final {
+                    tracing::debug!(target: "test", ?tx_hash, included_height, height=tip.height, "cross-shard transaction finished");
+                    return false;
+                }
+                assert!(
+                    tip.height - included_height <= max_blocks,
+                    "cross-shard transaction {tx_hash} included at height {included_height} is still {status:?} at height {}",
+                    tip.height
+                );
+                true
+            });
+        },
+    )
+}
+
+/// Returns a loop action that, starting `check_after_block` blocks after the resharding block,
+/// asserts at every new chunk of `shard_uid` that the congestion level of the shard is below
+/// `threshold`, i.e. that splitting the shard relieved its congestion.
+fn assert_congestion_level_below_threshold(
+    shard_uid: ShardUId,
+    threshold: f64,
+    check_after_block: BlockHeightDelta,
+) -> LoopActionFn {
+    let resharding_height = Cell::new(None);
+    let latest_height = Cell::new(0);
+    Box::new(
+        move |_: &[TestData],
+              test_loop_data: &mut TestLoopData,
+              client_handle: TestLoopDataHandle<ClientActorInner>| {
+            let client = &test_loop_data.get(&client_handle).client;
+            let tip = client.chain.head().unwrap();
+
+            // Run this action only once at every block height.
+            if latest_height.get() == tip.height {
+                return;
+            }
+            latest_height.set(tip.height);
+
+            if next_block_has_new_shard_layout(client.epoch_manager.clone(), &tip) {
+                resharding_height.set(Some(tip.height));
+                return;
+            }
+            let Some(height) = resharding_height.get() else {
+                return;
+            };
+            if tip.height < height + check_after_block {
+                return;
+            }
+
+            let shard_layout = client.epoch_manager.get_shard_layout(&tip.epoch_id).unwrap();
+            if !shard_layout.shard_uids().contains(&shard_uid) {
+                return;
+            }
+            let protocol_version =
+                client.epoch_manager.get_epoch_protocol_version(&tip.epoch_id).unwrap();
+            let runtime_config =
+                client.runtime_adapter.get_runtime_config(protocol_version).unwrap();
+            let block = client.chain.get_block(&tip.last_block_hash).unwrap();
+            for chunk in block.chunks().iter_raw() {
+                if chunk.shard_id() != shard_uid.shard_id() || !chunk.is_new_chunk(tip.height) {
+                    continue;
+                }
+                let congestion_info =
+                    chunk.congestion_info().expect("chunk header must have congestion info");
+                let congestion_level = CongestionControl::new(
+                    runtime_config.congestion_control_config,
+                    congestion_info,
+                    0,
+                )
+                .congestion_level();
+                tracing::debug!(target: "test", ?shard_uid, height=tip.height, congestion_level, "congestion level after resharding");
+                assert!(
+                    congestion_level < threshold,
+                    "congestion level of shard {shard_uid} at height {} is {congestion_level}, expected below {threshold}",
+                    tip.height
+                );
+            }
+        },
+    )
+}
+
+/// Returns the shard layout the resharding tests start with and the layout after splitting the
+/// shard of `account6` at that account.
+fn resharding_v3_shard_layouts() -> (ShardLayout, ShardLayout) {
+    let boundary_accounts = vec!["account1".parse().unwrap(), "account3".parse().unwrap()];
+    let base_shard_layout = ShardLayout::multi_shard_custom(boundary_accounts, 3);
+    let new_boundary_account: AccountId = "account6".parse().unwrap();
+    let parent_shard_uid = account_id_to_shard_uid(&new_boundary_account, &base_shard_layout);
+    let new_shard_layout =
+        ShardLayout::split_shard_uid(parent_shard_uid, new_boundary_account, &base_shard_layout)
+            .unwrap();
+    (base_shard_layout, new_shard_layout)
+}
+
+/// Generates a shard layout with `num_shards` shards over `accounts`, and the boundary account at
+/// which one of its shards is split. The boundaries are spread evenly over the accounts.
+fn generate_resharding_v3_base_shard_layout(
+    accounts: &[AccountId],
+    num_shards: usize,
+) -> (ShardLayout, AccountId) {
+    let mut accounts = accounts.to_vec();
+    accounts.sort();
+    // The first account can't be a boundary, the first shard would be empty.
+    let candidates = &accounts[1..];
+    let (mut boundary_accounts, split_index) = if num_shards <= candidates.len() {
+        let boundary_accounts = (0..num_shards)
+            .map(|i| candidates[i * candidates.len() / num_shards].clone())
+            .collect_vec();
+        (boundary_accounts, num_shards / 2)
+    } else {
+        // Every account but the first one is already a boundary. Split the last shard after the
+        // last account.
+        let mut boundary_accounts = candidates.to_vec();
+        boundary_accounts.push(format!("{}0", accounts.last().unwrap()).parse().unwrap());
+        (boundary_accounts, num_shards - 1)
+    };
+    let new_boundary_account = boundary_accounts.remove(split_index);
+    (ShardLayout::multi_shard_custom(boundary_accounts, 3), new_boundary_account)
+}
+
+/// Base setup to check sanity of Resharding V3.
+/// TODO(#11881): add the following scenarios:
+/// - Nodes must not track all shards. State sync must succeed.
+/// - Set up chunk validator-only nodes. State witness must pass validation.
+/// - Consistent tx load. All txs must succeed.
+/// - Delayed receipts, congestion control computation.
+/// - Cross-shard receipts of all kinds, crossing resharding boundary.
+/// - Shard layout v2 -> v2 transition.
 /// - Shard layout can be taken from mainnet.
-fn test_resharding_v3_base(params: TestReshardingParameters) {
+fn test_resharding_v3_base(mut params: TestReshardingParameters) {
+    if let Err(conflicts) = params.validate() {
+        panic!("invalid resharding test parameters: {}", conflicts.iter().join(", "));
+    }
     if !ProtocolFeature::SimpleNightshadeV4.enabled(PROTOCOL_VERSION) {
         return;
     }
@@ -594,28 +1928,47 @@ fn test_resharding_v3_base(params: TestReshardingParameters) {
         base_epoch_config.chunk_validator_only_kickout_threshold = 0;
     }
 
-    let boundary_accounts = vec!["account1".parse().unwrap(), "account3".parse().unwrap()];
-    let base_shard_layout = ShardLayout::multi_shard_custom(boundary_accounts, 3);
-
+    let (base_shard_layout, new_boundary_account) = match params.num_shards {
+        Some(num_shards) => generate_resharding_v3_base_shard_layout(&params.accounts, num_shards),
+        None => (resharding_v3_shard_layouts().0, "account6".parse().unwrap()),
+    };
     base_epoch_config.shard_layout = base_shard_layout.clone();
-    let new_boundary_account = "account6".parse().unwrap();
     let mut epoch_config = base_epoch_config.clone();
     let parent_shard_uid = account_id_to_shard_uid(&new_boundary_account, &base_shard_layout);
-
-    epoch_config.shard_layout =
-        ShardLayout::derive_shard_layout(&base_shard_layout, new_boundary_account);
+    epoch_config.shard_layout = ShardLayout::split_shard_uid(
+        parent_shard_uid,
+        new_boundary_account.clone(),
+        &base_shard_layout,
+    )
+    .unwrap();
     tracing::info!(target: "test", ?base_shard_layout, new_shard_layout=?epoch_config.shard_layout, "shard layout");
 
     let expected_num_shards = epoch_config.shard_layout.shard_ids().count();
-    let epoch_config_store = EpochConfigStore::test(BTreeMap::from_iter(vec![
-        (base_protocol_version, Arc::new(base_epoch_config)),
-        (base_protocol_version + 1, Arc::new(epoch_config)),
-    ]));
+    let children_shard_ids =
+        epoch_config.shard_layout.get_children_shards_ids(parent_shard_uid.shard_id()).unwrap();
+    // The parent state can only be checked by a client that tracks it and has its memtrie loaded.
+    if params.track_all_shards && params.load_mem_tries_for_tracked_shards {
+        params.loop_actions.push(check_state_sanity_for_parent_before_resharding(parent_shard_uid));
+        let children_shard_uids = epoch_config
+            .shard_layout
+            .get_children_shards_uids(parent_shard_uid.shard_id())
+            .unwrap();
+        params.loop_actions.push(check_bandwidth_requests_coverage_after_resharding(
+            parent_shard_uid,
+            children_shard_uids,
+        ));
+    }
 
+    let base_epoch_config = Arc::new(base_epoch_config);
+    let epoch_config = Arc::new(epoch_config);
+    let expected_epoch_config_store = EpochConfigStore::test(BTreeMap::from([
+        (base_protocol_version, base_epoch_config.clone()),
+        (base_protocol_version + 1, epoch_config.clone()),
+    ]));
     let mut genesis_builder = TestGenesisBuilder::new();
     genesis_builder
         .genesis_time_from_clock(&builder.clock())
-        .shard_layout(base_shard_layout)
+        .resharding_config(base_epoch_config, epoch_config, base_protocol_version + 1)
         .protocol_version(base_protocol_version)
         .epoch_length(params.epoch_length)
         .validators_desired_roles(
@@ -627,45 +1980,90 @@ fn test_resharding_v3_base(params: TestReshardingParameters) {
             &[],
         );
     for account in &params.accounts {
-        genesis_builder.add_user_account_simple(account.clone(), params.initial_balance);
+        if params.deploy_test_contract.as_ref() == Some(account) {
+            genesis_builder.add_user_account_with_contract(
+                account.clone(),
+                params.initial_balance,
+                near_test_contracts::rs_contract().to_vec(),
+            );
+        } else {
+            genesis_builder.add_user_account_simple(account.clone(), params.initial_balance);
+        }
     }
-    let (genesis, _) = genesis_builder.build();
+    if params.limit_outgoing_gas {
+        genesis_builder.congestion_control_max_outgoing_gas(100 * TGAS);
+    }
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+    let epoch_config_diffs =
+        EpochConfigStore::diff(&expected_epoch_config_store, &epoch_config_store);
+    assert!(
+        epoch_config_diffs.is_empty(),
+        "unexpected epoch configs: {}",
+        epoch_config_diffs.iter().join(", ")
+    );
 
     if params.track_all_shards {
         builder = builder.track_all_shards();
     }
 
-    if params.limit_outgoing_gas {
-        let mut runtime_config = RuntimeConfig::test();
-        runtime_config.congestion_control_config.max_outgoing_gas = 100 * TGAS;
-        runtime_config.congestion_control_config.min_outgoing_gas = 100 * TGAS;
-        let runtime_config_store = RuntimeConfigStore::with_one_config(runtime_config);
-        builder = builder.runtime_config_store(runtime_config_store);
+    // The first witnesses of the children shards must prove the split of the parent state as an
+    // implicit transition, so record the shards whose witnesses contain implicit transitions.
+    let shards_with_implicit_transitions = Arc::new(Mutex::new(HashSet::new()));
+    let recorded_shards = shards_with_implicit_transitions.clone();
+    let recorded_witnesses = Arc::new(Mutex::new(vec![]));
+    let witnesses_to_validate = recorded_witnesses.clone();
+    let validate_witnesses = params.validate_resharding_state_witnesses;
+    builder = builder.with_chunk_validation_hook(Box::new(move |witness: &ChunkStateWitness| {
+        if !witness.implicit_transitions.is_empty() {
+            recorded_shards.lock().unwrap().insert(witness.chunk_header.shard_id());
+        }
+        if validate_witnesses {
+            witnesses_to_validate.lock().unwrap().push(witness.clone());
+        }
+        witness.clone()
+    }));
+    let num_validated_witnesses = Rc::new(Cell::new(0));
+    if validate_witnesses {
+        params.loop_actions.push(validate_recorded_resharding_state_witnesses(
+            recorded_witnesses,
+            num_validated_witnesses.clone(),
+        ));
+    }
+    if params.shuffle_shard_assignment_for_chunk_producers {
+        builder = builder.with_shard_assignment_trace();
+    }
+    // Buffered receipts gas of every shard at every height, as seen when applying its chunks.
+    let buffered_gas_trajectory = Arc::new(Mutex::new(BTreeMap::new()));
+    if params.check_buffered_gas_trajectory {
+        let trajectory = buffered_gas_trajectory.clone();
+        builder = builder.with_apply_result_hook(Box::new(
+            move |shard_id: ShardId, height: BlockHeight, apply_result: &ApplyResult| {
+                let Some(congestion_info) = apply_result.congestion_info else {
+                    return;
+                };
+                trajectory
+                    .lock()
+                    .unwrap()
+                    .entry(height)
+                    .or_insert_with(BTreeMap::new)
+                    .insert(shard_id, congestion_info.buffered_receipts_gas());
+            },
+        ));
     }
 
     let TestLoopEnv { mut test_loop, datas: node_datas, tempdir } = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
         .clients(params.clients)
         .load_mem_tries_for_tracked_shards(params.load_mem_tries_for_tracked_shards)
+        .gc_num_epochs_to_keep(params.gc_num_epochs_to_keep)
         .drop_protocol_upgrade_chunks(
             base_protocol_version + 1,
             params.chunk_ranges_to_drop.clone(),
         )
         .build();
 
-    if let Some(account) = params.deploy_test_contract {
-        let signer = &create_user_test_signer(&account).into();
-        let deploy_contract_tx = SignedTransaction::deploy_contract(
-            101,
-            &account,
-            near_test_contracts::rs_contract().into(),
-            &signer,
-            get_shared_block_hash(&node_datas, &test_loop),
-        );
-        run_tx(&mut test_loop, deploy_contract_tx, &node_datas, Duration::seconds(5));
-    }
-
     let client_handles =
         node_datas.iter().map(|data| data.client_sender.actor_handle()).collect_vec();
 
@@ -691,7 +2089,7 @@ fn test_resharding_v3_base(params: TestReshardingParameters) {
             }
             latest_block_height.set(tip.height);
             println!("block: {} chunks: {:?}", tip.height, block_header.chunk_mask());
-            if params.all_chunks_expected && params.chunk_ranges_to_drop.is_empty() {
+            if params.all_chunks_expected {
                 assert!(block_header.chunk_mask().iter().all(|chunk_bit| *chunk_bit));
             }
         }
@@ -720,16 +2118,65 @@ fn test_resharding_v3_base(params: TestReshardingParameters) {
     );
     // Wait for garbage collection to kick in, so that it is tested as well.
     test_loop
-        .run_for(Duration::seconds((DEFAULT_GC_NUM_EPOCHS_TO_KEEP * params.epoch_length) as i64));
+        .run_for(Duration::seconds((params.gc_num_epochs_to_keep * params.epoch_length) as i64));
 
     // At the end of the test we know for sure resharding has been completed.
     // Verify that state is equal across tries and flat storage for all children shards.
     let clients =
         client_handles.iter().map(|handle| &test_loop.data.get(handle).client).collect_vec();
     assert_state_sanity_for_children_shard(parent_shard_uid, &clients[0]);
-
-    TestLoopEnv { test_loop, datas: node_datas, tempdir }
-        .shutdown_and_drain_remaining_events(Duration::seconds(20));
+    for client in &clients {
+        check_no_state_data_in_children_shards_after_gc(client, parent_shard_uid);
+    }
+    for child_shard_id in &children_shard_ids {
+        assert!(
+            shards_with_implicit_transitions.lock().unwrap().contains(child_shard_id),
+            "no state witness of child shard {child_shard_id} contains an implicit transition"
+        );
+    }
+    let final_head = clients[0].chain.final_head().unwrap();
+    let children_shard_uids = clients[0]
+        .epoch_manager
+        .get_shard_layout(&final_head.epoch_id)
+        .unwrap()
+        .get_children_shards_uids(parent_shard_uid.shard_id())
+        .unwrap();
+    for &child_shard_uid in &children_shard_uids {
+        check_storage_usage_consistency(&clients[0], child_shard_uid, &final_head.last_block_hash);
+    }
+    let [left_child_uid, right_child_uid] = children_shard_uids[..] else {
+        panic!("the parent shard should be split in two children");
+    };
+    check_trie_key_prefix_completeness(
+        &clients[0],
+        parent_shard_uid,
+        left_child_uid,
+        right_child_uid,
+        &new_boundary_account,
+    );
+    if params.check_outgoing_buffers_drained {
+        check_outgoing_buffer_migration_complete(
+            &clients[0],
+            parent_shard_uid,
+            &final_head.last_block_hash,
+        );
+    }
+    if params.check_buffered_gas_trajectory {
+        assert_buffered_gas_trajectory(&buffered_gas_trajectory.lock().unwrap());
+    }
+    if params.validate_resharding_state_witnesses {
+        assert!(num_validated_witnesses.get() > 0, "no resharding state witness was validated");
+    }
+    let genesis_hash = *clients[0].chain.genesis().hash();
+    let head_hash = clients[0].chain.head().unwrap().last_block_hash;
+    assert_no_skipped_epochs_during_resharding(&clients[0], &genesis_hash, &head_hash);
+
+    let env = TestLoopEnv { test_loop, datas: node_datas, tempdir };
+    if params.shuffle_shard_assignment_for_chunk_producers {
+        let client = &env.test_loop.data.get(&client_handles[0]).client;
+        assert_shard_assignment_changed_at_epoch_boundary(client, &env.shard_assignment_trace());
+    }
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
 }
 
 #[test]
@@ -737,6 +2184,26 @@ fn test_resharding_v3() {
     test_resharding_v3_base(TestReshardingParameters::new());
 }
 
+#[test]
+fn test_resharding_v3_from_two_shards() {
+    test_resharding_v3_base(TestReshardingParameters::new().with_num_shards(2));
+}
+
+/// Validates the state witnesses of the resharding epoch and the first witnesses of the children
+/// shards explicitly, to catch witness generation bugs specific to resharding.
+#[test]
+fn test_resharding_v3_receipt_proof_validity() {
+    test_resharding_v3_base(TestReshardingParameters::new().validate_resharding_state_witnesses());
+}
+
+/// All other resharding tests use very short epochs. Run the resharding with a long epoch to
+/// catch off-by-one errors in epoch-relative calculations, e.g. in garbage collection, that only
+/// show up when the epoch is much longer than the number of clients.
+#[test]
+fn test_resharding_v3_long_running_epoch() {
+    test_resharding_v3_base(TestReshardingParameters::new().epoch_length(50));
+}
+
 #[test]
 fn test_resharding_v3_drop_chunks_before() {
     let chunk_ranges_to_drop = HashMap::from([(ShardUId { shard_id: 1, version: 3 }, -2..0)]);
@@ -809,17 +2276,52 @@ fn test_resharding_v3_shard_shuffling() {
     test_resharding_v3_base(params);
 }
 
+/// Upper bound on the delayed receipt queue length in the delayed receipts tests. The test
+/// contract calls pile up a few more receipts per block than a chunk can execute, so the queue
+/// grows by about one receipt per block until the calls stop one block after resharding.
+const DELAYED_RECEIPTS_MAX_QUEUE_LEN: u64 = 40;
+
+/// Asserts on the total buffered receipts gas of all shards at every height, as recorded from the
+/// apply results: receipts must have been buffered at some point during the test, and all the
+/// buffers must have been drained by its end.
+fn assert_buffered_gas_trajectory(trajectory: &BTreeMap<BlockHeight, BTreeMap<ShardId, u128>>) {
+    let totals = trajectory
+        .iter()
+        .map(|(height, gas_per_shard)| (*height, gas_per_shard.values().sum::<u128>()))
+        .collect_vec();
+    tracing::debug!(target: "test", ?totals, "buffered receipts gas trajectory");
+    let (peak_height, peak_gas) =
+        totals.iter().max_by_key(|(_, gas)| *gas).expect("no chunk has been applied");
+    assert_ne!(*peak_gas, 0, "no receipt has been buffered during the test");
+    let (last_height, last_gas) = totals.last().unwrap();
+    assert!(peak_height < last_height, "buffered gas is still at its peak at the end of the test");
+    assert_eq!(*last_gas, 0, "buffers are not drained at height {last_height}: {totals:?}");
+}
+
+/// Congestion level the child shard of the delayed receipts tests must get below once the
+/// calls stopped, and the number of blocks after resharding it is given to get there.
+const DELAYED_RECEIPTS_MAX_CONGESTION_LEVEL: f64 = 0.5;
+const DELAYED_RECEIPTS_CONGESTION_GRACE_PERIOD: BlockHeightDelta = 5;
+
 #[test]
 // TODO(resharding): fix nearcore and replace the line below with #[cfg_attr(not(feature = "test_features"), ignore)]
 #[ignore]
 fn test_resharding_v3_delayed_receipts_left_child() {
     let account: AccountId = "account4".parse().unwrap();
+    let (_, new_shard_layout) = resharding_v3_shard_layouts();
+    let child_shard_uid = account_id_to_shard_uid(&account, &new_shard_layout);
     let params = TestReshardingParameters::new()
         .deploy_test_contract(account.clone())
         .add_loop_action(call_burn_gas_contract(vec![account.clone()], account.clone(), 275 * TGAS))
         .add_loop_action(check_receipts_presence_at_resharding_block(
-            account,
+            account.clone(),
             ReceiptKind::Delayed,
+        ))
+        .add_loop_action(check_receipt_queue_bounded(account, DELAYED_RECEIPTS_MAX_QUEUE_LEN))
+        .add_loop_action(assert_congestion_level_below_threshold(
+            child_shard_uid,
+            DELAYED_RECEIPTS_MAX_CONGESTION_LEVEL,
+            DELAYED_RECEIPTS_CONGESTION_GRACE_PERIOD,
         ));
     test_resharding_v3_base(params);
 }
@@ -829,12 +2331,20 @@ fn test_resharding_v3_delayed_receipts_left_child() {
 #[ignore]
 fn test_resharding_v3_delayed_receipts_right_child() {
     let account: AccountId = "account6".parse().unwrap();
+    let (_, new_shard_layout) = resharding_v3_shard_layouts();
+    let child_shard_uid = account_id_to_shard_uid(&account, &new_shard_layout);
     let params = TestReshardingParameters::new()
         .deploy_test_contract(account.clone())
         .add_loop_action(call_burn_gas_contract(vec![account.clone()], account.clone(), 275 * TGAS))
         .add_loop_action(check_receipts_presence_at_resharding_block(
-            account,
+            account.clone(),
             ReceiptKind::Delayed,
+        ))
+        .add_loop_action(check_receipt_queue_bounded(account, DELAYED_RECEIPTS_MAX_QUEUE_LEN))
+        .add_loop_action(assert_congestion_level_below_threshold(
+            child_shard_uid,
+            DELAYED_RECEIPTS_MAX_CONGESTION_LEVEL,
+            DELAYED_RECEIPTS_CONGESTION_GRACE_PERIOD,
         ));
     test_resharding_v3_base(params);
 }
@@ -857,7 +2367,9 @@ fn test_resharding_v3_split_parent_buffered_receipts() {
         .add_loop_action(check_receipts_presence_at_resharding_block(
             account_in_right_child,
             ReceiptKind::Buffered,
-        ));
+        ))
+        .check_outgoing_buffers_drained()
+        .check_buffered_gas_trajectory();
     test_resharding_v3_base(params);
 }
 
@@ -879,13 +2391,15 @@ fn test_resharding_v3_buffered_receipts_towards_splitted_shard() {
         .add_loop_action(check_receipts_presence_at_resharding_block(
             account_1_in_stable_shard,
             ReceiptKind::Buffered,
-        ));
+        ))
+        .check_buffered_gas_trajectory();
     test_resharding_v3_base(params);
 }
 
 #[test]
 #[cfg_attr(not(feature = "test_features"), ignore)]
 fn test_resharding_v3_outgoing_receipts_towards_splitted_shard() {
+    const CROSS_SHARD_RECEIPT_MAX_DELIVERY_BLOCKS: BlockHeightDelta = 10;
     let receiver_account: AccountId = "account4".parse().unwrap();
     let account_1_in_stable_shard: AccountId = "account1".parse().unwrap();
     let account_2_in_stable_shard: AccountId = "account2".parse().unwrap();
@@ -895,6 +2409,9 @@ fn test_resharding_v3_outgoing_receipts_towards_splitted_shard() {
             vec![account_1_in_stable_shard, account_2_in_stable_shard],
             receiver_account,
             5 * TGAS,
+        ))
+        .add_loop_action(check_cross_shard_receipt_delivery_latency(
+            CROSS_SHARD_RECEIPT_MAX_DELIVERY_BLOCKS,
         ));
     test_resharding_v3_base(params);
 }
@@ -909,10 +2426,91 @@ fn test_resharding_v3_outgoing_receipts_from_splitted_shard() {
         .deploy_test_contract(receiver_account.clone())
         .add_loop_action(call_burn_gas_contract(
             vec![account_in_left_child, account_in_right_child],
-            receiver_account,
+            receiver_account.clone(),
             5 * TGAS,
+        ))
+        // The receipts from the children must keep being executed by the receiver shard rather
+        // than piling up in its delayed receipt queue. Reading the queue from the trie is costly,
+        // so only check it every few blocks.
+        .add_loop_action_every_n_blocks(
+            check_receipt_queue_bounded(receiver_account, DELAYED_RECEIPTS_MAX_QUEUE_LEN),
+            2,
+        );
+    test_resharding_v3_base(params);
+}
+
+#[test]
+fn test_resharding_v3_nonce_consistency() {
+    let account_in_stable_shard: AccountId = "account1".parse().unwrap();
+    let account_in_left_child: AccountId = "account4".parse().unwrap();
+    let account_in_right_child: AccountId = "account6".parse().unwrap();
+    let params =
+        TestReshardingParameters::new().add_loop_action(send_transfers_and_check_nonces(vec![
+            account_in_stable_shard,
+            account_in_left_child,
+            account_in_right_child,
+        ]));
+    test_resharding_v3_base(params);
+}
+
+#[test]
+fn test_resharding_v3_wasm_execution_after_resharding() {
+    let account_in_parent: AccountId = "account4".parse().unwrap();
+    let params = TestReshardingParameters::new()
+        .deploy_test_contract(account_in_parent.clone())
+        .add_loop_action(increment_counter_across_resharding(account_in_parent, 3, 5));
+    test_resharding_v3_base(params);
+}
+
+#[test]
+fn test_resharding_v3_validator_selection_after_resharding() {
+    let (base_shard_layout, new_shard_layout) = resharding_v3_shard_layouts();
+    let parent_shard_uid =
+        account_id_to_shard_uid(&"account6".parse().unwrap(), &base_shard_layout);
+    let children_shard_uids =
+        new_shard_layout.get_children_shards_uids(parent_shard_uid.shard_id()).unwrap();
+    let params = TestReshardingParameters::new()
+        .add_loop_action(check_chunk_producers_after_resharding(children_shard_uids));
+    test_resharding_v3_base(params);
+}
+
+/// With more chunk producers than shards, each tracking only the shards it produces chunks for,
+/// the producers are reassigned when the parent is split, so that some of them start tracking a
+/// child shard without having tracked the parent and have to state sync it during resharding. The
+/// epoch is long enough for them to catch up without missing chunks.
+#[test]
+fn test_resharding_v3_incremental_state_sync_during_resharding() {
+    let (base_shard_layout, _) = resharding_v3_shard_layouts();
+    let parent_shard_uid =
+        account_id_to_shard_uid(&"account6".parse().unwrap(), &base_shard_layout);
+    let num_synced_clients = Rc::new(Cell::new(0));
+    let params = TestReshardingParameters::with_clients(5)
+        .epoch_length(10)
+        .single_shard_tracking()
+        .add_loop_action(check_state_sync_during_resharding(
+            parent_shard_uid,
+            num_synced_clients.clone(),
         ));
     test_resharding_v3_base(params);
+    assert!(
+        num_synced_clients.get() > 0,
+        "no client state synced a child of {parent_shard_uid} during resharding"
+    );
+}
+
+#[test]
+fn test_resharding_v3_view_client_queries() {
+    let params = TestReshardingParameters::new();
+    let account_ids = params.accounts.clone();
+    let params = params.add_loop_action(check_view_client_queries_after_resharding(account_ids));
+    test_resharding_v3_base(params);
+}
+
+#[test]
+fn test_resharding_v3_short_gc_window() {
+    test_resharding_v3_base(
+        TestReshardingParameters::new().gc_num_epochs_to_keep(MIN_GC_NUM_EPOCHS_TO_KEEP),
+    );
 }
 
 #[test]
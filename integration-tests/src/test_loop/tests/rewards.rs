@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use near_async::messaging::Handler;
+use near_async::time::Duration;
+use near_chain_configs::test_genesis::TestGenesisBuilder;
+use near_client::Query;
+use near_epoch_manager::{RewardCalculator, ValidatorOnlineThresholds};
+use near_o11y::testonly::init_test_logger;
+use near_primitives::hash::CryptoHash;
+use near_primitives::num_rational::Rational32;
+use near_primitives::types::{AccountId, Balance, BlockId, BlockReference, NumBlocks};
+use near_primitives::version::ProtocolFeature;
+use near_primitives::views::{QueryRequest, QueryResponseKind};
+
+use crate::test_loop::builder::TestLoopBuilder;
+use crate::test_loop::env::TestLoopEnv;
+use crate::test_loop::utils::ONE_NEAR;
+
+const EPOCH_LENGTH: u64 = 10;
+const NUM_BLOCKS_PER_YEAR: u64 = 100;
+
+/// Returns the liquid and the locked balance of `account_id` at `block_hash`,
+/// as seen by the view client of the first node.
+fn query_balance(
+    env: &mut TestLoopEnv,
+    account_id: &AccountId,
+    block_hash: CryptoHash,
+) -> (Balance, Balance) {
+    let view_client_handle = env.datas[0].view_client_sender.actor_handle();
+    let view_client = env.test_loop.data.get_mut(&view_client_handle);
+    let query = Query::new(
+        BlockReference::BlockId(BlockId::Hash(block_hash)),
+        QueryRequest::ViewAccount { account_id: account_id.clone() },
+    );
+    let response = view_client.handle(query).unwrap();
+    let QueryResponseKind::ViewAccount(account_view) = response.kind else {
+        panic!("unexpected query response for {account_id}");
+    };
+    (account_view.amount, account_view.locked)
+}
+
+/// Returns the hash and the total supply of the head block of the first node.
+fn head_total_supply(env: &TestLoopEnv) -> (CryptoHash, Balance) {
+    let client = &env.test_loop.data.get(&env.datas[0].client_sender.actor_handle()).client;
+    let head = client.chain.head().unwrap();
+    let header = client.chain.get_block_header(&head.last_block_hash).unwrap();
+    (head.last_block_hash, header.total_supply())
+}
+
+/// Runs the chain for a year worth of blocks with rewards enabled and checks
+/// that the minted tokens are split between the protocol treasury and the
+/// validators according to the protocol reward rate.
+///
+/// The chain runs the latest protocol version, where the epoch reward depends
+/// on the epoch duration rather than on `num_blocks_per_year`, which only
+/// sets the length of the run here. See
+/// `test_epoch_reward_before_rectify_inflation` for the latter.
+#[test]
+fn test_rewards_over_a_year_of_blocks() {
+    init_test_logger();
+    let builder = TestLoopBuilder::new();
+
+    let initial_balance = 1_000_000 * ONE_NEAR;
+    let treasury: AccountId = "near".parse().unwrap();
+    let validators: Vec<AccountId> =
+        (0..2).map(|i| format!("validator{}", i).parse().unwrap()).collect_vec();
+    let validator_names = validators.iter().map(|account| account.as_str()).collect_vec();
+
+    let mut genesis_builder = TestGenesisBuilder::new();
+    genesis_builder
+        .genesis_time_from_clock(&builder.clock())
+        .protocol_version_latest()
+        .genesis_height(10000)
+        .gas_prices_free()
+        .epoch_length(EPOCH_LENGTH)
+        .validators_equal_stake(&validator_names)
+        .protocol_treasury_account(treasury.to_string())
+        .max_inflation_rate(Rational32::new(1, 10))
+        .num_blocks_per_year(NUM_BLOCKS_PER_YEAR);
+    for account in validators.iter().chain([&treasury]) {
+        genesis_builder.add_user_account_simple(account.clone(), initial_balance);
+    }
+    let (mut genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+    // The builder doesn't reward the treasury by default.
+    let protocol_reward_rate = Rational32::new(1, 10);
+    genesis.config.protocol_reward_rate = protocol_reward_rate;
+    assert_eq!(genesis.config.num_blocks_per_year, NUM_BLOCKS_PER_YEAR);
+
+    let mut env = builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(validators.clone())
+        .build();
+
+    let (genesis_hash, initial_total_supply) = head_total_supply(&env);
+    let initial_treasury_balance = query_balance(&mut env, &treasury, genesis_hash).0;
+    let initial_validator_balances = validators
+        .iter()
+        .map(|account| query_balance(&mut env, account, genesis_hash))
+        .collect_vec();
+
+    // Stop in the middle of an epoch, the rewards are minted at the start.
+    let client_handle = env.datas[0].client_sender.actor_handle();
+    let target_height = 10000 + NUM_BLOCKS_PER_YEAR + EPOCH_LENGTH / 2;
+    env.test_loop.run_until(
+        |test_loop_data| {
+            test_loop_data.get(&client_handle).client.chain.head().unwrap().height >= target_height
+        },
+        Duration::seconds(NUM_BLOCKS_PER_YEAR as i64 + 10),
+    );
+
+    let (head_hash, total_supply) = head_total_supply(&env);
+    let treasury_reward =
+        query_balance(&mut env, &treasury, head_hash).0 - initial_treasury_balance;
+    let mut validators_reward = 0;
+    for (account, (initial_amount, initial_locked)) in
+        validators.iter().zip(initial_validator_balances)
+    {
+        let (amount, locked) = query_balance(&mut env, account, head_hash);
+        // Rewards are staked, the liquid balance stays the same.
+        assert_eq!(amount, initial_amount);
+        assert!(locked > initial_locked, "{account} didn't get any reward");
+        validators_reward += locked - initial_locked;
+    }
+
+    // No transactions were executed, nothing was burnt.
+    let minted = total_supply - initial_total_supply;
+    assert_eq!(minted, treasury_reward + validators_reward);
+    // The treasury gets its share of each epoch reward, the validators which
+    // were online the whole time share the rest, up to rounding.
+    assert!(treasury_reward > 0);
+    let num_epochs = (NUM_BLOCKS_PER_YEAR + EPOCH_LENGTH - 1) / EPOCH_LENGTH + 1;
+    let expected_treasury_reward =
+        minted * *protocol_reward_rate.numer() as u128 / *protocol_reward_rate.denom() as u128;
+    assert!(
+        expected_treasury_reward.abs_diff(treasury_reward) <= num_epochs as u128 * 2,
+        "treasury got {treasury_reward}, expected {expected_treasury_reward}"
+    );
+
+    env.shutdown_and_drain_remaining_events(Duration::seconds(20));
+}
+
+/// Before `RectifyInflation`, the epoch reward is the yearly inflation spread
+/// over `num_blocks_per_year`. Checks that the genesis value set by the
+/// builder is the one used to compute the treasury reward of an epoch.
+#[test]
+fn test_epoch_reward_before_rectify_inflation() {
+    let protocol_version = ProtocolFeature::RectifyInflation.protocol_version() - 1;
+    let treasury: AccountId = "near".parse().unwrap();
+    let max_inflation_rate = Rational32::new(1, 10);
+    let protocol_reward_rate = Rational32::new(1, 10);
+
+    let epoch_treasury_reward = |num_blocks_per_year: NumBlocks| {
+        let mut genesis_builder = TestGenesisBuilder::new();
+        genesis_builder
+            .protocol_version(protocol_version)
+            .epoch_length(EPOCH_LENGTH)
+            .validators_equal_stake(&["validator0"])
+            .protocol_treasury_account(treasury.to_string())
+            .max_inflation_rate(max_inflation_rate)
+            .num_blocks_per_year(num_blocks_per_year)
+            .add_user_account_simple(treasury.clone(), 1_000_000 * ONE_NEAR);
+        let (mut genesis, _, _) = genesis_builder.build();
+        genesis.config.protocol_reward_rate = protocol_reward_rate;
+
+        let reward_calculator = RewardCalculator::new(&genesis.config, EPOCH_LENGTH);
+        let online_thresholds = ValidatorOnlineThresholds {
+            online_min_threshold: Rational32::new(9, 10),
+            online_max_threshold: Rational32::new(99, 100),
+            endorsement_cutoff_threshold: None,
+        };
+        // Without validator stats, only the treasury is rewarded. The epoch
+        // duration is ignored before `RectifyInflation`.
+        let (rewards, _) = reward_calculator.calculate_reward(
+            HashMap::new(),
+            &HashMap::new(),
+            genesis.config.total_supply,
+            protocol_version,
+            protocol_version,
+            0,
+            online_thresholds,
+        );
+        (genesis.config.total_supply, rewards[&treasury])
+    };
+
+    let (total_supply, treasury_reward) = epoch_treasury_reward(NUM_BLOCKS_PER_YEAR);
+    let epoch_reward = total_supply * *max_inflation_rate.numer() as u128 * EPOCH_LENGTH as u128
+        / (NUM_BLOCKS_PER_YEAR as u128 * *max_inflation_rate.denom() as u128);
+    let expected_treasury_reward = epoch_reward * *protocol_reward_rate.numer() as u128
+        / *protocol_reward_rate.denom() as u128;
+    assert_eq!(treasury_reward, expected_treasury_reward);
+
+    // Twice as many blocks per year halve the reward of each epoch.
+    let (_, slower_treasury_reward) = epoch_treasury_reward(2 * NUM_BLOCKS_PER_YEAR);
+    assert!(treasury_reward.abs_diff(2 * slower_treasury_reward) <= 2);
+}
@@ -118,11 +118,12 @@ fn setup_initial_blockchain(
             }
         }
     }
-    let (genesis, epoch_config_store) = genesis_builder.build();
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
 
     let env = builder
         .genesis(genesis)
         .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
         .clients(clients)
         .drop_chunks_by_height(chunks_produced)
         .build();
@@ -48,7 +48,12 @@ pub fn standard_setup_1() -> TestLoopEnv {
     for account in accounts {
         genesis_builder.add_user_account_simple(account.clone(), initial_balance);
     }
-    let (genesis, epoch_config_store) = genesis_builder.build();
-
-    builder.genesis(genesis).epoch_config_store(epoch_config_store).clients(clients).build()
+    let (genesis, epoch_config_store, runtime_config_store) = genesis_builder.build();
+
+    builder
+        .genesis(genesis)
+        .epoch_config_store(epoch_config_store)
+        .runtime_config_store(runtime_config_store)
+        .clients(clients)
+        .build()
 }
@@ -303,6 +303,71 @@ fn test_protocol_upgrade_simple() {
     check_congestion_info(&env, check_congested_protocol_upgrade);
 }
 
+/// Send cross-shard receipts in every block around the protocol upgrade, so
+/// that the chunks at the exact upgrade block switch from the old receipt sink
+/// to the congestion control one while they have outgoing receipts. None of
+/// the receipts may be lost in the transition.
+#[test]
+fn test_protocol_upgrade_cross_shard_receipts() {
+    init_test_logger();
+
+    // The following only makes sense to test if the feature is enabled in the current build.
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+
+    let sender_id: AccountId = "test0".parse().unwrap();
+    let mut env = setup_real_runtime(
+        sender_id.clone(),
+        ProtocolFeature::CongestionControl.protocol_version() - 1,
+    );
+    check_old_protocol(&env);
+
+    // The receiver is on a different shard than the sender. It doesn't need
+    // to exist, the receipts only have to be delivered.
+    let receiver_id: AccountId = "test3".parse().unwrap();
+    let signer = InMemorySigner::from_seed(sender_id.clone(), KeyType::ED25519, sender_id.as_str());
+    let mut nonce = 10;
+    let mut tx_hashes = vec![];
+    let mut upgrade_height = None;
+    let tip = env.clients[0].chain.head().unwrap();
+    for i in 1..=3 * env.clients[0].chain.epoch_length {
+        let block = env.clients[0].chain.get_head_block().unwrap();
+        for _ in 0..5 {
+            let tx = new_cheap_fn_call(&mut nonce, &signer, receiver_id.clone(), *block.hash());
+            tx_hashes.push(tx.get_hash());
+            let response = env.clients[0].process_tx(tx, false, false);
+            assert_eq!(response, ProcessTxResponse::ValidTx);
+        }
+        env.produce_block(0, tip.height + i);
+
+        let head_block = env.clients[0].chain.get_head_block().unwrap();
+        let has_congestion_info =
+            head_block.chunks().iter_deprecated().all(|chunk| chunk.congestion_info().is_some());
+        if has_congestion_info && upgrade_height.is_none() {
+            upgrade_height = Some(head_block.header().height());
+        }
+    }
+    let upgrade_height = upgrade_height.expect("protocol upgrade didn't happen");
+    tracing::info!(target: "test", upgrade_height, "protocol upgraded");
+    assert!(ProtocolFeature::CongestionControl.enabled(env.get_head_protocol_version()));
+
+    // Let all the receipts get delivered.
+    let tip = env.clients[0].chain.head().unwrap();
+    for i in 1..5 {
+        env.produce_block(0, tip.height + i);
+    }
+    for tx_hash in &tx_hashes {
+        env.clients[0]
+            .chain
+            .get_final_transaction_result(tx_hash)
+            .expect("all transactions sent around the protocol upgrade should be executed");
+    }
+
+    let check_congested_protocol_upgrade = false;
+    check_congestion_info(&env, check_congested_protocol_upgrade);
+}
+
 fn head_congestion_control_config(
     env: &TestEnv,
 ) -> near_parameters::config::CongestionControlConfig {
@@ -101,6 +101,7 @@ impl TestEnvNightshadeSetupExt for TestEnvBuilder {
                 Some(runtime_config_store),
                 trie_config,
                 state_snapshot_type.clone(),
+                None,
             )
         };
         let dummy_runtime_configs =
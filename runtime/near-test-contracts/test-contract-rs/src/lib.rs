@@ -385,6 +385,34 @@ pub unsafe fn read_value() {
     }
 }
 
+/// Increments the counter stored under the `counter` key by the `u64` given as input and
+/// returns the new value of the counter. A missing counter is treated as 0.
+#[unsafe(no_mangle)]
+pub unsafe fn increment_counter() {
+    input(0);
+    if register_len(0) != size_of::<u64>() as u64 {
+        panic()
+    }
+    let mut delta = [0u8; size_of::<u64>()];
+    read_register(0, delta.as_mut_ptr() as u64);
+
+    let key = b"counter";
+    let mut value = [0u8; size_of::<u64>()];
+    if storage_read(key.len() as u64, key.as_ptr() as u64, 1) == 1 {
+        read_register(1, value.as_mut_ptr() as u64);
+    }
+    let counter = u64::from_le_bytes(value) + u64::from_le_bytes(delta);
+    let value = counter.to_le_bytes();
+    storage_write(
+        key.len() as u64,
+        key.as_ptr() as u64,
+        value.len() as u64,
+        value.as_ptr() as u64,
+        1,
+    );
+    value_return(value.len() as u64, value.as_ptr() as u64);
+}
+
 #[unsafe(no_mangle)]
 pub unsafe fn log_something() {
     let data = b"hello";
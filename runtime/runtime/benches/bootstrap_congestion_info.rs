@@ -0,0 +1,73 @@
+#[macro_use]
+extern crate bencher;
+
+use std::borrow::Cow;
+
+use bencher::Bencher;
+use near_parameters::RuntimeConfig;
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::ReceiptOrStateStoredReceipt;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::types::{ShardId, StateChangeCause};
+use near_store::adapter::StoreUpdateAdapter;
+use near_store::flat::FlatStateChanges;
+use near_store::test_utils::{gen_receipts, TestTriesBuilder};
+use near_store::trie::receipts_column_helper::{
+    DelayedReceiptQueue, ShardsOutgoingReceiptBuffer, TrieQueue,
+};
+use near_store::trie::update::TrieUpdateResult;
+use near_store::Trie;
+use node_runtime::{bootstrap_congestion_info, bootstrap_congestion_info_incremental};
+
+/// Creates a trie with a few thousand delayed and buffered receipts, backed by
+/// flat storage with its head at the default block hash.
+fn trie_with_receipts() -> Trie {
+    let mut rng = rand::thread_rng();
+    let shard_uid = ShardUId::single_shard();
+    let tries = TestTriesBuilder::new().with_flat_storage(true).build();
+    let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+
+    let mut delayed_receipts = DelayedReceiptQueue::load(&state_update).unwrap();
+    for receipt in gen_receipts(&mut rng, 2000) {
+        let receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Owned(receipt));
+        delayed_receipts.push_back(&mut state_update, &receipt).unwrap();
+    }
+    let mut outgoing_buffers = ShardsOutgoingReceiptBuffer::load(&state_update).unwrap();
+    for shard_id in 0..4 {
+        for receipt in gen_receipts(&mut rng, 500) {
+            let receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Owned(receipt));
+            outgoing_buffers
+                .to_shard(ShardId::new(shard_id))
+                .push_back(&mut state_update, &receipt)
+                .unwrap();
+        }
+    }
+
+    state_update.commit(StateChangeCause::InitialState);
+    let TrieUpdateResult { trie_changes, state_changes, .. } = state_update.finalize().unwrap();
+    let mut store_update = tries.store_update();
+    let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+    FlatStateChanges::from_state_changes(&state_changes)
+        .apply_to_flat_state(&mut store_update.flat_store_update(), shard_uid);
+    store_update.commit().unwrap();
+    tries.get_trie_with_block_hash_for_shard(shard_uid, root, &CryptoHash::default(), false)
+}
+
+fn bootstrap_full(bench: &mut Bencher) {
+    let trie = trie_with_receipts();
+    let config = RuntimeConfig::test();
+    bench.iter(|| {
+        bootstrap_congestion_info(&trie, &config, ShardId::new(0)).unwrap();
+    });
+}
+
+fn bootstrap_incremental(bench: &mut Bencher) {
+    let trie = trie_with_receipts();
+    let config = RuntimeConfig::test();
+    bench.iter(|| {
+        bootstrap_congestion_info_incremental(&trie, &config, ShardId::new(0)).unwrap();
+    });
+}
+
+benchmark_group!(benches, bootstrap_full, bootstrap_incremental);
+benchmark_main!(benches);
@@ -0,0 +1,59 @@
+#[macro_use]
+extern crate bencher;
+
+use std::borrow::Cow;
+
+use bencher::{black_box, Bencher};
+use near_parameters::RuntimeConfig;
+use near_primitives::receipt::ReceiptOrStateStoredReceipt;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::types::StateChangeCause;
+use near_store::test_utils::{gen_receipts, TestTriesBuilder};
+use near_store::trie::receipts_column_helper::{DelayedReceiptQueue, TrieQueue};
+use near_store::Trie;
+use node_runtime::estimated_gas_in_next_n;
+
+/// Creates a trie with a few thousand delayed receipts.
+fn trie_with_delayed_receipts() -> Trie {
+    let mut rng = rand::thread_rng();
+    let shard_uid = ShardUId::single_shard();
+    let tries = TestTriesBuilder::new().build();
+    let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+
+    let mut delayed_receipts = DelayedReceiptQueue::load(&state_update).unwrap();
+    for receipt in gen_receipts(&mut rng, 2000) {
+        let receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Owned(receipt));
+        delayed_receipts.push_back(&mut state_update, &receipt).unwrap();
+    }
+
+    state_update.commit(StateChangeCause::InitialState);
+    let trie_changes = state_update.finalize().unwrap().trie_changes;
+    let mut store_update = tries.store_update();
+    let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+    store_update.commit().unwrap();
+    tries.get_trie_for_shard(shard_uid, root)
+}
+
+fn estimate_gas(bench: &mut Bencher, n: usize) {
+    let trie = trie_with_delayed_receipts();
+    let queue = DelayedReceiptQueue::load(&trie).unwrap();
+    let config = RuntimeConfig::test();
+    bench.iter(|| {
+        black_box(estimated_gas_in_next_n(&queue, &trie, &config, n).unwrap());
+    });
+}
+
+fn estimate_gas_next_10(bench: &mut Bencher) {
+    estimate_gas(bench, 10);
+}
+
+fn estimate_gas_next_50(bench: &mut Bencher) {
+    estimate_gas(bench, 50);
+}
+
+fn estimate_gas_next_100(bench: &mut Bencher) {
+    estimate_gas(bench, 100);
+}
+
+benchmark_group!(benches, estimate_gas_next_10, estimate_gas_next_50, estimate_gas_next_100);
+benchmark_main!(benches);
@@ -0,0 +1,178 @@
+#[macro_use]
+extern crate bencher;
+
+use bencher::{black_box, Bencher};
+use near_crypto::{KeyType, PublicKey};
+use near_parameters::RuntimeConfig;
+use near_primitives::action::{Action, FunctionCallAction};
+use near_primitives::apply::ApplyChunkReason;
+use near_primitives::bandwidth_scheduler::BlockBandwidthRequests;
+use near_primitives::congestion_info::BlockCongestionInfo;
+use near_primitives::errors::EpochError;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum, ReceiptV0};
+use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+use near_primitives::shard_layout::ShardLayout;
+use near_primitives::types::{AccountId, Balance, EpochId, EpochInfoProvider, ShardId};
+use near_primitives::version::PROTOCOL_VERSION;
+use near_store::test_utils::TestTriesBuilder;
+use near_store::{ShardUId, Trie};
+use node_runtime::estimator::forward_receipts;
+use node_runtime::ApplyState;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Number of shards the receipts are sent to.
+const NUM_SHARDS: u64 = 4;
+
+/// Maps receivers to shards with a multi shard layout, the rest is unused.
+struct ShardLayoutEpochInfoProvider {
+    shard_layout: ShardLayout,
+}
+
+impl EpochInfoProvider for ShardLayoutEpochInfoProvider {
+    fn validator_stake(
+        &self,
+        _epoch_id: &EpochId,
+        _last_block_hash: &CryptoHash,
+        _account_id: &AccountId,
+    ) -> Result<Option<Balance>, EpochError> {
+        Ok(None)
+    }
+
+    fn validator_total_stake(
+        &self,
+        _epoch_id: &EpochId,
+        _last_block_hash: &CryptoHash,
+    ) -> Result<Balance, EpochError> {
+        Ok(0)
+    }
+
+    fn minimum_stake(&self, _prev_block_hash: &CryptoHash) -> Result<Balance, EpochError> {
+        Ok(0)
+    }
+
+    fn chain_id(&self) -> String {
+        "localnet".into()
+    }
+
+    fn account_id_to_shard_id(
+        &self,
+        account_id: &AccountId,
+        _epoch_id: &EpochId,
+    ) -> Result<ShardId, EpochError> {
+        Ok(self.shard_layout.account_id_to_shard_id(account_id))
+    }
+}
+
+fn apply_state() -> ApplyState {
+    // Lift the size limit as well, so that no receipt gets buffered.
+    let mut config = RuntimeConfig::test();
+    config.congestion_control_config.outgoing_receipts_usual_size_limit = u64::MAX;
+    ApplyState {
+        apply_reason: ApplyChunkReason::UpdateTrackedShard,
+        block_height: 1,
+        prev_block_hash: Default::default(),
+        block_hash: Default::default(),
+        shard_id: ShardUId::single_shard().shard_id(),
+        epoch_id: Default::default(),
+        epoch_height: 0,
+        gas_price: 1,
+        block_timestamp: 100,
+        gas_limit: None,
+        random_seed: Default::default(),
+        current_protocol_version: PROTOCOL_VERSION,
+        config: Arc::new(config),
+        cache: None,
+        is_new_chunk: true,
+        migration_data: Arc::new(MigrationData::default()),
+        migration_flags: MigrationFlags::default(),
+        congestion_info: BlockCongestionInfo::new(BTreeMap::new()),
+        bandwidth_requests: BlockBandwidthRequests::empty(),
+    }
+}
+
+/// Creates `n` function call receipts, sent in turns to a few receivers on
+/// every shard, the way a contract fanning out calls would.
+fn receipts(n: u64) -> Vec<Receipt> {
+    let signer_id: AccountId = "alice.near".parse().unwrap();
+    (0..n)
+        .map(|i| {
+            let receiver_id: AccountId =
+                format!("test{}-{}", i % NUM_SHARDS, i % 3).parse().unwrap();
+            Receipt::V0(ReceiptV0 {
+                predecessor_id: signer_id.clone(),
+                receiver_id,
+                receipt_id: hash(&i.to_le_bytes()),
+                receipt: ReceiptEnum::Action(ActionReceipt {
+                    signer_id: signer_id.clone(),
+                    signer_public_key: PublicKey::empty(KeyType::ED25519),
+                    gas_price: 0,
+                    output_data_receivers: vec![],
+                    input_data_ids: vec![],
+                    actions: vec![Action::FunctionCall(Box::new(FunctionCallAction {
+                        method_name: "main".to_string(),
+                        args: vec![0; 100],
+                        gas: 10u64.pow(13),
+                        deposit: 0,
+                    }))],
+                }),
+            })
+        })
+        .collect()
+}
+
+fn forward(bench: &mut Bencher, num_receipts: u64, batched: bool) {
+    let tries = TestTriesBuilder::new().build();
+    let apply_state = apply_state();
+    let epoch_info_provider =
+        ShardLayoutEpochInfoProvider { shard_layout: ShardLayout::multi_shard(NUM_SHARDS, 0) };
+    let receipts = receipts(num_receipts);
+    bench.iter(|| {
+        let mut state_update = tries.new_trie_update(ShardUId::single_shard(), Trie::EMPTY_ROOT);
+        let outgoing_receipts = forward_receipts(
+            &mut state_update,
+            &apply_state,
+            receipts.clone(),
+            &epoch_info_provider,
+            batched,
+        )
+        .unwrap();
+        black_box(outgoing_receipts);
+    });
+}
+
+fn forward_sequential_10(bench: &mut Bencher) {
+    forward(bench, 10, false);
+}
+
+fn forward_batch_10(bench: &mut Bencher) {
+    forward(bench, 10, true);
+}
+
+fn forward_sequential_100(bench: &mut Bencher) {
+    forward(bench, 100, false);
+}
+
+fn forward_batch_100(bench: &mut Bencher) {
+    forward(bench, 100, true);
+}
+
+fn forward_sequential_1000(bench: &mut Bencher) {
+    forward(bench, 1000, false);
+}
+
+fn forward_batch_1000(bench: &mut Bencher) {
+    forward(bench, 1000, true);
+}
+
+benchmark_group!(
+    benches,
+    forward_sequential_10,
+    forward_batch_10,
+    forward_sequential_100,
+    forward_batch_100,
+    forward_sequential_1000,
+    forward_batch_1000
+);
+benchmark_main!(benches);
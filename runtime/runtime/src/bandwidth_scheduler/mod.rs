@@ -1,6 +1,11 @@
+use std::fmt::Write;
 use std::num::NonZeroU64;
+use std::sync::LazyLock;
 
-use near_primitives::bandwidth_scheduler::{BandwidthSchedulerParams, BandwidthSchedulerState};
+use near_primitives::bandwidth_scheduler::{
+    BandwidthRequestValues, BandwidthRequests, BandwidthSchedulerParams, BandwidthSchedulerState,
+    BlockBandwidthRequests,
+};
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::types::{ShardId, StateChangeCause};
 use near_primitives::version::ProtocolFeature;
@@ -19,6 +24,49 @@ pub struct BandwidthSchedulerOutput {
     pub scheduler_state_hash: CryptoHash,
 }
 
+/// When this environment variable is set, a diagnostic report of the
+/// scheduler decisions is logged for every applied chunk.
+const NEAR_BANDWIDTH_SCHEDULER_DIAGNOSTICS: &str = "NEAR_BANDWIDTH_SCHEDULER_DIAGNOSTICS";
+
+static DIAGNOSTICS_ENABLED: LazyLock<bool> =
+    LazyLock::new(|| std::env::var(NEAR_BANDWIDTH_SCHEDULER_DIAGNOSTICS).is_ok());
+
+impl BandwidthSchedulerOutput {
+    /// Formats a table with the bandwidth requested and granted for every
+    /// (sender, receiver) pair of shards that sent a request, together with
+    /// what limited the grant. Meant for operators debugging bandwidth issues.
+    ///
+    /// The scheduler doesn't hand out grants above the base bandwidth yet, so
+    /// every pair is granted `base_bandwidth` and larger requests are reported
+    /// as limited by bandwidth.
+    pub fn to_diagnostic_report(&self, requests: &BlockBandwidthRequests) -> String {
+        let request_values = BandwidthRequestValues::new(&self.params);
+        let mut report = format!(
+            "{:>10} {:>8} {:>12} {:>12} {}\n",
+            "from_shard", "to_shard", "requested", "granted", "limited_by"
+        );
+        for (from_shard, shard_requests) in &requests.shards_bandwidth_requests {
+            let BandwidthRequests::V1(shard_requests) = shard_requests;
+            for request in &shard_requests.requests {
+                let bitmap = &request.requested_values_bitmap;
+                let requested = (0..bitmap.len())
+                    .filter(|&idx| bitmap.get_bit(idx))
+                    .last()
+                    .map_or(0, |idx| request_values.values[idx]);
+                let granted = self.params.base_bandwidth;
+                let limited_by = if requested > granted { "bandwidth" } else { "-" };
+                writeln!(
+                    report,
+                    "{:>10} {:>8} {:>12} {:>12} {}",
+                    from_shard, request.to_shard, requested, granted, limited_by
+                )
+                .unwrap();
+            }
+        }
+        report
+    }
+}
+
 pub fn run_bandwidth_scheduler(
     apply_state: &ApplyState,
     state_update: &mut TrieUpdate,
@@ -88,5 +136,15 @@ pub fn run_bandwidth_scheduler(
     set_bandwidth_scheduler_state(state_update, &scheduler_state);
     state_update.commit(StateChangeCause::BandwidthSchedulerStateUpdate);
 
-    Ok(Some(BandwidthSchedulerOutput { params, scheduler_state_hash }))
+    let output = BandwidthSchedulerOutput { params, scheduler_state_hash };
+    if *DIAGNOSTICS_ENABLED {
+        tracing::info!(
+            target: "runtime",
+            height = apply_state.block_height,
+            shard_id = ?apply_state.shard_id,
+            "bandwidth scheduler report:\n{}",
+            output.to_diagnostic_report(bandwidth_requests)
+        );
+    }
+    Ok(Some(output))
 }
@@ -59,6 +59,21 @@ pub fn safe_add_compute(a: Compute, b: Compute) -> Result<Compute, IntegerOverfl
     a.checked_add(b).ok_or(IntegerOverflowError {})
 }
 
+/// Adds a `u64` value, e.g. gas, to a `u128` accumulator. Accumulators are
+/// u128 where the sum of many u64 values may not fit into u64, e.g. the
+/// total gas of all receipts in congestion control.
+pub fn safe_add_u64_to_u128(a: u128, b: u64) -> Result<u128, IntegerOverflowError> {
+    a.checked_add(u128::from(b)).ok_or(IntegerOverflowError {})
+}
+
+pub fn safe_add_u128(a: u128, b: u128) -> Result<u128, IntegerOverflowError> {
+    a.checked_add(b).ok_or(IntegerOverflowError {})
+}
+
+pub fn safe_sub_u128(a: u128, b: u128) -> Result<u128, IntegerOverflowError> {
+    a.checked_sub(b).ok_or(IntegerOverflowError {})
+}
+
 #[macro_export]
 macro_rules! safe_add_balance_apply {
     ($x: expr) => {$x};
@@ -387,4 +402,45 @@ mod tests {
         assert_eq!(safe_gas_price_inflated(10000, Rational32::new(101, 100), 3).unwrap(), 10304);
         assert_eq!(safe_gas_price_inflated(10000, Rational32::new(101, 100), 32).unwrap(), 13750);
     }
+
+    #[test]
+    fn test_safe_add_u64_to_u128() {
+        bolero::check!().with_type().for_each(|(a, b): &(u128, u64)| {
+            match safe_add_u64_to_u128(*a, *b) {
+                Ok(sum) => {
+                    assert_eq!(sum - *a, u128::from(*b));
+                    assert!(sum >= *a);
+                }
+                Err(_) => assert!(u128::MAX - *a < u128::from(*b)),
+            }
+        });
+    }
+
+    #[test]
+    fn test_safe_add_u128() {
+        bolero::check!().with_type().for_each(|(a, b): &(u128, u128)| {
+            match safe_add_u128(*a, *b) {
+                Ok(sum) => {
+                    assert_eq!(sum - *a, *b);
+                    assert_eq!(safe_add_u128(*b, *a).unwrap(), sum);
+                }
+                Err(_) => {
+                    assert!(u128::MAX - *a < *b);
+                    assert!(safe_add_u128(*b, *a).is_err());
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_safe_sub_u128() {
+        bolero::check!().with_type().for_each(|(a, b): &(u128, u128)| {
+            match safe_sub_u128(*a, *b) {
+                Ok(diff) => {
+                    assert_eq!(safe_add_u128(diff, *b).unwrap(), *a);
+                }
+                Err(_) => assert!(*a < *b),
+            }
+        });
+    }
 }
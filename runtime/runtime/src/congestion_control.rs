@@ -1,29 +1,39 @@
 use crate::bandwidth_scheduler::BandwidthSchedulerOutput;
 use crate::config::{
-    safe_add_gas, total_prepaid_exec_fees, total_prepaid_gas, total_prepaid_send_fees,
+    safe_add_gas, safe_add_u64_to_u128, total_prepaid_exec_fees, total_prepaid_gas,
+    total_prepaid_send_fees,
 };
 use crate::ApplyState;
+use borsh::BorshDeserialize;
 use bytesize::ByteSize;
+use near_parameters::config::CongestionControlConfig;
 use near_parameters::{ActionCosts, RuntimeConfig};
 use near_primitives::bandwidth_scheduler::{
     BandwidthRequest, BandwidthRequests, BandwidthRequestsV1, BandwidthSchedulerParams,
 };
 use near_primitives::congestion_info::{CongestionControl, CongestionInfo, CongestionInfoV1};
 use near_primitives::errors::{IntegerOverflowError, RuntimeError};
+use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::{
     Receipt, ReceiptEnum, ReceiptOrStateStoredReceipt, StateStoredReceipt,
     StateStoredReceiptMetadata,
 };
-use near_primitives::types::{EpochInfoProvider, Gas, ShardId};
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::state::FlatStateValue;
+use near_primitives::trie_key::col;
+use near_primitives::types::{AccountId, EpochInfoProvider, Gas, ShardId, StateRoot};
 use near_primitives::version::ProtocolFeature;
+use near_store::flat::FlatStorageChunkView;
 use near_store::trie::outgoing_metadata::{OutgoingMetadatas, ReceiptGroupsConfig};
 use near_store::trie::receipts_column_helper::{
     DelayedReceiptQueue, ShardsOutgoingReceiptBuffer, TrieQueue, TrieQueueIterator,
 };
-use near_store::{StorageError, TrieAccess, TrieUpdate};
+use near_store::{ShardTries, StorageError, Trie, TrieAccess, TrieUpdate};
 use near_vm_runner::logic::ProtocolVersion;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
 /// Handle receipt forwarding for different protocol versions.
 pub(crate) enum ReceiptSink {
@@ -31,6 +41,41 @@ pub(crate) enum ReceiptSink {
     V2(ReceiptSinkV2),
 }
 
+/// Statistics about the receipts forwarded and buffered by a [`ReceiptSinkV2`]
+/// while applying a chunk, reported in the [`ApplyResult`] for performance
+/// analysis.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiptForwardingStats {
+    /// Receipts put in the outgoing receipts, either directly or from a buffer.
+    pub receipts_forwarded_count: u64,
+    /// Receipts put in an outgoing buffer.
+    pub receipts_buffered_count: u64,
+    pub total_gas_forwarded: Gas,
+    pub total_gas_buffered: Gas,
+    pub total_bytes_forwarded: u64,
+    pub total_bytes_buffered: u64,
+    /// Number of times forwarding from a buffer emptied it.
+    pub num_buffer_flushes: u64,
+    /// Largest congestion gas of a single receipt forwarded or buffered.
+    pub max_single_receipt_gas: Gas,
+}
+
+impl ReceiptForwardingStats {
+    fn on_receipt_forwarded(&mut self, size: u64, gas: Gas) {
+        self.receipts_forwarded_count += 1;
+        self.total_gas_forwarded = self.total_gas_forwarded.saturating_add(gas);
+        self.total_bytes_forwarded = self.total_bytes_forwarded.saturating_add(size);
+        self.max_single_receipt_gas = self.max_single_receipt_gas.max(gas);
+    }
+
+    fn on_receipt_buffered(&mut self, size: u64, gas: Gas) {
+        self.receipts_buffered_count += 1;
+        self.total_gas_buffered = self.total_gas_buffered.saturating_add(gas);
+        self.total_bytes_buffered = self.total_bytes_buffered.saturating_add(size);
+        self.max_single_receipt_gas = self.max_single_receipt_gas.max(gas);
+    }
+}
+
 /// Always put receipt to the outgoing receipts.
 pub(crate) struct ReceiptSinkV1 {
     pub(crate) outgoing_receipts: Vec<Receipt>,
@@ -52,18 +97,99 @@ pub(crate) struct ReceiptSinkV2 {
     /// used to make forwarding decisions.
     pub(crate) own_congestion_info: CongestionInfo,
     pub(crate) outgoing_receipts: Vec<Receipt>,
+    /// Shards that receipts in `outgoing_receipts` were forwarded to by this
+    /// sink. Receipts carried over from a [`ReceiptSinkV1`] are not included.
+    pub(crate) outgoing_receipts_shards: HashSet<ShardId>,
     pub(crate) outgoing_limit: HashMap<ShardId, OutgoingLimit>,
     pub(crate) outgoing_buffers: ShardsOutgoingReceiptBuffer,
     pub(crate) outgoing_metadatas: OutgoingMetadatas,
     pub(crate) bandwidth_scheduler_output: Option<BandwidthSchedulerOutput>,
     pub(crate) protocol_version: ProtocolVersion,
+    /// Receipts forwarded and buffered by this sink, see
+    /// [`ReceiptSink::generate_apply_stats`].
+    pub(crate) forwarding_stats: ReceiptForwardingStats,
 }
 
 /// Limits for outgoing receipts to a shard.
 /// Receipts are sent out until the limit is hit, after that they're buffered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct OutgoingLimit {
     pub gas: Gas,
     pub size: u64,
+    /// How many more receipts can be forwarded to the shard.
+    pub max_receipts: u64,
+}
+
+/// A point-in-time copy of the state that determines backpressure in a
+/// [`ReceiptSinkV2`]. Useful to compare forwarding decisions across chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BackpressureSnapshot {
+    pub own_congestion_info: CongestionInfo,
+    pub outgoing_limit: BTreeMap<ShardId, OutgoingLimit>,
+    pub buffer_lens: BTreeMap<ShardId, u64>,
+}
+
+impl fmt::Display for BackpressureSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "delayed_gas={} buffered_gas={} receipt_bytes={} allowed_shard={}",
+            self.own_congestion_info.delayed_receipts_gas(),
+            self.own_congestion_info.buffered_receipts_gas(),
+            self.own_congestion_info.receipt_bytes(),
+            self.own_congestion_info.allowed_shard(),
+        )?;
+        for (shard_id, limit) in &self.outgoing_limit {
+            write!(
+                f,
+                " limit[{}]=(gas={}, size={}, receipts={})",
+                shard_id, limit.gas, limit.size, limit.max_receipts
+            )?;
+        }
+        for (shard_id, len) in &self.buffer_lens {
+            write!(f, " buffer[{}]={}", shard_id, len)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which of the outgoing limits prevented a receipt from being forwarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GasOrSize {
+    Gas,
+    Size,
+    ReceiptCount,
+}
+
+/// Describes why a receipt couldn't be forwarded to a shard and was buffered
+/// instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LimitHitDescription {
+    pub receipt_id: CryptoHash,
+    pub shard_id: ShardId,
+    pub available_gas: Gas,
+    pub required_gas: Gas,
+    pub available_size: u64,
+    pub required_size: u64,
+    /// The limit that was hit. If several were hit, the receipt count is
+    /// reported first, then gas.
+    pub binding_constraint: GasOrSize,
+}
+
+impl fmt::Display for LimitHitDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "receipt {} to shard {} hit the {:?} limit: gas {}/{} size {}/{}",
+            self.receipt_id,
+            self.shard_id,
+            self.binding_constraint,
+            self.required_gas,
+            self.available_gas,
+            self.required_size,
+            self.available_size,
+        )
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -99,54 +225,15 @@ impl ReceiptSink {
         prev_own_congestion_info: Option<CongestionInfo>,
         bandwidth_scheduler_output: Option<BandwidthSchedulerOutput>,
     ) -> Result<Self, StorageError> {
+        let sink = ReceiptSinkV1 { outgoing_receipts: Vec::new() };
         if let Some(own_congestion_info) = prev_own_congestion_info {
             debug_assert!(ProtocolFeature::CongestionControl.enabled(protocol_version));
-            let outgoing_buffers = ShardsOutgoingReceiptBuffer::load(trie)?;
-
-            let outgoing_limit: HashMap<ShardId, OutgoingLimit> = apply_state
-                .congestion_info
-                .iter()
-                .map(|(&shard_id, congestion)| {
-                    let other_congestion_control = CongestionControl::new(
-                        apply_state.config.congestion_control_config,
-                        congestion.congestion_info,
-                        congestion.missed_chunks_count,
-                    );
-                    let gas_limit = if shard_id != apply_state.shard_id {
-                        other_congestion_control.outgoing_gas_limit(apply_state.shard_id)
-                    } else {
-                        // No gas limits on receipts that stay on the same shard. Backpressure
-                        // wouldn't help, the receipt takes the same memory if buffered or
-                        // in the delayed receipts queue.
-                        Gas::MAX
-                    };
-
-                    let size_limit =
-                        other_congestion_control.outgoing_size_limit(apply_state.shard_id);
-
-                    (shard_id, OutgoingLimit { gas: gas_limit, size: size_limit })
-                })
-                .collect();
-
-            let outgoing_metadatas = OutgoingMetadatas::load(
-                trie,
-                outgoing_buffers.shards(),
-                ReceiptGroupsConfig::default_config(),
-                apply_state.current_protocol_version,
-            )?;
-
-            Ok(ReceiptSink::V2(ReceiptSinkV2 {
-                own_congestion_info,
-                outgoing_receipts: Vec::new(),
-                outgoing_limit,
-                outgoing_buffers,
-                outgoing_metadatas,
-                bandwidth_scheduler_output,
-                protocol_version,
-            }))
+            let mut sink = sink.into_v2(trie, apply_state, own_congestion_info)?;
+            sink.bandwidth_scheduler_output = bandwidth_scheduler_output;
+            Ok(ReceiptSink::V2(sink))
         } else {
             debug_assert!(!ProtocolFeature::CongestionControl.enabled(protocol_version));
-            Ok(ReceiptSink::V1(ReceiptSinkV1 { outgoing_receipts: Vec::new() }))
+            Ok(ReceiptSink::V1(sink))
         }
     }
 
@@ -163,6 +250,41 @@ impl ReceiptSink {
         }
     }
 
+    /// Forward more receipts from the buffers at the end of the chunk, as much
+    /// as the remaining limits allow. Returns the number of forwarded receipts.
+    pub(crate) fn try_forward_all_remaining(
+        &mut self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+    ) -> Result<u64, RuntimeError> {
+        // Buffered receipts always count towards the memory congestion, so
+        // there is nothing left to forward if the shard isn't congested at all.
+        let config = &apply_state.config.congestion_control_config;
+        if self.effective_congestion_level(config) == 0.0 {
+            return Ok(0);
+        }
+        match self {
+            ReceiptSink::V1(_inner) => Ok(0),
+            ReceiptSink::V2(inner) => inner.try_forward_all_remaining(state_update, apply_state),
+        }
+    }
+
+    /// Remove all receipts from the outgoing buffers, ignoring the outgoing
+    /// limits, and return them so that the caller can redistribute them.
+    /// Meant for resharding, where the buffers of the parent shard are split
+    /// between the children.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn drain_all_buffers_unconditionally(
+        &mut self,
+        state_update: &mut TrieUpdate,
+        config: &RuntimeConfig,
+    ) -> Result<Vec<Receipt>, RuntimeError> {
+        match self {
+            ReceiptSink::V1(_inner) => Ok(vec![]),
+            ReceiptSink::V2(inner) => inner.drain_all_buffers_unconditionally(state_update, config),
+        }
+    }
+
     /// Put a receipt in the outgoing receipts vector (=forward) if the
     /// congestion preventing limits allow it. Put it in the buffered receipts
     /// queue otherwise.
@@ -208,6 +330,42 @@ impl ReceiptSink {
         }
     }
 
+    /// Statistics about the receipts forwarded and buffered while applying the
+    /// chunk. Without congestion control receipts are never buffered and
+    /// nothing is tracked.
+    pub(crate) fn generate_apply_stats(&self) -> ReceiptForwardingStats {
+        match self {
+            ReceiptSink::V1(_) => ReceiptForwardingStats::default(),
+            ReceiptSink::V2(inner) => inner.forwarding_stats,
+        }
+    }
+
+    /// Congestion level of the own shard, computed from the congestion info
+    /// that is updated while the chunk is applied, rather than from the one
+    /// stored at the end of the previous chunk. Changes to the delayed receipts
+    /// are only included after [`Self::apply_accounting_to_congestion`].
+    pub(crate) fn effective_congestion_level(&self, config: &CongestionControlConfig) -> f64 {
+        match self {
+            ReceiptSink::V1(_) => 0.0,
+            ReceiptSink::V2(inner) => inner.own_congestion_info.localized_congestion_level(config),
+        }
+    }
+
+    /// Applies the changes in delayed receipts gas and bytes accumulated in the
+    /// wrapper to the own congestion info. Without congestion control there is
+    /// nothing to account for.
+    pub(crate) fn apply_accounting_to_congestion(
+        &mut self,
+        delayed_wrapper: DelayedReceiptQueueWrapper,
+    ) -> Result<(), RuntimeError> {
+        match self {
+            ReceiptSink::V1(_) => Ok(()),
+            ReceiptSink::V2(inner) => {
+                delayed_wrapper.apply_congestion_changes(&mut inner.own_congestion_info)
+            }
+        }
+    }
+
     pub(crate) fn bandwidth_scheduler_output(&self) -> Option<&BandwidthSchedulerOutput> {
         match self {
             ReceiptSink::V1(_) => None,
@@ -233,9 +391,100 @@ impl ReceiptSinkV1 {
     pub(crate) fn forward(&mut self, receipt: Receipt) {
         self.outgoing_receipts.push(receipt);
     }
+
+    /// Upgrades the sink to a [`ReceiptSinkV2`] once congestion control is
+    /// enabled. The receipts forwarded so far are kept, while the outgoing
+    /// buffers and their metadata are loaded from the trie.
+    pub(crate) fn into_v2(
+        self,
+        trie: &dyn TrieAccess,
+        apply_state: &ApplyState,
+        initial_congestion: CongestionInfo,
+    ) -> Result<ReceiptSinkV2, StorageError> {
+        let outgoing_buffers = ShardsOutgoingReceiptBuffer::load(trie)?;
+
+        let outgoing_limit: HashMap<ShardId, OutgoingLimit> = apply_state
+            .congestion_info
+            .iter()
+            .map(|(&shard_id, congestion)| {
+                let other_congestion_control = CongestionControl::new(
+                    apply_state.config.congestion_control_config,
+                    congestion.congestion_info,
+                    congestion.missed_chunks_count,
+                );
+                let gas_limit = if shard_id != apply_state.shard_id {
+                    other_congestion_control.outgoing_gas_limit(apply_state.shard_id)
+                } else {
+                    // No gas limits on receipts that stay on the same shard. Backpressure
+                    // wouldn't help, the receipt takes the same memory if buffered or
+                    // in the delayed receipts queue.
+                    Gas::MAX
+                };
+
+                let size_limit = other_congestion_control.outgoing_size_limit(apply_state.shard_id);
+                let receipts_limit = if shard_id != apply_state.shard_id {
+                    other_congestion_control.outgoing_receipts_limit()
+                } else {
+                    // Same as for gas, limiting receipts to the own shard doesn't help.
+                    u64::MAX
+                };
+
+                (
+                    shard_id,
+                    OutgoingLimit {
+                        gas: gas_limit,
+                        size: size_limit,
+                        max_receipts: receipts_limit,
+                    },
+                )
+            })
+            .collect();
+
+        let outgoing_metadatas = OutgoingMetadatas::load(
+            trie,
+            outgoing_buffers.shards(),
+            ReceiptGroupsConfig::default_config(),
+            apply_state.current_protocol_version,
+        )?;
+
+        Ok(ReceiptSinkV2 {
+            own_congestion_info: initial_congestion,
+            outgoing_receipts: self.outgoing_receipts,
+            outgoing_receipts_shards: HashSet::new(),
+            outgoing_limit,
+            outgoing_buffers,
+            outgoing_metadatas,
+            bandwidth_scheduler_output: None,
+            protocol_version: apply_state.current_protocol_version,
+            forwarding_stats: ReceiptForwardingStats::default(),
+        })
+    }
 }
 
 impl ReceiptSinkV2 {
+    /// Creates a sink with empty outgoing buffers and the given outgoing
+    /// limits, for tests which need precise limits without setting up the
+    /// congestion info of all shards in an `ApplyState`. The buffers only
+    /// match tries without any buffered receipts.
+    #[cfg(test)]
+    pub(crate) fn for_testing(
+        outgoing_limits: HashMap<ShardId, OutgoingLimit>,
+        own_congestion_info: CongestionInfo,
+        protocol_version: ProtocolVersion,
+    ) -> Self {
+        Self {
+            own_congestion_info,
+            outgoing_receipts: Vec::new(),
+            outgoing_receipts_shards: HashSet::new(),
+            outgoing_limit: outgoing_limits,
+            outgoing_buffers: ShardsOutgoingReceiptBuffer::empty(),
+            outgoing_metadatas: OutgoingMetadatas::new(ReceiptGroupsConfig::default_config()),
+            bandwidth_scheduler_output: None,
+            protocol_version,
+            forwarding_stats: ReceiptForwardingStats::default(),
+        }
+    }
+
     /// Forward receipts already in the buffer to the outgoing receipts vector, as
     /// much as the gas limits allow.
     pub(crate) fn forward_from_buffer(
@@ -243,20 +492,119 @@ impl ReceiptSinkV2 {
         state_update: &mut TrieUpdate,
         apply_state: &ApplyState,
     ) -> Result<(), RuntimeError> {
-        // store shards in vec to avoid borrowing self.outgoing_limit
-        let shards: Vec<_> = self.outgoing_limit.keys().copied().collect();
+        let shards = self.outgoing_limit_shards();
         for shard_id in shards {
             self.forward_from_buffer_to_shard(shard_id, state_update, apply_state)?;
         }
         Ok(())
     }
 
+    /// Shards with an outgoing limit, sorted by shard ID so that the buffers
+    /// are always drained in the same order. The IDs are collected in a vec
+    /// to avoid borrowing `self.outgoing_limit` while forwarding.
+    fn outgoing_limit_shards(&self) -> Vec<ShardId> {
+        let mut shards: Vec<ShardId> = self.outgoing_limit.keys().copied().collect();
+        shards.sort();
+        shards
+    }
+
+    /// Forward receipts already in the buffer to the outgoing receipts vector,
+    /// allowing up to `gas_budget` and `size_budget` to be forwarded to each
+    /// shard instead of the outgoing limits. The outgoing limits are left
+    /// untouched. Returns the number of forwarded receipts.
+    #[cfg(test)]
+    pub(crate) fn forward_from_buffer_with_budget(
+        &mut self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        gas_budget: Gas,
+        size_budget: u64,
+    ) -> Result<u64, RuntimeError> {
+        let budget = OutgoingLimit { gas: gas_budget, size: size_budget, max_receipts: u64::MAX };
+        let budgets = self.outgoing_limit.keys().map(|shard_id| (*shard_id, budget)).collect();
+        let outgoing_limit = std::mem::replace(&mut self.outgoing_limit, budgets);
+
+        let mut num_forwarded = 0;
+        for shard_id in self.outgoing_limit_shards() {
+            num_forwarded +=
+                self.forward_from_buffer_to_shard(shard_id, state_update, apply_state)?;
+        }
+        self.outgoing_limit = outgoing_limit;
+        Ok(num_forwarded)
+    }
+
+    /// Forward more receipts from the buffers after all receipts of the chunk
+    /// were processed, using the outgoing limits that are left. Returns the
+    /// number of additionally forwarded receipts.
+    pub(crate) fn try_forward_all_remaining(
+        &mut self,
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+    ) -> Result<u64, RuntimeError> {
+        if !ProtocolFeature::ForwardRemainingBufferedReceipts.enabled(self.protocol_version) {
+            return Ok(0);
+        }
+
+        let shards = self.outgoing_limit_shards();
+        let mut num_forwarded = 0;
+        for shard_id in shards {
+            num_forwarded +=
+                self.forward_from_buffer_to_shard(shard_id, state_update, apply_state)?;
+        }
+        Ok(num_forwarded)
+    }
+
+    /// Remove all receipts from the outgoing buffers, in shard ID order,
+    /// without checking the outgoing limits. The removed receipts are
+    /// returned instead of being forwarded, and they no longer count towards
+    /// the own congestion info and the outgoing metadata.
+    pub(crate) fn drain_all_buffers_unconditionally(
+        &mut self,
+        state_update: &mut TrieUpdate,
+        config: &RuntimeConfig,
+    ) -> Result<Vec<Receipt>, RuntimeError> {
+        let mut shards = self.outgoing_buffers.shards();
+        shards.sort();
+
+        let mut drained = Vec::new();
+        for shard_id in shards {
+            let mut outgoing_metadatas_updates: Vec<(ByteSize, Gas)> = Vec::new();
+            let mut num_drained = 0;
+            for receipt_result in
+                self.outgoing_buffers.to_shard(shard_id).iter(&state_update.trie, true)
+            {
+                let receipt = receipt_result?;
+                let gas = receipt_congestion_gas(&receipt, config)?;
+                let size = receipt_size(&receipt)?;
+                if receipt.should_update_outgoing_metadatas() {
+                    // Can't update metadatas immediately because state_update is borrowed by iterator.
+                    outgoing_metadatas_updates.push((ByteSize::b(size), gas));
+                }
+                self.own_congestion_info.remove_receipt_bytes(size)?;
+                self.own_congestion_info.remove_buffered_receipt_gas(gas)?;
+                drained.push(receipt.into_receipt());
+                num_drained += 1;
+            }
+            self.outgoing_buffers.to_shard(shard_id).pop_n(state_update, num_drained)?;
+            for (size, gas) in outgoing_metadatas_updates {
+                self.outgoing_metadatas.update_on_receipt_popped(
+                    shard_id,
+                    size,
+                    gas,
+                    state_update,
+                )?;
+            }
+        }
+        Ok(drained)
+    }
+
+    /// Returns the number of receipts forwarded from the buffer to the shard.
     fn forward_from_buffer_to_shard(
         &mut self,
         shard_id: ShardId,
         state_update: &mut TrieUpdate,
         apply_state: &ApplyState,
-    ) -> Result<(), RuntimeError> {
+    ) -> Result<u64, RuntimeError> {
         let mut num_forwarded = 0;
         let mut outgoing_metadatas_updates: Vec<(ByteSize, Gas)> = Vec::new();
         for receipt_result in
@@ -275,11 +623,12 @@ impl ReceiptSinkV2 {
                 shard_id,
                 &mut self.outgoing_limit,
                 &mut self.outgoing_receipts,
+                &mut self.outgoing_receipts_shards,
                 apply_state,
             )? {
                 ReceiptForwarding::Forwarded => {
-                    self.own_congestion_info.remove_receipt_bytes(size)?;
-                    self.own_congestion_info.remove_buffered_receipt_gas(gas)?;
+                    self.own_congestion_info.subtract_forwarded(size, gas)?;
+                    self.forwarding_stats.on_receipt_forwarded(size, gas);
                     if should_update_outgoing_metadatas {
                         // Can't update metadatas immediately because state_update is borrowed by iterator.
                         outgoing_metadatas_updates.push((ByteSize::b(size), gas));
@@ -298,7 +647,10 @@ impl ReceiptSinkV2 {
         for (size, gas) in outgoing_metadatas_updates {
             self.outgoing_metadatas.update_on_receipt_popped(shard_id, size, gas, state_update)?;
         }
-        Ok(())
+        if num_forwarded > 0 && self.outgoing_buffers.to_shard(shard_id).len() == 0 {
+            self.forwarding_stats.num_buffer_flushes += 1;
+        }
+        Ok(num_forwarded)
     }
 
     /// Put a receipt in the outgoing receipts vector (=forward) if the
@@ -324,12 +676,16 @@ impl ReceiptSinkV2 {
             shard,
             &mut self.outgoing_limit,
             &mut self.outgoing_receipts,
+            &mut self.outgoing_receipts_shards,
             apply_state,
         )? {
-            ReceiptForwarding::Forwarded => (),
+            ReceiptForwarding::Forwarded => {
+                self.forwarding_stats.on_receipt_forwarded(size, gas);
+            }
             ReceiptForwarding::NotForwarded(receipt) => {
+                self.forwarding_stats.on_receipt_buffered(size, gas);
                 self.buffer_receipt(
-                    receipt,
+                    &receipt,
                     size,
                     gas,
                     state_update,
@@ -341,12 +697,85 @@ impl ReceiptSinkV2 {
         Ok(())
     }
 
+    /// Same as calling [`ReceiptSinkV2::forward_or_buffer_receipt`] for every
+    /// receipt, but the receipts are first grouped by receiving shard and each
+    /// group is forwarded together. The receiving shard is looked up once per
+    /// receiver account and the outgoing receipts vector grows once per group.
+    ///
+    /// The relative order of the receipts to the same shard is preserved, so
+    /// the outgoing receipts and buffers of every shard end up the same as
+    /// with sequential forwarding. Only the interleaving of receipts to
+    /// different shards in the outgoing receipts vector differs.
+    ///
+    /// Returns the receipts which were buffered instead of forwarded, grouped
+    /// by receiving shard.
+    pub(crate) fn forward_receipts_batch(
+        &mut self,
+        receipts: Vec<Receipt>,
+        apply_state: &ApplyState,
+        state_update: &mut TrieUpdate,
+        epoch_info_provider: &dyn EpochInfoProvider,
+    ) -> Result<Vec<Receipt>, RuntimeError> {
+        let mut receiver_shards: HashMap<AccountId, ShardId> = HashMap::new();
+        // A BTreeMap keeps the order in which groups are forwarded
+        // deterministic.
+        let mut groups: BTreeMap<ShardId, Vec<Receipt>> = BTreeMap::new();
+        for receipt in receipts {
+            let shard = match receiver_shards.get(receipt.receiver_id()) {
+                Some(shard) => *shard,
+                None => {
+                    let shard = epoch_info_provider
+                        .account_id_to_shard_id(receipt.receiver_id(), &apply_state.epoch_id)?;
+                    receiver_shards.insert(receipt.receiver_id().clone(), shard);
+                    shard
+                }
+            };
+            groups.entry(shard).or_default().push(receipt);
+        }
+
+        let mut buffered = vec![];
+        for (shard, group) in groups {
+            self.outgoing_receipts.reserve(group.len());
+            for receipt in group {
+                let size = compute_receipt_size(&receipt)?;
+                let gas = compute_receipt_congestion_gas(&receipt, &apply_state.config)?;
+                match Self::try_forward(
+                    receipt,
+                    gas,
+                    size,
+                    shard,
+                    &mut self.outgoing_limit,
+                    &mut self.outgoing_receipts,
+                    &mut self.outgoing_receipts_shards,
+                    apply_state,
+                )? {
+                    ReceiptForwarding::Forwarded => {
+                        self.forwarding_stats.on_receipt_forwarded(size, gas);
+                    }
+                    ReceiptForwarding::NotForwarded(receipt) => {
+                        self.forwarding_stats.on_receipt_buffered(size, gas);
+                        self.buffer_receipt(
+                            &receipt,
+                            size,
+                            gas,
+                            state_update,
+                            shard,
+                            apply_state.config.use_state_stored_receipt,
+                        )?;
+                        buffered.push(receipt);
+                    }
+                }
+            }
+        }
+        Ok(buffered)
+    }
+
     /// Forward a receipt if possible and return whether it was forwarded or
     /// not.
     ///
     /// This does not take `&mut self` as first argument to make lifetime
     /// management easier. Instead it takes exactly the fields it requires,
-    /// namely `outgoing_limit` and `outgoing_receipt`.
+    /// namely `outgoing_limit`, `outgoing_receipts` and `outgoing_receipts_shards`.
     fn try_forward(
         receipt: Receipt,
         gas: u64,
@@ -354,6 +783,7 @@ impl ReceiptSinkV2 {
         shard: ShardId,
         outgoing_limit: &mut HashMap<ShardId, OutgoingLimit>,
         outgoing_receipts: &mut Vec<Receipt>,
+        outgoing_receipts_shards: &mut HashSet<ShardId>,
         apply_state: &ApplyState,
     ) -> Result<ReceiptForwarding, RuntimeError> {
         // Default case set to `Gas::MAX`: If no outgoing limit was defined for the receiving
@@ -361,18 +791,23 @@ impl ReceiptSinkV2 {
         // could be a special case during resharding events. Or even a bug. In
         // any case, if we cannot know a limit, treating it as literally "no
         // limit" is the safest approach to ensure availability.
-        // For the size limit, we default to the usual limit that is applied to all (non-special) shards.
+        // For the size and receipt count limits, we default to the usual
+        // limits that are applied to all (non-special) shards.
+        let congestion_control_config = &apply_state.config.congestion_control_config;
         let default_outgoing_limit = OutgoingLimit {
             gas: Gas::MAX,
-            size: apply_state.config.congestion_control_config.outgoing_receipts_usual_size_limit,
+            size: congestion_control_config.outgoing_receipts_usual_size_limit,
+            max_receipts: congestion_control_config.max_outgoing_receipts,
         };
         let forward_limit = outgoing_limit.entry(shard).or_insert(default_outgoing_limit);
 
-        if forward_limit.gas > gas && forward_limit.size > size {
+        if forward_limit.gas > gas && forward_limit.size > size && forward_limit.max_receipts > 0 {
             outgoing_receipts.push(receipt);
-            // underflow impossible: checked forward_limit > gas/size_to_forward above
+            outgoing_receipts_shards.insert(shard);
+            // underflow impossible: checked forward_limit > gas/size_to_forward/0 above
             forward_limit.gas -= gas;
             forward_limit.size -= size;
+            forward_limit.max_receipts -= 1;
             Ok(ReceiptForwarding::Forwarded)
         } else {
             Ok(ReceiptForwarding::NotForwarded(receipt))
@@ -382,23 +817,28 @@ impl ReceiptSinkV2 {
     /// Put a receipt in the outgoing receipt buffer of a shard.
     fn buffer_receipt(
         &mut self,
-        receipt: Receipt,
+        receipt: &Receipt,
         size: u64,
         gas: u64,
         state_update: &mut TrieUpdate,
         shard: ShardId,
         use_state_stored_receipt: bool,
     ) -> Result<(), RuntimeError> {
+        tracing::debug!(
+            target: "runtime",
+            limit_hit = %self.describe_limit_hit(receipt, shard, gas, size),
+            "buffering receipt"
+        );
         let receipt = match use_state_stored_receipt {
             true => {
                 let metadata =
                     StateStoredReceiptMetadata { congestion_gas: gas, congestion_size: size };
                 let receipt =
-                    StateStoredReceipt::new_owned(receipt, metadata, self.protocol_version);
+                    StateStoredReceipt::new_borrowed(receipt, metadata, self.protocol_version);
                 let receipt = ReceiptOrStateStoredReceipt::StateStoredReceipt(receipt);
                 receipt
             }
-            false => ReceiptOrStateStoredReceipt::Receipt(std::borrow::Cow::Owned(receipt)),
+            false => ReceiptOrStateStoredReceipt::Receipt(std::borrow::Cow::Borrowed(receipt)),
         };
 
         self.own_congestion_info.add_receipt_bytes(size)?;
@@ -417,6 +857,128 @@ impl ReceiptSinkV2 {
         Ok(())
     }
 
+    /// Describes which outgoing limit of `shard` prevents forwarding `receipt`
+    /// with the given congestion `gas` and `size`.
+    pub(crate) fn describe_limit_hit(
+        &self,
+        receipt: &Receipt,
+        shard: ShardId,
+        gas: Gas,
+        size: u64,
+    ) -> LimitHitDescription {
+        let limit = self.outgoing_limit.get(&shard).copied().unwrap_or(OutgoingLimit {
+            gas: Gas::MAX,
+            size: u64::MAX,
+            max_receipts: u64::MAX,
+        });
+        // Same condition as in `try_forward`.
+        let binding_constraint = if limit.max_receipts == 0 {
+            GasOrSize::ReceiptCount
+        } else if limit.gas <= gas {
+            GasOrSize::Gas
+        } else {
+            GasOrSize::Size
+        };
+        LimitHitDescription {
+            receipt_id: *receipt.receipt_id(),
+            shard_id: shard,
+            available_gas: limit.gas,
+            required_gas: gas,
+            available_size: limit.size,
+            required_size: size,
+            binding_constraint,
+        }
+    }
+
+    /// Returns true if there are receipts for `shard_id` that are either
+    /// forwarded in this chunk or still waiting in the outgoing buffer.
+    pub(crate) fn has_pending_receipts_for_shard(&self, shard_id: ShardId) -> bool {
+        self.outgoing_receipts_shards.contains(&shard_id)
+            || self.outgoing_buffers.buffer_len(shard_id).unwrap_or(0) > 0
+    }
+
+    /// Returns true if the sink holds no receipts at all, neither in the
+    /// outgoing receipts nor in any of the outgoing buffers.
+    pub(crate) fn is_fully_drained(&self) -> bool {
+        self.outgoing_receipts.is_empty()
+            && self
+                .outgoing_buffers
+                .shards()
+                .into_iter()
+                .all(|shard_id| !self.has_pending_receipts_for_shard(shard_id))
+    }
+
+    /// Capture the current backpressure state of the sink.
+    pub(crate) fn backpressure_snapshot(&self) -> BackpressureSnapshot {
+        BackpressureSnapshot {
+            own_congestion_info: self.own_congestion_info,
+            outgoing_limit: self
+                .outgoing_limit
+                .iter()
+                .map(|(&shard_id, &limit)| (shard_id, limit))
+                .collect(),
+            buffer_lens: self
+                .outgoing_buffers
+                .shards()
+                .into_iter()
+                .map(|shard_id| (shard_id, self.outgoing_buffers.buffer_len(shard_id).unwrap_or(0)))
+                .collect(),
+        }
+    }
+
+    /// Reads all receipts in the outgoing buffer to the given shard, in order.
+    /// The receipts buffered by this sink are only visible if `trie` is the
+    /// trie update they were written to.
+    #[cfg(any(test, feature = "test_features"))]
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn inspect_outgoing_buffer(
+        &self,
+        shard_id: ShardId,
+        trie: &dyn TrieAccess,
+    ) -> Result<Vec<Receipt>, StorageError> {
+        let mut outgoing_buffers = ShardsOutgoingReceiptBuffer::load(trie)?;
+        debug_assert_eq!(
+            outgoing_buffers.buffer_len(shard_id),
+            self.outgoing_buffers.buffer_len(shard_id)
+        );
+        outgoing_buffers
+            .to_shard(shard_id)
+            .iter(trie, false)
+            .map(|receipt| receipt.map(ReceiptOrStateStoredReceipt::into_receipt))
+            .collect()
+    }
+
+    /// Logs the buffer length and outgoing limits of every destination shard,
+    /// together with the own congestion info, to help debugging failed tests.
+    #[cfg(test)]
+    pub(crate) fn debug_print_buffer_sizes(&self) {
+        let shards: std::collections::BTreeSet<ShardId> = self
+            .outgoing_buffers
+            .shards()
+            .into_iter()
+            .chain(self.outgoing_limit.keys().copied())
+            .collect();
+        for shard_id in shards {
+            let limit = self.outgoing_limit.get(&shard_id);
+            tracing::debug!(
+                target: "runtime",
+                %shard_id,
+                buffer_len = self.outgoing_buffers.buffer_len(shard_id).unwrap_or(0),
+                outgoing_limit_gas = ?limit.map(|limit| limit.gas),
+                outgoing_limit_size = ?limit.map(|limit| limit.size),
+                "outgoing buffer"
+            );
+        }
+        tracing::debug!(
+            target: "runtime",
+            delayed_receipts_gas = self.own_congestion_info.delayed_receipts_gas(),
+            buffered_receipts_gas = self.own_congestion_info.buffered_receipts_gas(),
+            receipt_bytes = self.own_congestion_info.receipt_bytes(),
+            allowed_shard = self.own_congestion_info.allowed_shard(),
+            "own congestion info"
+        );
+    }
+
     fn generate_bandwidth_requests(
         &self,
         trie: &dyn TrieAccess,
@@ -573,12 +1135,11 @@ pub fn bootstrap_congestion_info(
     let mut delayed_receipts_gas: u128 = 0;
     let mut buffered_receipts_gas: u128 = 0;
 
-    let delayed_receipt_queue = &DelayedReceiptQueue::load(trie)?;
-    for receipt_result in delayed_receipt_queue.iter(trie, true) {
-        let receipt = receipt_result?;
-        let gas = receipt_congestion_gas(&receipt, config).map_err(int_overflow_to_storage_err)?;
+    let delayed_receipt_queue = DelayedReceiptQueueWrapper::new(DelayedReceiptQueue::load(trie)?);
+    for receipt_result in delayed_receipt_queue.iter_with_gas(trie, config) {
+        let (receipt, gas) = receipt_result.map_err(runtime_err_to_storage_err)?;
         delayed_receipts_gas =
-            safe_add_gas_to_u128(delayed_receipts_gas, gas).map_err(int_overflow_to_storage_err)?;
+            safe_add_u64_to_u128(delayed_receipts_gas, gas).map_err(int_overflow_to_storage_err)?;
 
         let memory = receipt_size(&receipt).map_err(int_overflow_to_storage_err)? as u64;
         receipt_bytes = receipt_bytes.checked_add(memory).ok_or_else(overflow_storage_err)?;
@@ -590,7 +1151,7 @@ pub fn bootstrap_congestion_info(
             let receipt = receipt_result?;
             let gas =
                 receipt_congestion_gas(&receipt, config).map_err(int_overflow_to_storage_err)?;
-            buffered_receipts_gas = safe_add_gas_to_u128(buffered_receipts_gas, gas)
+            buffered_receipts_gas = safe_add_u64_to_u128(buffered_receipts_gas, gas)
                 .map_err(int_overflow_to_storage_err)?;
             let memory = receipt_size(&receipt).map_err(int_overflow_to_storage_err)? as u64;
             receipt_bytes = receipt_bytes.checked_add(memory).ok_or_else(overflow_storage_err)?;
@@ -610,6 +1171,224 @@ pub fn bootstrap_congestion_info(
     }))
 }
 
+/// Outgoing metadata of a shard which tracks more receipts than there are in
+/// the outgoing buffer to that shard, see [`check_outgoing_metadatas`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutgoingMetadataMismatch {
+    pub shard_id: ShardId,
+    /// Number of receipts in the outgoing buffer.
+    pub buffered_receipts: u64,
+    /// Number of receipts tracked by the metadata stored in the state.
+    pub stored_receipts: u64,
+    /// Number of receipts tracked by the metadata recomputed from the buffer.
+    pub recomputed_receipts: u64,
+}
+
+/// Checks that the outgoing metadata stored in `trie` is consistent with the
+/// outgoing buffers, i.e. that the metadata doesn't track more receipts than
+/// there are in the buffer to its shard. Only the queue lengths are compared,
+/// so the check is cheap enough to run on node startup.
+///
+/// If the check fails for any shard, the metadata is recomputed from the
+/// buffers with [`OutgoingMetadatas::recompute_from_buffer`] and written to
+/// `scratch_update`, which must be on the same state as `trie`. It must not be
+/// committed: writing the metadata outside of applying a chunk would change
+/// the state root. Returns the shards that failed the check.
+pub fn check_outgoing_metadatas(
+    trie: &dyn TrieAccess,
+    scratch_update: &mut TrieUpdate,
+    config: &RuntimeConfig,
+    protocol_version: ProtocolVersion,
+) -> Result<Vec<OutgoingMetadataMismatch>, StorageError> {
+    if !ProtocolFeature::BandwidthScheduler.enabled(protocol_version) {
+        return Ok(vec![]);
+    }
+    let buffers = ShardsOutgoingReceiptBuffer::load(trie)?;
+    let groups_config = ReceiptGroupsConfig::default_config();
+    let stored = OutgoingMetadatas::load(trie, buffers.shards(), groups_config, protocol_version)?;
+    let tracked_receipts = |metadatas: &OutgoingMetadatas, shard_id: ShardId| {
+        metadatas
+            .get_metadata_for_shard(&shard_id)
+            .map_or(0, |metadata| metadata.total_receipts_num())
+    };
+    let inconsistent_shards: Vec<ShardId> = buffers
+        .shards()
+        .into_iter()
+        .filter(|&shard_id| {
+            tracked_receipts(&stored, shard_id) > buffers.buffer_len(shard_id).unwrap_or(0)
+        })
+        .collect();
+    if inconsistent_shards.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut recomputed =
+        OutgoingMetadatas::load(trie, buffers.shards(), groups_config, protocol_version)?;
+    recomputed.recompute_from_buffer(&buffers, trie, config, scratch_update)?;
+    Ok(inconsistent_shards
+        .into_iter()
+        .map(|shard_id| OutgoingMetadataMismatch {
+            shard_id,
+            buffered_receipts: buffers.buffer_len(shard_id).unwrap_or(0),
+            stored_receipts: tracked_receipts(&stored, shard_id),
+            recomputed_receipts: tracked_receipts(&recomputed, shard_id),
+        })
+        .collect())
+}
+
+/// Runs [`bootstrap_congestion_info`] for each of the given shards in
+/// parallel, one rayon task per shard.
+///
+/// Each shard is read through its own view trie, so the tasks don't share any
+/// state. If bootstrapping fails for any of the shards, the error of the first
+/// failing shard in `shards` is returned, regardless of which task failed
+/// first, and the results of the other shards are discarded.
+pub fn bootstrap_congestion_info_for_all_shards(
+    tries: &ShardTries,
+    config: &RuntimeConfig,
+    shards: &[(ShardUId, StateRoot)],
+) -> Result<HashMap<ShardUId, CongestionInfo>, StorageError> {
+    let results: Vec<_> = shards
+        .par_iter()
+        .map(|&(shard_uid, state_root)| {
+            let trie = tries.get_view_trie_for_shard(shard_uid, state_root);
+            bootstrap_congestion_info(&trie, config, shard_uid.shard_id())
+                .map(|congestion_info| (shard_uid, congestion_info))
+        })
+        .collect();
+    results.into_iter().collect()
+}
+
+/// Sums the congestion gas of the first `n` receipts in the delayed receipts
+/// `queue`, without removing them. Returns the total of the whole queue if it
+/// holds fewer than `n` receipts.
+///
+/// Useful to estimate how much gas the next chunk will spend on delayed
+/// receipts before accepting new transactions.
+pub fn estimated_gas_in_next_n(
+    queue: &DelayedReceiptQueue,
+    trie: &dyn TrieAccess,
+    config: &RuntimeConfig,
+    n: usize,
+) -> Result<Gas, StorageError> {
+    let mut total_gas: Gas = 0;
+    for receipt_result in queue.iter(trie, true).take(n) {
+        let receipt = receipt_result?;
+        let gas = receipt_congestion_gas(&receipt, config).map_err(int_overflow_to_storage_err)?;
+        total_gas = safe_add_gas(total_gas, gas).map_err(int_overflow_to_storage_err)?;
+    }
+    Ok(total_gas)
+}
+
+/// Computes the same `CongestionInfo` as [`bootstrap_congestion_info`], but
+/// instead of looking up every queued receipt in the trie by its queue index,
+/// it scans the ranges of flat storage keys that hold delayed and buffered
+/// receipts.
+///
+/// This is meant for shards that a node starts tracking for the first time,
+/// e.g. after state sync, where the flat storage head is at the block of the
+/// state root that was just received. Range scans only see the flat state at
+/// the flat storage head, so the flat storage chunk view of `trie` must be at
+/// its head. Values which are not inlined in flat storage are read from `trie`.
+///
+/// Applying a chunk always uses [`bootstrap_congestion_info`], which reads the
+/// receipts through the trie so that chunk validators see the same reads.
+/// This function is only meant to be called explicitly by state sync or at
+/// node startup.
+pub fn bootstrap_congestion_info_incremental(
+    trie: &Trie,
+    config: &RuntimeConfig,
+    shard_id: ShardId,
+) -> Result<CongestionInfo, StorageError> {
+    let flat_storage_chunk_view = trie.get_flat_storage_chunk_view().ok_or_else(|| {
+        StorageError::StorageInconsistentState(format!(
+            "no flat storage to bootstrap the congestion info of shard {shard_id} from"
+        ))
+    })?;
+    let block_hash = flat_storage_chunk_view.get_block_hash();
+    let flat_head = flat_storage_chunk_view.get_head_hash();
+    if block_hash != flat_head {
+        return Err(StorageError::FlatStorageBlockNotSupported(format!(
+            "flat storage head {flat_head} is not at block {block_hash}"
+        )));
+    }
+    let _span = tracing::debug_span!(
+        target: "runtime",
+        "bootstrap_congestion_info_incremental",
+        ?shard_id,
+        ?block_hash
+    )
+    .entered();
+
+    let mut receipt_bytes: u64 = 0;
+    let mut delayed_receipts_gas: u128 = 0;
+    let mut buffered_receipts_gas: u128 = 0;
+
+    for_each_receipt_in_column(
+        flat_storage_chunk_view,
+        trie,
+        col::DELAYED_RECEIPT_OR_INDICES,
+        |receipt| {
+            let gas =
+                receipt_congestion_gas(receipt, config).map_err(int_overflow_to_storage_err)?;
+            delayed_receipts_gas = safe_add_u64_to_u128(delayed_receipts_gas, gas)
+                .map_err(int_overflow_to_storage_err)?;
+            let memory = receipt_size(receipt).map_err(int_overflow_to_storage_err)?;
+            receipt_bytes = receipt_bytes.checked_add(memory).ok_or_else(overflow_storage_err)?;
+            Ok(())
+        },
+    )?;
+
+    for_each_receipt_in_column(flat_storage_chunk_view, trie, col::BUFFERED_RECEIPT, |receipt| {
+        let gas = receipt_congestion_gas(receipt, config).map_err(int_overflow_to_storage_err)?;
+        buffered_receipts_gas = safe_add_u64_to_u128(buffered_receipts_gas, gas)
+            .map_err(int_overflow_to_storage_err)?;
+        let memory = receipt_size(receipt).map_err(int_overflow_to_storage_err)?;
+        receipt_bytes = receipt_bytes.checked_add(memory).ok_or_else(overflow_storage_err)?;
+        Ok(())
+    })?;
+
+    Ok(CongestionInfo::V1(CongestionInfoV1 {
+        delayed_receipts_gas,
+        buffered_receipts_gas,
+        receipt_bytes,
+        // Same as in `bootstrap_congestion_info`, the own shard id is good
+        // enough for the first chunk.
+        allowed_shard: shard_id.into(),
+    }))
+}
+
+/// Scan all receipts stored under the given trie column in flat storage.
+/// Values which are not inlined are read from `trie`.
+///
+/// The delayed receipt queue indices share a column with the delayed
+/// receipts. They are the only key without an index suffix and are skipped.
+fn for_each_receipt_in_column(
+    flat_storage_chunk_view: &FlatStorageChunkView,
+    trie: &Trie,
+    column: u8,
+    mut f: impl FnMut(&ReceiptOrStateStoredReceipt) -> Result<(), StorageError>,
+) -> Result<(), StorageError> {
+    for item in flat_storage_chunk_view.iter_range(Some(&[column]), Some(&[column + 1])) {
+        let (key, value) = item?;
+        if key.len() == 1 {
+            continue;
+        }
+        let value = match value {
+            FlatStateValue::Inlined(value) => value,
+            FlatStateValue::Ref(value_ref) => trie.retrieve_value(&value_ref.hash)?,
+        };
+        let receipt = ReceiptOrStateStoredReceipt::try_from_slice(&value).map_err(|err| {
+            StorageError::StorageInconsistentState(format!(
+                "failed to deserialize receipt stored in column {}: {}",
+                column, err
+            ))
+        })?;
+        f(&receipt)?;
+    }
+    Ok(())
+}
+
 impl DelayedReceiptQueueWrapper {
     pub fn new(queue: DelayedReceiptQueue) -> Self {
         Self {
@@ -669,6 +1448,23 @@ impl DelayedReceiptQueueWrapper {
         Ok(receipt)
     }
 
+    /// Pops all receipts from the queue and returns them in order, paired
+    /// with their congestion gas. The removed gas and bytes are accounted for
+    /// like with [`Self::pop`].
+    #[cfg(test)]
+    pub(crate) fn drain_to_vec(
+        &mut self,
+        trie_update: &mut TrieUpdate,
+        config: &RuntimeConfig,
+    ) -> Result<Vec<(Receipt, Gas)>, RuntimeError> {
+        let mut receipts = Vec::new();
+        while let Some(receipt) = self.pop(trie_update, config)? {
+            let gas = receipt_congestion_gas(&receipt, config)?;
+            receipts.push((receipt.into_receipt(), gas));
+        }
+        Ok(receipts)
+    }
+
     pub(crate) fn peek_iter<'a>(
         &'a self,
         trie_update: &'a TrieUpdate,
@@ -676,6 +1472,24 @@ impl DelayedReceiptQueueWrapper {
         self.queue.iter(trie_update, false)
     }
 
+    /// Iterates over the delayed receipts, paired with their congestion gas.
+    ///
+    /// Unlike [`Self::peek_iter`], the trie reads have side effects, so that
+    /// it can be used while applying a chunk the same way as iterating over
+    /// the queue directly.
+    pub(crate) fn iter_with_gas<'a>(
+        &'a self,
+        trie: &'a dyn TrieAccess,
+        config: &'a RuntimeConfig,
+    ) -> impl Iterator<Item = Result<(ReceiptOrStateStoredReceipt<'static>, Gas), RuntimeError>> + 'a
+    {
+        self.queue.iter(trie, true).map(move |receipt_result| {
+            let receipt = receipt_result?;
+            let gas = receipt_congestion_gas(&receipt, config)?;
+            Ok((receipt, gas))
+        })
+    }
+
     pub(crate) fn len(&self) -> u64 {
         self.queue.len()
     }
@@ -688,6 +1502,11 @@ impl DelayedReceiptQueueWrapper {
         congestion.remove_delayed_receipt_gas(self.removed_delayed_gas)?;
         congestion.add_receipt_bytes(self.new_delayed_bytes)?;
         congestion.remove_receipt_bytes(self.removed_delayed_bytes)?;
+        debug_assert_eq!(
+            congestion.sanity_check(),
+            Ok(()),
+            "inconsistent congestion info after applying delayed receipts"
+        );
         Ok(())
     }
 }
@@ -722,13 +1541,15 @@ fn int_overflow_to_storage_err(_err: IntegerOverflowError) -> StorageError {
     overflow_storage_err()
 }
 
+fn runtime_err_to_storage_err(err: RuntimeError) -> StorageError {
+    match err {
+        RuntimeError::StorageError(err) => err,
+        _ => overflow_storage_err(),
+    }
+}
+
 fn overflow_storage_err() -> StorageError {
     StorageError::StorageInconsistentState(
         "Calculations on stored receipt overflows calculations".to_owned(),
     )
 }
-
-// we use u128 for accumulated gas because congestion may deal with a lot of gas
-fn safe_add_gas_to_u128(a: u128, b: Gas) -> Result<u128, IntegerOverflowError> {
-    a.checked_add(b as u128).ok_or(IntegerOverflowError {})
-}
@@ -12,8 +12,12 @@ pub use crate::verifier::{
 };
 use bandwidth_scheduler::{run_bandwidth_scheduler, BandwidthSchedulerOutput};
 use config::total_prepaid_send_fees;
-pub use congestion_control::bootstrap_congestion_info;
 use congestion_control::ReceiptSink;
+pub use congestion_control::{
+    bootstrap_congestion_info, bootstrap_congestion_info_for_all_shards,
+    bootstrap_congestion_info_incremental, check_outgoing_metadatas, estimated_gas_in_next_n,
+    OutgoingMetadataMismatch, ReceiptForwardingStats,
+};
 use metrics::ApplyMetrics;
 pub use near_crypto;
 use near_parameters::{ActionCosts, RuntimeConfig};
@@ -201,6 +205,8 @@ pub struct ApplyResult {
     pub bandwidth_scheduler_state_hash: CryptoHash,
     /// Contracts accessed and deployed while applying the chunk.
     pub contract_updates: ContractUpdates,
+    /// Receipts forwarded and buffered while applying the chunk.
+    pub receipt_forwarding_stats: ReceiptForwardingStats,
 }
 
 #[derive(Debug)]
@@ -1501,6 +1507,14 @@ impl Runtime {
         )?;
         // Forward buffered receipts from previous chunks.
         receipt_sink.forward_from_buffer(&mut processing_state.state_update, apply_state)?;
+        if let ReceiptSink::V2(inner) = &receipt_sink {
+            tracing::trace!(
+                target: "runtime",
+                shard_id = ?apply_state.shard_id,
+                backpressure = %inner.backpressure_snapshot(),
+                "forwarded buffered receipts"
+            );
+        }
 
         // Step 3: process transactions.
         self.process_transactions(&mut processing_state, &mut receipt_sink)?;
@@ -1509,6 +1523,46 @@ impl Runtime {
         let process_receipts_result =
             self.process_receipts(&mut processing_state, &mut receipt_sink)?;
 
+        // Use what is left of the outgoing limits to forward more buffered receipts.
+        let num_forwarded = receipt_sink
+            .try_forward_all_remaining(&mut processing_state.state_update, apply_state)?;
+        let fully_drained =
+            matches!(&receipt_sink, ReceiptSink::V2(inner) if inner.is_fully_drained());
+        tracing::trace!(
+            target: "runtime",
+            shard_id = ?apply_state.shard_id,
+            num_forwarded,
+            fully_drained,
+            "forwarded remaining buffered receipts"
+        );
+        // Summing up the buffered bytes reads the metadata of every shard, so
+        // only do it when the trace is actually recorded.
+        let metadata_trace_enabled =
+            tracing::event_enabled!(target: "runtime", tracing::Level::TRACE);
+        match &receipt_sink {
+            ReceiptSink::V2(inner) if metadata_trace_enabled => {
+                let total_buffered_bytes: u64 = inner
+                    .outgoing_buffers
+                    .shards()
+                    .iter()
+                    .filter_map(|shard_id| {
+                        inner.outgoing_metadatas.get_metadata_for_shard(shard_id)
+                    })
+                    .map(|metadata| metadata.total_size())
+                    .sum();
+                tracing::trace!(
+                    target: "runtime",
+                    shard_id = ?apply_state.shard_id,
+                    metadata_size_bytes = inner.outgoing_metadatas.total_metadata_size_bytes(),
+                    total_buffered_bytes,
+                    storage_overhead_ratio =
+                        inner.outgoing_metadatas.storage_overhead_ratio(total_buffered_bytes),
+                    "outgoing buffers metadata"
+                );
+            }
+            _ => {}
+        }
+
         // After receipt processing is done, report metrics on outgoing buffers
         // and on congestion indicators.
         metrics::report_congestion_metrics(
@@ -2043,7 +2097,7 @@ impl Runtime {
         processing_state: ApplyProcessingReceiptState<'a>,
         process_receipts_result: ProcessReceiptsResult,
         validator_accounts_update: &Option<ValidatorAccountsUpdate>,
-        receipt_sink: ReceiptSink,
+        mut receipt_sink: ReceiptSink,
         state_patch: SandboxStatePatch,
     ) -> Result<ApplyResult, RuntimeError> {
         let _span = tracing::debug_span!(target: "runtime", "apply_commit").entered();
@@ -2066,9 +2120,9 @@ impl Runtime {
         // this shard is fully congested.
 
         let delayed_receipts_count = delayed_receipts.len();
+        receipt_sink.apply_accounting_to_congestion(delayed_receipts)?;
         let mut own_congestion_info = receipt_sink.own_congestion_info();
         if let Some(congestion_info) = &mut own_congestion_info {
-            delayed_receipts.apply_congestion_changes(congestion_info)?;
             let all_shards = apply_state.congestion_info.all_shards();
 
             // TODO(wacban) Using non-contiguous shard id here breaks some
@@ -2080,6 +2134,11 @@ impl Runtime {
                 all_shards.as_slice(),
                 congestion_seed,
             );
+            debug_assert_eq!(
+                congestion_info.check_allowed_shard(apply_state.shard_id, all_shards.as_slice()),
+                Ok(()),
+                "allowed shard is not one of the shards of the block"
+            );
         }
 
         let bandwidth_requests = receipt_sink.generate_bandwidth_requests(&state_update, true)?;
@@ -2149,6 +2208,7 @@ impl Runtime {
             .bandwidth_scheduler_output()
             .map(|o| o.scheduler_state_hash)
             .unwrap_or_default();
+        let receipt_forwarding_stats = receipt_sink.generate_apply_stats();
         Ok(ApplyResult {
             state_root,
             trie_changes,
@@ -2166,6 +2226,7 @@ impl Runtime {
             bandwidth_requests,
             bandwidth_scheduler_state_hash,
             contract_updates,
+            receipt_forwarding_stats,
         })
     }
 }
@@ -2295,6 +2356,7 @@ fn missing_chunk_apply_result(
             .map(|o| o.scheduler_state_hash)
             .unwrap_or_default(),
         contract_updates,
+        receipt_forwarding_stats: ReceiptForwardingStats::default(),
     });
 }
 
@@ -2660,6 +2722,62 @@ pub mod estimator {
         stats: &mut ApplyStats,
         epoch_info_provider: &(dyn EpochInfoProvider),
     ) -> Result<ExecutionOutcomeWithId, RuntimeError> {
+        let mut receipt_sink = ReceiptSink::V2(limitless_receipt_sink(state_update, apply_state)?);
+        let empty_pipeline = ReceiptPreparationPipeline::new(
+            std::sync::Arc::clone(&apply_state.config),
+            apply_state.cache.as_ref().map(|c| c.handle()),
+            apply_state.current_protocol_version,
+            state_update.contract_storage(),
+        );
+        let apply_result = Runtime {}.apply_action_receipt(
+            state_update,
+            apply_state,
+            &empty_pipeline,
+            receipt,
+            &mut receipt_sink,
+            validator_proposals,
+            stats,
+            epoch_info_provider,
+        );
+        outgoing_receipts.extend(receipt_sink.into_outgoing_receipts().into_iter());
+        apply_result
+    }
+
+    /// Forwards `receipts` through a receipt sink without outgoing limits,
+    /// either one by one or as a single batch, and returns the outgoing
+    /// receipts.
+    pub fn forward_receipts(
+        state_update: &mut TrieUpdate,
+        apply_state: &ApplyState,
+        receipts: Vec<Receipt>,
+        epoch_info_provider: &dyn EpochInfoProvider,
+        batched: bool,
+    ) -> Result<Vec<Receipt>, RuntimeError> {
+        let mut receipt_sink = limitless_receipt_sink(state_update, apply_state)?;
+        if batched {
+            receipt_sink.forward_receipts_batch(
+                receipts,
+                apply_state,
+                state_update,
+                epoch_info_provider,
+            )?;
+        } else {
+            for receipt in receipts {
+                receipt_sink.forward_or_buffer_receipt(
+                    receipt,
+                    apply_state,
+                    state_update,
+                    epoch_info_provider,
+                )?;
+            }
+        }
+        Ok(ReceiptSink::V2(receipt_sink).into_outgoing_receipts())
+    }
+
+    fn limitless_receipt_sink(
+        state_update: &TrieUpdate,
+        apply_state: &ApplyState,
+    ) -> Result<ReceiptSinkV2, RuntimeError> {
         // TODO(congestion_control - edit runtime config parameters for limitless estimator runs
         let congestion_info = CongestionInfo::default();
         // no limits set for any shards => limitless
@@ -2674,32 +2792,16 @@ pub mod estimator {
             apply_state.current_protocol_version,
         )?;
 
-        let mut receipt_sink = ReceiptSink::V2(ReceiptSinkV2 {
+        Ok(ReceiptSinkV2 {
             own_congestion_info: congestion_info,
             outgoing_limit,
             outgoing_buffers: ShardsOutgoingReceiptBuffer::load(&state_update.trie)?,
             outgoing_receipts: Vec::new(),
+            outgoing_receipts_shards: HashSet::new(),
             outgoing_metadatas,
             bandwidth_scheduler_output: None,
             protocol_version: apply_state.current_protocol_version,
-        });
-        let empty_pipeline = ReceiptPreparationPipeline::new(
-            std::sync::Arc::clone(&apply_state.config),
-            apply_state.cache.as_ref().map(|c| c.handle()),
-            apply_state.current_protocol_version,
-            state_update.contract_storage(),
-        );
-        let apply_result = Runtime {}.apply_action_receipt(
-            state_update,
-            apply_state,
-            &empty_pipeline,
-            receipt,
-            &mut receipt_sink,
-            validator_proposals,
-            stats,
-            epoch_info_provider,
-        );
-        outgoing_receipts.extend(receipt_sink.into_outgoing_receipts().into_iter());
-        apply_result
+            forwarding_stats: Default::default(),
+        })
     }
 }
@@ -1,13 +1,20 @@
 use super::{to_yocto, GAS_PRICE};
 use crate::config::safe_add_gas;
-use crate::congestion_control::{compute_receipt_congestion_gas, compute_receipt_size};
+use crate::congestion_control::{
+    bootstrap_congestion_info, bootstrap_congestion_info_for_all_shards,
+    bootstrap_congestion_info_incremental, check_outgoing_metadatas,
+    compute_receipt_congestion_gas, compute_receipt_size, estimated_gas_in_next_n,
+    BackpressureSnapshot, DelayedReceiptQueueWrapper, GasOrSize, OutgoingLimit,
+    OutgoingMetadataMismatch, ReceiptSink, ReceiptSinkV2,
+};
 use crate::tests::{
     create_receipt_for_create_account, create_receipt_with_actions, set_sha256_cost,
-    MAX_ATTACHED_GAS,
+    CongestionInfoFromApplyResult, MAX_ATTACHED_GAS,
 };
 use crate::total_prepaid_exec_fees;
-use crate::{ApplyResult, ApplyState, Runtime, ValidatorAccountsUpdate};
+use crate::{ApplyResult, ApplyState, ReceiptForwardingStats, Runtime, ValidatorAccountsUpdate};
 use assert_matches::assert_matches;
+use bytesize::ByteSize;
 use near_crypto::{InMemorySigner, KeyType, PublicKey, Signer};
 use near_parameters::{ActionCosts, RuntimeConfig};
 use near_primitives::account::AccessKey;
@@ -21,9 +28,12 @@ use near_primitives::congestion_info::{
 };
 use near_primitives::errors::{ActionErrorKind, FunctionCallError, TxExecutionError};
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum, ReceiptPriority, ReceiptV0};
+use near_primitives::receipt::{
+    ActionReceipt, Receipt, ReceiptEnum, ReceiptOrStateStoredReceipt, ReceiptPriority, ReceiptV0,
+    StateStoredReceipt, StateStoredReceiptMetadata,
+};
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
-use near_primitives::shard_layout::ShardUId;
+use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::stateless_validation::contract_distribution::CodeHash;
 use near_primitives::test_utils::{account_new, MockEpochInfoProvider};
 use near_primitives::transaction::{
@@ -36,13 +46,20 @@ use near_primitives::types::{
 };
 use near_primitives::utils::create_receipt_id_from_transaction;
 use near_primitives::version::{ProtocolFeature, PROTOCOL_VERSION};
+use near_store::adapter::StoreUpdateAdapter;
+use near_store::flat::FlatStateChanges;
 use near_store::test_utils::TestTriesBuilder;
-use near_store::trie::receipts_column_helper::ShardsOutgoingReceiptBuffer;
+use near_store::trie::outgoing_metadata::{OutgoingMetadatas, ReceiptGroupsConfig};
+use near_store::trie::receipts_column_helper::{
+    DelayedReceiptQueue, ShardsOutgoingReceiptBuffer, TrieQueue,
+};
+use near_store::trie::update::TrieUpdateResult;
 use near_store::{
     get_account, set_access_key, set_account, MissingTrieValueContext, ShardTries, StorageError,
     Trie,
 };
 use near_vm_runner::{ContractCode, FilesystemContractRuntimeCache};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use testlib::runtime_utils::{alice_account, bob_account};
@@ -2561,6 +2578,641 @@ fn test_congestion_buffering() {
     }
 }
 
+/// Test that the backpressure snapshot of the receipt sink picks up the
+/// receipts buffered while applying a chunk with a congested receiver.
+#[test]
+fn test_backpressure_snapshot() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    // All accounts are on shard 0 with the MockEpochInfoProvider, use a
+    // different local shard to avoid local forwarding.
+    let local_shard = ShardId::new(1);
+    let local_shard_uid = ShardUId::new(0, local_shard);
+    let receiver_shard = ShardId::new(0);
+
+    let initial_balance = to_yocto(1_000_000);
+    let initial_locked = to_yocto(500_000);
+    let deposit = to_yocto(10_000);
+    // execute a single receipt per chunk
+    let gas_limit = 1;
+    let (runtime, tries, root, mut apply_state, _, epoch_info_provider) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        initial_balance,
+        initial_locked,
+        gas_limit,
+        local_shard_uid,
+    );
+    apply_state.shard_id = local_shard;
+
+    // Fully congest shard 0 and don't allow shard 1 to forward anything.
+    let max_congestion_incoming_gas: Gas =
+        apply_state.config.congestion_control_config.max_congestion_incoming_gas;
+    let receiver_congestion_info =
+        &mut apply_state.congestion_info.get_mut(&receiver_shard).unwrap().congestion_info;
+    receiver_congestion_info.add_delayed_receipt_gas(max_congestion_incoming_gas).unwrap();
+    receiver_congestion_info.set_allowed_shard(0);
+    apply_state.congestion_info.insert(local_shard, Default::default());
+
+    let snapshot_before = backpressure_snapshot(&tries, local_shard_uid, root, &apply_state);
+    assert_eq!(0, snapshot_before.outgoing_limit[&receiver_shard].gas);
+    assert!(snapshot_before.buffer_lens.is_empty());
+
+    let receipts = generate_delegate_actions(deposit, 3);
+    let apply_result = runtime
+        .apply(
+            tries.get_trie_for_shard(local_shard_uid, root),
+            &None,
+            &apply_state,
+            &receipts,
+            &[],
+            &epoch_info_provider,
+            Default::default(),
+        )
+        .unwrap();
+    let root = commit_apply_result(&apply_result, &mut apply_state, &tries);
+
+    let snapshot_after = backpressure_snapshot(&tries, local_shard_uid, root, &apply_state);
+    assert_ne!(snapshot_before, snapshot_after);
+    assert_eq!(apply_result.congestion_info.unwrap(), snapshot_after.own_congestion_info);
+    assert!(snapshot_after.own_congestion_info.buffered_receipts_gas() > 0);
+    assert_eq!(0, snapshot_after.outgoing_limit[&receiver_shard].gas);
+    assert_eq!(Some(&1), snapshot_after.buffer_lens.get(&receiver_shard));
+    assert!(snapshot_after.to_string().contains(&format!("buffer[{}]=1", receiver_shard)));
+}
+
+/// Test that the receipt sink reports the receipts buffered for a congested
+/// receiver shard as pending until they are forwarded.
+#[test]
+fn test_receipt_sink_pending_receipts() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    // All accounts are on shard 0 with the MockEpochInfoProvider, use a
+    // different local shard to avoid local forwarding.
+    let local_shard = ShardId::new(1);
+    let local_shard_uid = ShardUId::new(0, local_shard);
+    let receiver_shard = ShardId::new(0);
+
+    let initial_balance = to_yocto(1_000_000);
+    let initial_locked = to_yocto(500_000);
+    let deposit = to_yocto(10_000);
+    let gas_limit = 1;
+    let (runtime, tries, root, mut apply_state, _, epoch_info_provider) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        initial_balance,
+        initial_locked,
+        gas_limit,
+        local_shard_uid,
+    );
+    apply_state.shard_id = local_shard;
+
+    // Don't allow shard 1 to forward anything to shard 0.
+    let max_congestion_incoming_gas: Gas =
+        apply_state.config.congestion_control_config.max_congestion_incoming_gas;
+    let receiver_congestion_info =
+        &mut apply_state.congestion_info.get_mut(&receiver_shard).unwrap().congestion_info;
+    receiver_congestion_info.add_delayed_receipt_gas(max_congestion_incoming_gas).unwrap();
+    receiver_congestion_info.set_allowed_shard(0);
+    apply_state.congestion_info.insert(local_shard, Default::default());
+
+    let receipt_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    assert!(receipt_sink.is_fully_drained());
+    assert!(!receipt_sink.has_pending_receipts_for_shard(receiver_shard));
+
+    let receipts = generate_delegate_actions(deposit, 3);
+    let apply_result = runtime
+        .apply(
+            tries.get_trie_for_shard(local_shard_uid, root),
+            &None,
+            &apply_state,
+            &receipts,
+            &[],
+            &epoch_info_provider,
+            Default::default(),
+        )
+        .unwrap();
+    // The delayed receipts queue and the outgoing buffers were empty before
+    // the chunk, so the congestion info can be derived from the apply result.
+    let congestion_info = apply_result.congestion_info.unwrap();
+    let expected_congestion_info = CongestionInfo::from_apply_result(&apply_result, local_shard);
+    assert_eq!(
+        congestion_info.delayed_receipts_gas(),
+        expected_congestion_info.delayed_receipts_gas()
+    );
+    assert_eq!(
+        congestion_info.buffered_receipts_gas(),
+        expected_congestion_info.buffered_receipts_gas()
+    );
+    assert_eq!(congestion_info.receipt_bytes(), expected_congestion_info.receipt_bytes());
+    let root = commit_apply_result(&apply_result, &mut apply_state, &tries);
+
+    let receipt_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    assert!(!receipt_sink.is_fully_drained());
+    assert!(receipt_sink.has_pending_receipts_for_shard(receiver_shard));
+    assert!(!receipt_sink.has_pending_receipts_for_shard(local_shard));
+}
+
+/// Test that forwarding from the buffers with an explicit budget ignores the
+/// outgoing limits and leaves them untouched.
+#[test]
+fn test_forward_from_buffer_with_budget() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard = ShardId::new(1);
+    let local_shard_uid = ShardUId::new(0, local_shard);
+    let receiver_shard = ShardId::new(0);
+
+    let (runtime, tries, root, mut apply_state, _, epoch_info_provider) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+    apply_state.shard_id = local_shard;
+
+    // Don't allow shard 1 to forward anything to shard 0, so that the
+    // outgoing receipts end up in the buffer.
+    let max_congestion_incoming_gas: Gas =
+        apply_state.config.congestion_control_config.max_congestion_incoming_gas;
+    let receiver_congestion_info =
+        &mut apply_state.congestion_info.get_mut(&receiver_shard).unwrap().congestion_info;
+    receiver_congestion_info.add_delayed_receipt_gas(max_congestion_incoming_gas).unwrap();
+    receiver_congestion_info.set_allowed_shard(0);
+    apply_state.congestion_info.insert(local_shard, Default::default());
+
+    let receipts = generate_delegate_actions(to_yocto(10_000), 3);
+    let apply_result = runtime
+        .apply(
+            tries.get_trie_for_shard(local_shard_uid, root),
+            &None,
+            &apply_state,
+            &receipts,
+            &[],
+            &epoch_info_provider,
+            Default::default(),
+        )
+        .unwrap();
+    let root = commit_apply_result(&apply_result, &mut apply_state, &tries);
+
+    let mut receipt_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    let mut state_update = tries.new_trie_update(local_shard_uid, root);
+    let num_buffered = receipt_sink.outgoing_buffers.buffer_len(receiver_shard).unwrap();
+    assert!(num_buffered > 0);
+    let outgoing_limit = receipt_sink.outgoing_limit.clone();
+    let buffered_receipts =
+        receipt_sink.inspect_outgoing_buffer(receiver_shard, &state_update).unwrap();
+    assert_eq!(buffered_receipts.len() as u64, num_buffered);
+
+    // Nothing fits into an empty budget.
+    let num_forwarded = receipt_sink
+        .forward_from_buffer_with_budget(&mut state_update, &apply_state, 0, 0)
+        .unwrap();
+    assert_eq!(num_forwarded, 0);
+    assert!(receipt_sink.outgoing_receipts.is_empty());
+
+    // Everything fits into an unlimited budget, even though the receiver
+    // shard doesn't allow any gas.
+    let num_forwarded = receipt_sink
+        .forward_from_buffer_with_budget(&mut state_update, &apply_state, Gas::MAX, u64::MAX)
+        .unwrap();
+    assert_eq!(num_forwarded, num_buffered);
+    assert_eq!(receipt_sink.outgoing_receipts.len() as u64, num_buffered);
+    assert_eq!(receipt_sink.outgoing_buffers.buffer_len(receiver_shard), Some(0));
+    assert_eq!(receipt_sink.outgoing_receipts, buffered_receipts);
+    assert!(receipt_sink
+        .inspect_outgoing_buffer(receiver_shard, &state_update)
+        .unwrap()
+        .is_empty());
+    assert_eq!(receipt_sink.outgoing_limit, outgoing_limit);
+}
+
+/// Test that the forwarding stats count the receipts buffered while applying
+/// a chunk and the ones forwarded when the buffer is flushed later.
+#[test]
+fn test_receipt_forwarding_stats() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard = ShardId::new(1);
+    let local_shard_uid = ShardUId::new(0, local_shard);
+    let receiver_shard = ShardId::new(0);
+
+    let (runtime, tries, root, mut apply_state, _, epoch_info_provider) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+    apply_state.shard_id = local_shard;
+
+    // Don't allow shard 1 to forward anything to shard 0, so that the
+    // outgoing receipts end up in the buffer.
+    let max_congestion_incoming_gas: Gas =
+        apply_state.config.congestion_control_config.max_congestion_incoming_gas;
+    let receiver_congestion_info =
+        &mut apply_state.congestion_info.get_mut(&receiver_shard).unwrap().congestion_info;
+    receiver_congestion_info.add_delayed_receipt_gas(max_congestion_incoming_gas).unwrap();
+    receiver_congestion_info.set_allowed_shard(0);
+    apply_state.congestion_info.insert(local_shard, Default::default());
+
+    let receipts = generate_delegate_actions(to_yocto(10_000), 3);
+    let apply_result = runtime
+        .apply(
+            tries.get_trie_for_shard(local_shard_uid, root),
+            &None,
+            &apply_state,
+            &receipts,
+            &[],
+            &epoch_info_provider,
+            Default::default(),
+        )
+        .unwrap();
+    let stats = apply_result.receipt_forwarding_stats;
+    let root = commit_apply_result(&apply_result, &mut apply_state, &tries);
+
+    let mut receipt_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    let num_buffered = receipt_sink.outgoing_buffers.buffer_len(receiver_shard).unwrap();
+    assert!(num_buffered > 0);
+    assert_eq!(stats.receipts_buffered_count, num_buffered);
+    assert!(stats.total_bytes_buffered > 0);
+    assert!(stats.total_gas_buffered > 0);
+    assert!(stats.max_single_receipt_gas <= stats.total_gas_buffered);
+    assert_eq!(stats.num_buffer_flushes, 0);
+
+    // Flushing the whole buffer counts every receipt as forwarded.
+    let mut state_update = tries.new_trie_update(local_shard_uid, root);
+    assert_eq!(receipt_sink.forwarding_stats, ReceiptForwardingStats::default());
+    receipt_sink
+        .forward_from_buffer_with_budget(&mut state_update, &apply_state, Gas::MAX, u64::MAX)
+        .unwrap();
+    let stats = ReceiptSink::V2(receipt_sink).generate_apply_stats();
+    assert_eq!(stats.receipts_forwarded_count, num_buffered);
+    assert_eq!(stats.receipts_buffered_count, 0);
+    assert_eq!(stats.num_buffer_flushes, 1);
+}
+
+/// Test that forwarding receipts as a batch has the same outcome as
+/// forwarding them one by one.
+#[test]
+fn test_forward_receipts_batch() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard = ShardId::new(1);
+    let local_shard_uid = ShardUId::new(0, local_shard);
+    let receiver_shard = ShardId::new(0);
+
+    let (_, tries, root, mut apply_state, _, epoch_info_provider) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+    apply_state.shard_id = local_shard;
+    apply_state.congestion_info.insert(local_shard, Default::default());
+
+    // Only let the first few receipts through, the others get buffered.
+    let receipts = generate_delegate_actions(to_yocto(10_000), 10);
+    let gas_limit: Gas = receipts[..5]
+        .iter()
+        .map(|receipt| compute_receipt_congestion_gas(receipt, &apply_state.config).unwrap())
+        .sum();
+    let outgoing_limit = OutgoingLimit { gas: gas_limit, size: u64::MAX, max_receipts: u64::MAX };
+
+    let mut sequential_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    sequential_sink.outgoing_limit.insert(receiver_shard, outgoing_limit);
+    let mut sequential_update = tries.new_trie_update(local_shard_uid, root);
+    for receipt in receipts.clone() {
+        sequential_sink
+            .forward_or_buffer_receipt(
+                receipt,
+                &apply_state,
+                &mut sequential_update,
+                &epoch_info_provider,
+            )
+            .unwrap();
+    }
+
+    let mut batch_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    batch_sink.outgoing_limit.insert(receiver_shard, outgoing_limit);
+    let mut batch_update = tries.new_trie_update(local_shard_uid, root);
+    let buffered = batch_sink
+        .forward_receipts_batch(receipts, &apply_state, &mut batch_update, &epoch_info_provider)
+        .unwrap();
+
+    assert!(!buffered.is_empty());
+    assert!(!batch_sink.outgoing_receipts.is_empty());
+    assert_eq!(batch_sink.outgoing_receipts, sequential_sink.outgoing_receipts);
+    assert_eq!(
+        buffered,
+        sequential_sink.inspect_outgoing_buffer(receiver_shard, &sequential_update).unwrap()
+    );
+    assert_eq!(
+        buffered,
+        batch_sink.inspect_outgoing_buffer(receiver_shard, &batch_update).unwrap()
+    );
+    assert_eq!(batch_sink.own_congestion_info, sequential_sink.own_congestion_info);
+    assert_eq!(batch_sink.forwarding_stats, sequential_sink.forwarding_stats);
+}
+
+/// Test that a receipt is only forwarded if both the gas and the size left in
+/// the outgoing limit are strictly greater than its own.
+#[test]
+fn test_outgoing_limit_boundaries() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard_uid = ShardUId::new(0, ShardId::new(1));
+    let receiver_shard = ShardId::new(0);
+    let (_, tries, root, apply_state, _, epoch_info_provider) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+
+    let receipts = generate_delegate_actions(to_yocto(10_000), 2);
+    let gas = compute_receipt_congestion_gas(&receipts[0], &apply_state.config).unwrap();
+    let size = compute_receipt_size(&receipts[0]).unwrap();
+    assert_eq!(gas, compute_receipt_congestion_gas(&receipts[1], &apply_state.config).unwrap());
+    assert_eq!(size, compute_receipt_size(&receipts[1]).unwrap());
+
+    // The limit leaves exactly the gas, then exactly the size of the second
+    // receipt once the first one is forwarded.
+    for outgoing_limit in [
+        OutgoingLimit { gas: 2 * gas, size: u64::MAX, max_receipts: u64::MAX },
+        OutgoingLimit { gas: Gas::MAX, size: 2 * size, max_receipts: u64::MAX },
+    ] {
+        let mut receipt_sink = ReceiptSinkV2::for_testing(
+            HashMap::from([(receiver_shard, outgoing_limit)]),
+            CongestionInfo::default(),
+            PROTOCOL_VERSION,
+        );
+        let mut state_update = tries.new_trie_update(local_shard_uid, root);
+        for receipt in receipts.clone() {
+            receipt_sink
+                .forward_or_buffer_receipt(
+                    receipt,
+                    &apply_state,
+                    &mut state_update,
+                    &epoch_info_provider,
+                )
+                .unwrap();
+        }
+        assert_eq!(receipt_sink.outgoing_receipts, receipts[..1]);
+        assert_buffer_len(&receipt_sink, receiver_shard, 1);
+        let limit_left = receipt_sink.outgoing_limit[&receiver_shard];
+        assert!(limit_left.gas == gas || limit_left.size == size, "{limit_left:?}");
+        assert_eq!(receipt_sink.own_congestion_info.buffered_receipts_gas(), gas as u128);
+    }
+}
+
+/// Test that forwarding to a shard stops once `max_outgoing_receipts`
+/// receipts were sent, even if there is gas and size left in the limit.
+#[test]
+fn test_outgoing_receipts_count_limit() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard = ShardId::new(1);
+    let local_shard_uid = ShardUId::new(0, local_shard);
+    let receiver_shard = ShardId::new(0);
+    let (_, tries, root, mut apply_state, _, epoch_info_provider) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+    apply_state.shard_id = local_shard;
+    apply_state.congestion_info.insert(local_shard, Default::default());
+    let max_receipts = 5;
+    let congestion_control_config =
+        &mut Arc::make_mut(&mut apply_state.config).congestion_control_config;
+    congestion_control_config.max_outgoing_receipts = max_receipts;
+    congestion_control_config.outgoing_receipts_usual_size_limit = u64::MAX;
+    congestion_control_config.outgoing_receipts_big_size_limit = u64::MAX;
+
+    let mut receipt_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    let limit = receipt_sink.outgoing_limit[&receiver_shard];
+    assert_eq!(limit.max_receipts, max_receipts);
+    assert_eq!(receipt_sink.outgoing_limit[&local_shard].max_receipts, u64::MAX);
+
+    let receipts = generate_delegate_actions(to_yocto(10_000), 2 * max_receipts);
+    let mut state_update = tries.new_trie_update(local_shard_uid, root);
+    for receipt in receipts.clone() {
+        receipt_sink
+            .forward_or_buffer_receipt(
+                receipt,
+                &apply_state,
+                &mut state_update,
+                &epoch_info_provider,
+            )
+            .unwrap();
+    }
+
+    assert_eq!(receipt_sink.outgoing_receipts, receipts[..max_receipts as usize]);
+    assert_buffer_len(&receipt_sink, receiver_shard, max_receipts);
+    let limit_left = receipt_sink.outgoing_limit[&receiver_shard];
+    assert_eq!(limit_left.max_receipts, 0);
+    assert!(limit_left.gas > 0 && limit_left.size > 0, "{limit_left:?}");
+    let description = receipt_sink.describe_limit_hit(&receipts[0], receiver_shard, 0, 0);
+    assert_eq!(description.binding_constraint, GasOrSize::ReceiptCount);
+}
+
+/// Test that the outgoing buffers are drained in shard ID order, no matter the
+/// iteration order of the outgoing limits.
+#[test]
+fn test_forward_from_buffer_shard_order() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard_uid = ShardUId::new(0, ShardId::new(1));
+    let (_, tries, root, apply_state, _, _) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+    let mut state_update = tries.new_trie_update(local_shard_uid, root);
+
+    // One receipt buffered to each of many shards, pushed in reverse order.
+    let num_shards = 16;
+    let receipts = generate_delegate_actions(to_yocto(10_000), num_shards);
+    let mut outgoing_buffers = ShardsOutgoingReceiptBuffer::load(&state_update).unwrap();
+    let mut congestion_info = CongestionInfo::default();
+    for (shard_index, receipt) in receipts.iter().enumerate().rev() {
+        let shard_id = ShardId::new(shard_index as u64);
+        let stored_receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Borrowed(receipt));
+        outgoing_buffers.to_shard(shard_id).push_back(&mut state_update, &stored_receipt).unwrap();
+        congestion_info.add_receipt_bytes(compute_receipt_size(receipt).unwrap()).unwrap();
+        congestion_info
+            .add_buffered_receipt_gas(
+                compute_receipt_congestion_gas(receipt, &apply_state.config).unwrap(),
+            )
+            .unwrap();
+    }
+
+    let unlimited = OutgoingLimit { gas: Gas::MAX, size: u64::MAX, max_receipts: u64::MAX };
+    let outgoing_limits =
+        (0..num_shards).map(|shard_index| (ShardId::new(shard_index), unlimited)).collect();
+    let mut receipt_sink =
+        ReceiptSinkV2::for_testing(outgoing_limits, congestion_info, PROTOCOL_VERSION);
+    receipt_sink.outgoing_buffers = outgoing_buffers;
+    receipt_sink.forward_from_buffer(&mut state_update, &apply_state).unwrap();
+
+    assert_eq!(receipt_sink.outgoing_receipts, receipts);
+    assert_eq!(receipt_sink.own_congestion_info, CongestionInfo::default());
+}
+
+/// Test that draining the outgoing buffers returns every buffered receipt,
+/// regardless of the outgoing limits, and empties the buffers.
+#[test]
+fn test_drain_all_buffers_unconditionally() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard_uid = ShardUId::new(0, ShardId::new(1));
+    let (_, tries, root, apply_state, _, _) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+    let mut state_update = tries.new_trie_update(local_shard_uid, root);
+
+    // Two receipts buffered to each of two shards, higher shard ID first.
+    let receipts = generate_delegate_actions(to_yocto(10_000), 4);
+    let shards = [ShardId::new(2), ShardId::new(0)];
+    let mut outgoing_buffers = ShardsOutgoingReceiptBuffer::load(&state_update).unwrap();
+    let mut congestion_info = CongestionInfo::default();
+    for (shard_id, shard_receipts) in shards.iter().zip(receipts.chunks(2)) {
+        for receipt in shard_receipts {
+            let stored_receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Borrowed(receipt));
+            outgoing_buffers
+                .to_shard(*shard_id)
+                .push_back(&mut state_update, &stored_receipt)
+                .unwrap();
+            congestion_info.add_receipt_bytes(compute_receipt_size(receipt).unwrap()).unwrap();
+            congestion_info
+                .add_buffered_receipt_gas(
+                    compute_receipt_congestion_gas(receipt, &apply_state.config).unwrap(),
+                )
+                .unwrap();
+        }
+    }
+
+    // Nothing could be forwarded with these limits.
+    let blocked = OutgoingLimit { gas: 0, size: 0, max_receipts: 0 };
+    let outgoing_limits = shards.iter().map(|shard_id| (*shard_id, blocked)).collect();
+    let mut receipt_sink =
+        ReceiptSinkV2::for_testing(outgoing_limits, congestion_info, PROTOCOL_VERSION);
+    receipt_sink.outgoing_buffers = outgoing_buffers;
+    let drained = receipt_sink
+        .drain_all_buffers_unconditionally(&mut state_update, &apply_state.config)
+        .unwrap();
+
+    let expected = receipts[2..].iter().chain(&receipts[..2]).cloned().collect::<Vec<_>>();
+    assert_eq!(drained, expected);
+    assert!(receipt_sink.outgoing_receipts.is_empty());
+    assert_eq!(receipt_sink.own_congestion_info, CongestionInfo::default());
+    let outgoing_buffers = ShardsOutgoingReceiptBuffer::load(&state_update).unwrap();
+    for shard_id in shards {
+        assert_eq!(outgoing_buffers.buffer_len(shard_id), Some(0));
+    }
+}
+
+/// Test that the description of a buffered receipt reports the gas limit of
+/// a receiver shard which doesn't allow any incoming gas.
+#[test]
+fn test_describe_limit_hit() {
+    if !ProtocolFeature::CongestionControl.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let local_shard = ShardId::new(1);
+    let local_shard_uid = ShardUId::new(0, local_shard);
+    let receiver_shard = ShardId::new(0);
+
+    let (_, tries, root, mut apply_state, _, _) = setup_runtime_for_shard(
+        vec![alice_account(), bob_account()],
+        to_yocto(1_000_000),
+        to_yocto(500_000),
+        1,
+        local_shard_uid,
+    );
+    apply_state.shard_id = local_shard;
+
+    // Fully congest shard 0 and don't allow shard 1 to forward anything.
+    let max_congestion_incoming_gas: Gas =
+        apply_state.config.congestion_control_config.max_congestion_incoming_gas;
+    let receiver_congestion_info =
+        &mut apply_state.congestion_info.get_mut(&receiver_shard).unwrap().congestion_info;
+    receiver_congestion_info.add_delayed_receipt_gas(max_congestion_incoming_gas).unwrap();
+    receiver_congestion_info.set_allowed_shard(0);
+    apply_state.congestion_info.insert(local_shard, Default::default());
+
+    let receipt_sink = receipt_sink_v2(&tries, local_shard_uid, root, &apply_state);
+    let receipt = generate_delegate_actions(to_yocto(10_000), 1).pop().unwrap();
+    let description = receipt_sink.describe_limit_hit(&receipt, receiver_shard, 1, 100);
+    assert_eq!(description.receipt_id, *receipt.receipt_id());
+    assert_eq!(description.shard_id, receiver_shard);
+    assert_eq!(description.available_gas, 0);
+    assert_eq!(description.required_gas, 1);
+    assert_eq!(description.required_size, 100);
+    assert_eq!(description.binding_constraint, GasOrSize::Gas);
+    assert!(description.to_string().contains("Gas limit"));
+}
+
+/// Asserts the length of the outgoing buffer to `shard_id`, logging the state
+/// of all buffers first if it doesn't match.
+fn assert_buffer_len(receipt_sink: &ReceiptSinkV2, shard_id: ShardId, expected: u64) {
+    let buffer_len = receipt_sink.outgoing_buffers.buffer_len(shard_id);
+    if buffer_len != Some(expected) {
+        receipt_sink.debug_print_buffer_sizes();
+    }
+    assert_eq!(buffer_len, Some(expected), "unexpected length of the buffer to {shard_id}");
+}
+
+fn backpressure_snapshot(
+    tries: &ShardTries,
+    shard_uid: ShardUId,
+    root: CryptoHash,
+    apply_state: &ApplyState,
+) -> BackpressureSnapshot {
+    receipt_sink_v2(tries, shard_uid, root, apply_state).backpressure_snapshot()
+}
+
+fn receipt_sink_v2(
+    tries: &ShardTries,
+    shard_uid: ShardUId,
+    root: CryptoHash,
+    apply_state: &ApplyState,
+) -> ReceiptSinkV2 {
+    let trie = tries.get_trie_for_shard(shard_uid, root);
+    let own_congestion_info =
+        apply_state.congestion_info.get(&apply_state.shard_id).unwrap().congestion_info;
+    let receipt_sink = ReceiptSink::new(
+        apply_state.current_protocol_version,
+        &trie,
+        apply_state,
+        Some(own_congestion_info),
+        None,
+    )
+    .unwrap();
+    let ReceiptSink::V2(receipt_sink) = receipt_sink else {
+        panic!("congestion control is enabled, expected ReceiptSink::V2");
+    };
+    receipt_sink
+}
+
 // Apply trie changes in `ApplyResult` and update `ApplyState` with new
 // congestion info for the next call to apply().
 fn commit_apply_result(
@@ -2640,6 +3292,240 @@ fn test_congestion_info_bootstrapping() {
     check_congestion_info_bootstrapping(is_new_chunk, None);
 }
 
+/// Test that the incremental bootstrapping, which only scans the receipt
+/// columns of flat storage, computes the same congestion info as the regular
+/// one.
+#[test]
+fn test_congestion_info_bootstrapping_incremental() {
+    let config = RuntimeConfig::test();
+    let shard_uid = ShardUId::single_shard();
+    let tries = TestTriesBuilder::new().with_flat_storage(true).build();
+    let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+
+    let receipts = generate_receipts(to_yocto(1), 20);
+    let mut delayed_receipts = DelayedReceiptQueue::load(&state_update).unwrap();
+    let mut outgoing_buffers = ShardsOutgoingReceiptBuffer::load(&state_update).unwrap();
+    for (i, receipt) in receipts.iter().enumerate() {
+        let receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Borrowed(receipt));
+        match i % 3 {
+            0 => delayed_receipts.push_back(&mut state_update, &receipt).unwrap(),
+            shard => outgoing_buffers
+                .to_shard(ShardId::new(shard as u64))
+                .push_back(&mut state_update, &receipt)
+                .unwrap(),
+        }
+    }
+    // Pop some receipts to make sure removed ones are not picked up.
+    delayed_receipts.pop_front(&mut state_update).unwrap();
+    outgoing_buffers.to_shard(ShardId::new(1)).pop_front(&mut state_update).unwrap();
+    state_update.commit(StateChangeCause::InitialState);
+    let TrieUpdateResult { trie_changes, state_changes, .. } = state_update.finalize().unwrap();
+    let mut store_update = tries.store_update();
+    let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+    // The flat storage head is at the default block hash.
+    FlatStateChanges::from_state_changes(&state_changes)
+        .apply_to_flat_state(&mut store_update.flat_store_update(), shard_uid);
+    store_update.commit().unwrap();
+
+    let trie =
+        tries.get_trie_with_block_hash_for_shard(shard_uid, root, &CryptoHash::default(), false);
+    assert!(trie.has_flat_storage_chunk_view());
+    let shard_id = shard_uid.shard_id();
+    let expected = bootstrap_congestion_info(&trie, &config, shard_id).unwrap();
+    let incremental = bootstrap_congestion_info_incremental(&trie, &config, shard_id).unwrap();
+    assert_ne!(0, expected.delayed_receipts_gas());
+    assert_ne!(0, expected.buffered_receipts_gas());
+    assert_eq!(expected, incremental);
+}
+
+/// Test that bootstrapping all shards in parallel computes the same
+/// congestion info for each shard as bootstrapping them one by one.
+#[test]
+fn test_congestion_info_bootstrapping_for_all_shards() {
+    let config = RuntimeConfig::test();
+    let shard_layout = ShardLayout::multi_shard(3, 0);
+    let tries = TestTriesBuilder::new().with_shard_layout(shard_layout.clone()).build();
+
+    let mut shards = vec![];
+    for (shard_index, shard_uid) in shard_layout.shard_uids().enumerate() {
+        let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+        // Give every shard a different number of delayed receipts.
+        let receipts = generate_receipts(to_yocto(1), 5 * (shard_index as u64 + 1));
+        let mut delayed_receipts = DelayedReceiptQueue::load(&state_update).unwrap();
+        for receipt in &receipts {
+            let receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Borrowed(receipt));
+            delayed_receipts.push_back(&mut state_update, &receipt).unwrap();
+        }
+        state_update.commit(StateChangeCause::InitialState);
+        let trie_changes = state_update.finalize().unwrap().trie_changes;
+        let mut store_update = tries.store_update();
+        let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+        store_update.commit().unwrap();
+        shards.push((shard_uid, root));
+    }
+
+    let all_shards = bootstrap_congestion_info_for_all_shards(&tries, &config, &shards).unwrap();
+    assert_eq!(all_shards.len(), shards.len());
+    for (shard_uid, root) in shards {
+        let trie = tries.get_trie_for_shard(shard_uid, root);
+        let expected = bootstrap_congestion_info(&trie, &config, shard_uid.shard_id()).unwrap();
+        assert_ne!(0, expected.delayed_receipts_gas());
+        assert_eq!(all_shards[&shard_uid], expected);
+    }
+}
+
+/// Test that bootstrapping all shards in parallel returns the error of the
+/// first failing shard in the given order, no matter which task fails first.
+#[test]
+fn test_congestion_info_bootstrapping_for_all_shards_error() {
+    let config = RuntimeConfig::test();
+    let shard_layout = ShardLayout::multi_shard(8, 0);
+    let tries = TestTriesBuilder::new().with_shard_layout(shard_layout.clone()).build();
+
+    // The first shard has an empty state, all the others have a state root
+    // which is missing from the store.
+    let shards: Vec<_> = shard_layout
+        .shard_uids()
+        .enumerate()
+        .map(|(shard_index, shard_uid)| {
+            let root =
+                if shard_index == 0 { Trie::EMPTY_ROOT } else { hash(&shard_index.to_le_bytes()) };
+            (shard_uid, root)
+        })
+        .collect();
+    let expected_missing_root = shards[1].1;
+    for _ in 0..10 {
+        let err = bootstrap_congestion_info_for_all_shards(&tries, &config, &shards).unwrap_err();
+        assert_matches!(
+            err,
+            StorageError::MissingTrieValue(_, missing_hash) if missing_hash == expected_missing_root
+        );
+    }
+}
+
+/// Test that checking the outgoing metadata only reports the shards whose
+/// metadata tracks more receipts than their outgoing buffer holds, together
+/// with the metadata recomputed from the buffer.
+#[test]
+fn test_check_outgoing_metadatas() {
+    if !ProtocolFeature::BandwidthScheduler.enabled(PROTOCOL_VERSION) {
+        return;
+    }
+    let config = RuntimeConfig::test();
+    let shard_uid = ShardUId::single_shard();
+    let tries = TestTriesBuilder::new().build();
+    let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+
+    // Shard 0 has two buffered receipts, but its metadata tracks three of
+    // them. The metadata of shard 1 matches its buffer.
+    let inconsistent_shard = ShardId::new(0);
+    let consistent_shard = ShardId::new(1);
+    let mut outgoing_buffers = ShardsOutgoingReceiptBuffer::load(&state_update).unwrap();
+    let mut metadatas = OutgoingMetadatas::new(ReceiptGroupsConfig::default_config());
+    let receipts = generate_receipts(to_yocto(1), 3);
+    for (i, receipt) in receipts.into_iter().enumerate() {
+        let shard_id = if i < 2 { inconsistent_shard } else { consistent_shard };
+        let metadata = StateStoredReceiptMetadata { congestion_gas: 1, congestion_size: 100 };
+        let receipt = ReceiptOrStateStoredReceipt::StateStoredReceipt(
+            StateStoredReceipt::new_owned(receipt, metadata, PROTOCOL_VERSION),
+        );
+        outgoing_buffers.to_shard(shard_id).push_back(&mut state_update, &receipt).unwrap();
+    }
+    for shard_id in [inconsistent_shard, inconsistent_shard, inconsistent_shard, consistent_shard] {
+        metadatas
+            .update_on_receipt_pushed(shard_id, ByteSize::b(100), 1, &mut state_update)
+            .unwrap();
+    }
+    state_update.commit(StateChangeCause::InitialState);
+    let trie_changes = state_update.finalize().unwrap().trie_changes;
+    let mut store_update = tries.store_update();
+    let root = tries.apply_all(&trie_changes, shard_uid, &mut store_update);
+    store_update.commit().unwrap();
+
+    let trie = tries.get_view_trie_for_shard(shard_uid, root);
+    let mut scratch_update = tries.new_trie_update_view(shard_uid, root);
+    let mismatches =
+        check_outgoing_metadatas(&trie, &mut scratch_update, &config, PROTOCOL_VERSION).unwrap();
+    assert_eq!(
+        mismatches,
+        vec![OutgoingMetadataMismatch {
+            shard_id: inconsistent_shard,
+            buffered_receipts: 2,
+            stored_receipts: 3,
+            recomputed_receipts: 2,
+        }]
+    );
+}
+
+#[test]
+fn test_estimated_gas_in_next_n() {
+    let config = RuntimeConfig::test();
+    let shard_uid = ShardUId::single_shard();
+    let tries = TestTriesBuilder::new().build();
+    let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+
+    let receipts = generate_receipts(to_yocto(1), 10);
+    let mut delayed_receipts = DelayedReceiptQueue::load(&state_update).unwrap();
+    for receipt in &receipts {
+        let receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Borrowed(receipt));
+        delayed_receipts.push_back(&mut state_update, &receipt).unwrap();
+    }
+    let gas_of_first = |n: usize| -> Gas {
+        receipts[..n]
+            .iter()
+            .map(|receipt| compute_receipt_congestion_gas(receipt, &config).unwrap())
+            .sum()
+    };
+
+    assert_eq!(estimated_gas_in_next_n(&delayed_receipts, &state_update, &config, 0).unwrap(), 0);
+    assert_eq!(
+        estimated_gas_in_next_n(&delayed_receipts, &state_update, &config, 3).unwrap(),
+        gas_of_first(3)
+    );
+    // Asking for more receipts than queued sums up the whole queue.
+    assert_eq!(
+        estimated_gas_in_next_n(&delayed_receipts, &state_update, &config, 100).unwrap(),
+        gas_of_first(receipts.len())
+    );
+    // Peeking doesn't remove anything from the queue.
+    assert_eq!(delayed_receipts.len(), receipts.len() as u64);
+}
+
+#[test]
+fn test_delayed_receipts_drain_to_vec() {
+    let config = RuntimeConfig::test();
+    let shard_uid = ShardUId::single_shard();
+    let tries = TestTriesBuilder::new().build();
+    let mut state_update = tries.new_trie_update(shard_uid, Trie::EMPTY_ROOT);
+
+    let receipts = generate_receipts(to_yocto(1), 5);
+    let mut delayed_receipts = DelayedReceiptQueue::load(&state_update).unwrap();
+    for receipt in &receipts {
+        let receipt = ReceiptOrStateStoredReceipt::Receipt(Cow::Borrowed(receipt));
+        delayed_receipts.push_back(&mut state_update, &receipt).unwrap();
+    }
+    let mut delayed_receipts = DelayedReceiptQueueWrapper::new(delayed_receipts);
+
+    let drained = delayed_receipts.drain_to_vec(&mut state_update, &config).unwrap();
+    let expected = receipts
+        .iter()
+        .map(|receipt| (receipt.clone(), compute_receipt_congestion_gas(receipt, &config).unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(drained, expected);
+    assert_eq!(delayed_receipts.len(), 0);
+
+    // The drained receipts are accounted for as removed from the queue.
+    let mut congestion_info = CongestionInfo::default();
+    let total_gas: Gas = expected.iter().map(|(_, gas)| gas).sum();
+    let total_size: u64 =
+        receipts.iter().map(|receipt| compute_receipt_size(receipt).unwrap() as u64).sum();
+    congestion_info.add_delayed_receipt_gas(total_gas).unwrap();
+    congestion_info.add_receipt_bytes(total_size).unwrap();
+    delayed_receipts.apply_congestion_changes(&mut congestion_info).unwrap();
+    assert_eq!(congestion_info.delayed_receipts_gas(), 0);
+    assert_eq!(congestion_info.receipt_bytes(), 0);
+}
+
 #[test]
 fn test_deploy_and_call_local_receipt() {
     let (runtime, tries, root, apply_state, signers, epoch_info_provider) =
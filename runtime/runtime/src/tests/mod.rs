@@ -1,17 +1,24 @@
+use borsh::BorshDeserialize;
 use near_crypto::Signer;
 use near_parameters::{ExtCosts, ParameterCost, RuntimeConfig};
 use near_primitives::account::AccessKey;
 use near_primitives::action::{Action, AddKeyAction, CreateAccountAction, TransferAction};
+use near_primitives::congestion_info::CongestionInfo;
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::receipt::{ActionReceipt, Receipt, ReceiptEnum, ReceiptV0};
+use near_primitives::receipt::{
+    ActionReceipt, Receipt, ReceiptEnum, ReceiptOrStateStoredReceipt, ReceiptV0,
+};
 use near_primitives::test_utils::account_new;
-use near_primitives::types::{AccountId, Balance, Compute, Gas, MerkleHash, StateChangeCause};
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{
+    AccountId, Balance, Compute, Gas, MerkleHash, ShardId, StateChangeCause,
+};
 use near_store::test_utils::TestTriesBuilder;
 use near_store::{get_account, set_account, ShardUId};
 use std::sync::Arc;
 use testlib::runtime_utils::bob_account;
 
-use crate::ApplyState;
+use crate::{ApplyResult, ApplyState};
 
 mod apply;
 
@@ -93,6 +100,50 @@ fn set_sha256_cost(
     sha256_cost
 }
 
+/// Constructs congestion info from the outcome of applying a chunk, without
+/// reading the trie. Only meant for tests, as a cross-check of the congestion
+/// info computed by the runtime.
+trait CongestionInfoFromApplyResult {
+    fn from_apply_result(result: &ApplyResult, shard_id: ShardId) -> Self;
+}
+
+impl CongestionInfoFromApplyResult for CongestionInfo {
+    /// Sums up the receipts that the chunk left in the delayed receipts queue
+    /// and in the outgoing buffers, as recorded in the state changes. Hence
+    /// this only matches the congestion info of the runtime if the queues and
+    /// buffers were empty before the chunk. All receipts must be stored as
+    /// `StateStoredReceipt`, so that their congestion gas and size can be
+    /// read from the metadata.
+    fn from_apply_result(result: &ApplyResult, shard_id: ShardId) -> Self {
+        let mut congestion_info = CongestionInfo::default();
+        for state_change in &result.state_changes {
+            let is_delayed = match state_change.trie_key {
+                TrieKey::DelayedReceipt { .. } => true,
+                TrieKey::BufferedReceipt { .. } => false,
+                _ => continue,
+            };
+            let Some(data) = state_change.changes.last().and_then(|change| change.data.as_ref())
+            else {
+                // The receipt was removed by the end of the chunk.
+                continue;
+            };
+            let receipt = ReceiptOrStateStoredReceipt::try_from_slice(data).unwrap();
+            let ReceiptOrStateStoredReceipt::StateStoredReceipt(receipt) = receipt else {
+                panic!("from_apply_result requires receipts stored as StateStoredReceipt");
+            };
+            let metadata = receipt.metadata();
+            if is_delayed {
+                congestion_info.add_delayed_receipt_gas(metadata.congestion_gas).unwrap();
+            } else {
+                congestion_info.add_buffered_receipt_gas(metadata.congestion_gas).unwrap();
+            }
+            congestion_info.add_receipt_bytes(metadata.congestion_size).unwrap();
+        }
+        congestion_info.set_allowed_shard(shard_id.into());
+        congestion_info
+    }
+}
+
 #[test]
 fn test_get_and_set_accounts() {
     let tries = TestTriesBuilder::new().build();